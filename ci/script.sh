@@ -6,6 +6,7 @@ set -ex
 main() {
     RUSTFLAGS="-C target-feature=$FEATURES" cross build --target $TARGET
     RUSTFLAGS="-C target-feature=$FEATURES" cross build --target $TARGET --release
+    RUSTFLAGS="-C target-feature=$FEATURES" cross build --target $TARGET --no-default-features
 
     if [ ! -z $DISABLE_TESTS ]; then
         return
@@ -13,6 +14,7 @@ main() {
 
     RUSTFLAGS="-C target-feature=$FEATURES" cross test --target $TARGET
     RUSTFLAGS="-C target-feature=$FEATURES" cross test --target $TARGET --release
+    RUSTFLAGS="-C target-feature=$FEATURES" cross test --target $TARGET --no-default-features
 
     # cross run --target $TARGET
     # cross run --target $TARGET --release
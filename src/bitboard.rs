@@ -14,7 +14,8 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
-use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr};
+use core::cmp;
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not, Shl, Shr};
 
 use crate::types::SquareMap;
 
@@ -75,6 +76,11 @@ impl Bitboard {
     pub fn squares(self) -> SquareIterator {
         SquareIterator { bb: self }
     }
+
+    /// Iterates the set squares from h8 down to a1.
+    pub fn squares_rev(self) -> core::iter::Rev<SquareIterator> {
+        self.squares().rev()
+    }
 }
 
 impl From<u64> for Bitboard {
@@ -224,6 +230,49 @@ impl Square {
             self.flip_rank().rank()
         }
     }
+
+    /// Chebyshev distance, i.e. the number of king moves between the two squares.
+    pub fn chebyshev_distance(self, other: Square) -> u8 {
+        CHEBYSHEV_DISTANCE[self.0 as usize][other.0 as usize]
+    }
+
+    /// Manhattan distance, i.e. the number of rook moves of a single square
+    /// between the two squares.
+    pub fn manhattan_distance(self, other: Square) -> u8 {
+        MANHATTAN_DISTANCE[self.0 as usize][other.0 as usize]
+    }
+
+    /// Manhattan distance to the closest of the four center squares.
+    pub fn center_manhattan_distance(self) -> u8 {
+        let file_distance = cmp::max(3, self.file()) - cmp::min(4, self.file());
+        let rank_distance = cmp::max(3, self.rank()) - cmp::min(4, self.rank());
+        file_distance + rank_distance
+    }
+}
+
+static CHEBYSHEV_DISTANCE: [[u8; 64]; 64] = distance_table(false);
+static MANHATTAN_DISTANCE: [[u8; 64]; 64] = distance_table(true);
+
+const fn distance_table(manhattan: bool) -> [[u8; 64]; 64] {
+    let mut table = [[0; 64]; 64];
+    let mut a: u8 = 0;
+    while a < 64 {
+        let mut b: u8 = 0;
+        while b < 64 {
+            let file_distance = (a % 8).abs_diff(b % 8);
+            let rank_distance = (a / 8).abs_diff(b / 8);
+            table[a as usize][b as usize] = if manhattan {
+                file_distance + rank_distance
+            } else if file_distance > rank_distance {
+                file_distance
+            } else {
+                rank_distance
+            };
+            b += 1;
+        }
+        a += 1;
+    }
+    table
 }
 
 impl Into<u8> for Square {
@@ -277,7 +326,19 @@ impl Iterator for SquareIterator {
     }
 }
 
-impl std::iter::FusedIterator for SquareIterator {}
+impl DoubleEndedIterator for SquareIterator {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.bb.0 > 0 {
+            let sq = Square(63 - self.bb.0.leading_zeros() as u8);
+            self.bb.0 ^= 1 << sq.0;
+            return Some(sq);
+        }
+
+        None
+    }
+}
+
+impl core::iter::FusedIterator for SquareIterator {}
 
 impl ExactSizeIterator for SquareIterator {
     fn len(&self) -> usize {
@@ -300,6 +361,8 @@ pub const FILE_H: Bitboard = Bitboard(0x80_80_80_80_80_80_80_80);
 pub const FILES: [Bitboard; 8] = [
     FILE_A, FILE_B, FILE_C, FILE_D, FILE_E, FILE_F, FILE_G, FILE_H,
 ];
+pub const QUEENSIDE: Bitboard = Bitboard(0x0F_0F_0F_0F_0F_0F_0F_0F);
+pub const KINGSIDE: Bitboard = Bitboard(0xF0_F0_F0_F0_F0_F0_F0_F0);
 
 pub const RANK_1: Bitboard = Bitboard(0x00_00_00_00_00_00_00_FF);
 pub const RANK_2: Bitboard = Bitboard(0x00_00_00_00_00_00_FF_00);
@@ -484,6 +547,161 @@ pub const KING_ATTACKS: SquareMap<Bitboard> = SquareMap::from_array([
     Bitboard(0x40_C0_00_00_00_00_00_00),
 ]);
 
+/// Squares attacked by a pawn of the given side standing on a square, indexed
+/// like `PAWN_CORRIDOR` (black = 0, white = 1).
+pub const PAWN_ATTACKS: [SquareMap<Bitboard>; 2] = [
+    // Black
+    SquareMap::from_array([
+        // rank 1
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        // rank 2
+        Bitboard(0x00_00_00_00_00_00_00_02),
+        Bitboard(0x00_00_00_00_00_00_00_05),
+        Bitboard(0x00_00_00_00_00_00_00_0A),
+        Bitboard(0x00_00_00_00_00_00_00_14),
+        Bitboard(0x00_00_00_00_00_00_00_28),
+        Bitboard(0x00_00_00_00_00_00_00_50),
+        Bitboard(0x00_00_00_00_00_00_00_A0),
+        Bitboard(0x00_00_00_00_00_00_00_40),
+        // rank 3
+        Bitboard(0x00_00_00_00_00_00_02_00),
+        Bitboard(0x00_00_00_00_00_00_05_00),
+        Bitboard(0x00_00_00_00_00_00_0A_00),
+        Bitboard(0x00_00_00_00_00_00_14_00),
+        Bitboard(0x00_00_00_00_00_00_28_00),
+        Bitboard(0x00_00_00_00_00_00_50_00),
+        Bitboard(0x00_00_00_00_00_00_A0_00),
+        Bitboard(0x00_00_00_00_00_00_40_00),
+        // rank 4
+        Bitboard(0x00_00_00_00_00_02_00_00),
+        Bitboard(0x00_00_00_00_00_05_00_00),
+        Bitboard(0x00_00_00_00_00_0A_00_00),
+        Bitboard(0x00_00_00_00_00_14_00_00),
+        Bitboard(0x00_00_00_00_00_28_00_00),
+        Bitboard(0x00_00_00_00_00_50_00_00),
+        Bitboard(0x00_00_00_00_00_A0_00_00),
+        Bitboard(0x00_00_00_00_00_40_00_00),
+        // rank 5
+        Bitboard(0x00_00_00_00_02_00_00_00),
+        Bitboard(0x00_00_00_00_05_00_00_00),
+        Bitboard(0x00_00_00_00_0A_00_00_00),
+        Bitboard(0x00_00_00_00_14_00_00_00),
+        Bitboard(0x00_00_00_00_28_00_00_00),
+        Bitboard(0x00_00_00_00_50_00_00_00),
+        Bitboard(0x00_00_00_00_A0_00_00_00),
+        Bitboard(0x00_00_00_00_40_00_00_00),
+        // rank 6
+        Bitboard(0x00_00_00_02_00_00_00_00),
+        Bitboard(0x00_00_00_05_00_00_00_00),
+        Bitboard(0x00_00_00_0A_00_00_00_00),
+        Bitboard(0x00_00_00_14_00_00_00_00),
+        Bitboard(0x00_00_00_28_00_00_00_00),
+        Bitboard(0x00_00_00_50_00_00_00_00),
+        Bitboard(0x00_00_00_A0_00_00_00_00),
+        Bitboard(0x00_00_00_40_00_00_00_00),
+        // rank 7
+        Bitboard(0x00_00_02_00_00_00_00_00),
+        Bitboard(0x00_00_05_00_00_00_00_00),
+        Bitboard(0x00_00_0A_00_00_00_00_00),
+        Bitboard(0x00_00_14_00_00_00_00_00),
+        Bitboard(0x00_00_28_00_00_00_00_00),
+        Bitboard(0x00_00_50_00_00_00_00_00),
+        Bitboard(0x00_00_A0_00_00_00_00_00),
+        Bitboard(0x00_00_40_00_00_00_00_00),
+        // rank 8
+        Bitboard(0x00_02_00_00_00_00_00_00),
+        Bitboard(0x00_05_00_00_00_00_00_00),
+        Bitboard(0x00_0A_00_00_00_00_00_00),
+        Bitboard(0x00_14_00_00_00_00_00_00),
+        Bitboard(0x00_28_00_00_00_00_00_00),
+        Bitboard(0x00_50_00_00_00_00_00_00),
+        Bitboard(0x00_A0_00_00_00_00_00_00),
+        Bitboard(0x00_40_00_00_00_00_00_00),
+    ]),
+    // White
+    SquareMap::from_array([
+        // rank 1
+        Bitboard(0x00_00_00_00_00_00_02_00),
+        Bitboard(0x00_00_00_00_00_00_05_00),
+        Bitboard(0x00_00_00_00_00_00_0A_00),
+        Bitboard(0x00_00_00_00_00_00_14_00),
+        Bitboard(0x00_00_00_00_00_00_28_00),
+        Bitboard(0x00_00_00_00_00_00_50_00),
+        Bitboard(0x00_00_00_00_00_00_A0_00),
+        Bitboard(0x00_00_00_00_00_00_40_00),
+        // rank 2
+        Bitboard(0x00_00_00_00_00_02_00_00),
+        Bitboard(0x00_00_00_00_00_05_00_00),
+        Bitboard(0x00_00_00_00_00_0A_00_00),
+        Bitboard(0x00_00_00_00_00_14_00_00),
+        Bitboard(0x00_00_00_00_00_28_00_00),
+        Bitboard(0x00_00_00_00_00_50_00_00),
+        Bitboard(0x00_00_00_00_00_A0_00_00),
+        Bitboard(0x00_00_00_00_00_40_00_00),
+        // rank 3
+        Bitboard(0x00_00_00_00_02_00_00_00),
+        Bitboard(0x00_00_00_00_05_00_00_00),
+        Bitboard(0x00_00_00_00_0A_00_00_00),
+        Bitboard(0x00_00_00_00_14_00_00_00),
+        Bitboard(0x00_00_00_00_28_00_00_00),
+        Bitboard(0x00_00_00_00_50_00_00_00),
+        Bitboard(0x00_00_00_00_A0_00_00_00),
+        Bitboard(0x00_00_00_00_40_00_00_00),
+        // rank 4
+        Bitboard(0x00_00_00_02_00_00_00_00),
+        Bitboard(0x00_00_00_05_00_00_00_00),
+        Bitboard(0x00_00_00_0A_00_00_00_00),
+        Bitboard(0x00_00_00_14_00_00_00_00),
+        Bitboard(0x00_00_00_28_00_00_00_00),
+        Bitboard(0x00_00_00_50_00_00_00_00),
+        Bitboard(0x00_00_00_A0_00_00_00_00),
+        Bitboard(0x00_00_00_40_00_00_00_00),
+        // rank 5
+        Bitboard(0x00_00_02_00_00_00_00_00),
+        Bitboard(0x00_00_05_00_00_00_00_00),
+        Bitboard(0x00_00_0A_00_00_00_00_00),
+        Bitboard(0x00_00_14_00_00_00_00_00),
+        Bitboard(0x00_00_28_00_00_00_00_00),
+        Bitboard(0x00_00_50_00_00_00_00_00),
+        Bitboard(0x00_00_A0_00_00_00_00_00),
+        Bitboard(0x00_00_40_00_00_00_00_00),
+        // rank 6
+        Bitboard(0x00_02_00_00_00_00_00_00),
+        Bitboard(0x00_05_00_00_00_00_00_00),
+        Bitboard(0x00_0A_00_00_00_00_00_00),
+        Bitboard(0x00_14_00_00_00_00_00_00),
+        Bitboard(0x00_28_00_00_00_00_00_00),
+        Bitboard(0x00_50_00_00_00_00_00_00),
+        Bitboard(0x00_A0_00_00_00_00_00_00),
+        Bitboard(0x00_40_00_00_00_00_00_00),
+        // rank 7
+        Bitboard(0x02_00_00_00_00_00_00_00),
+        Bitboard(0x05_00_00_00_00_00_00_00),
+        Bitboard(0x0A_00_00_00_00_00_00_00),
+        Bitboard(0x14_00_00_00_00_00_00_00),
+        Bitboard(0x28_00_00_00_00_00_00_00),
+        Bitboard(0x50_00_00_00_00_00_00_00),
+        Bitboard(0xA0_00_00_00_00_00_00_00),
+        Bitboard(0x40_00_00_00_00_00_00_00),
+        // rank 8
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+        Bitboard(0x00_00_00_00_00_00_00_00),
+    ]),
+];
+
 const LEFT_FILES: [Bitboard; 9] = [
     Bitboard(0x00_00_00_00_00_00_00_00),
     Bitboard(0x01_01_01_01_01_01_01_01),
@@ -667,10 +885,12 @@ pub const KNIGHT_OUTPOSTS: [Bitboard; 2] = [
 ];
 
 pub const CENTER: Bitboard = Bitboard(0x00_00_00_3C_3C_00_00_00);
+pub const LIGHT_SQUARES: Bitboard = Bitboard(0x55_AA_55_AA_55_AA_55_AA);
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::vec::Vec;
 
     #[test]
     fn pawn_corridor_does_not_contain_origin() {
@@ -680,4 +900,54 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn square_distance() {
+        assert_eq!(SQUARE_A1.chebyshev_distance(SQUARE_A1), 0);
+        assert_eq!(SQUARE_A1.chebyshev_distance(SQUARE_H8), 7);
+        assert_eq!(SQUARE_E1.chebyshev_distance(SQUARE_E8), 7);
+        assert_eq!(SQUARE_H1.chebyshev_distance(Square::file_rank(5, 2)), 2);
+    }
+
+    #[test]
+    fn manhattan_distances() {
+        assert_eq!(SQUARE_A1.manhattan_distance(SQUARE_A1), 0);
+        assert_eq!(SQUARE_A1.manhattan_distance(SQUARE_H8), 14);
+        assert_eq!(SQUARE_H1.manhattan_distance(SQUARE_A8), 14);
+        assert_eq!(SQUARE_E1.manhattan_distance(Square::file_rank(3, 0)), 1);
+        assert_eq!(SQUARE_E1.manhattan_distance(Square::file_rank(5, 1)), 2);
+        assert_eq!(SQUARE_E1.chebyshev_distance(Square::file_rank(5, 1)), 1);
+
+        for a in ALL_SQUARES.squares() {
+            for b in ALL_SQUARES.squares() {
+                assert_eq!(a.manhattan_distance(b), b.manhattan_distance(a));
+                assert!(a.chebyshev_distance(b) <= a.manhattan_distance(b));
+            }
+        }
+
+        assert_eq!(SQUARE_A1.center_manhattan_distance(), 6);
+        assert_eq!(SQUARE_H8.center_manhattan_distance(), 6);
+        assert_eq!(SQUARE_E1.center_manhattan_distance(), 3);
+        assert_eq!(Square::file_rank(3, 3).center_manhattan_distance(), 0);
+        assert_eq!(Square::file_rank(4, 4).center_manhattan_distance(), 0);
+        assert_eq!(Square::file_rank(2, 4).center_manhattan_distance(), 1);
+    }
+
+    #[test]
+    fn squares_rev_is_reversed_squares() {
+        let boards = [
+            Bitboard(0),
+            Bitboard(1),
+            Bitboard(1 << 63),
+            ALL_SQUARES,
+            LIGHT_SQUARES,
+            CENTER,
+            Bitboard(0x81_00_00_18_00_42_00_81),
+        ];
+        for &bb in boards.iter() {
+            let mut forward = bb.squares().collect::<Vec<_>>();
+            forward.reverse();
+            assert_eq!(bb.squares_rev().collect::<Vec<_>>(), forward);
+        }
+    }
 }
@@ -0,0 +1,256 @@
+/* Asymptote, a UCI chess engine
+   Copyright (C) 2018-2020  Maximilian Lupke
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync;
+
+use crate::movegen::Move;
+use crate::position::{FenError, Position};
+use crate::search::Ply;
+use crate::search_controller::SearchController;
+use crate::time::TimeControl;
+
+/// A single EPD record: a position and its operations, e.g. `bm Nf3;` or
+/// `id "WAC.001";`.
+pub struct EpdRecord {
+    pub position: Position,
+    /// Maps every opcode to its operands, with the quotes of string operands
+    /// removed.
+    pub operations: HashMap<String, Vec<String>>,
+}
+
+/// Reasons why `parse_epd` rejects a line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EpdError {
+    /// The first four fields don't describe a valid position.
+    Fen(FenError),
+    /// A string operand has no closing quote.
+    UnterminatedString,
+    /// The operation with the given opcode is not terminated by a semicolon.
+    MissingSemicolon(String),
+}
+
+impl From<FenError> for EpdError {
+    fn from(err: FenError) -> EpdError {
+        EpdError::Fen(err)
+    }
+}
+
+/// Parses an EPD line. EPD has no halfmove clock and move number fields, so
+/// they default to 0 and 1.
+// `map_or` instead of `is_some_and`, which needs Rust 1.70
+#[allow(clippy::unnecessary_map_or)]
+pub fn parse_epd(line: &str) -> Result<EpdRecord, EpdError> {
+    let mut rest = line.trim_start();
+    let mut fields = Vec::with_capacity(4);
+    for _ in 0..4 {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(EpdError::Fen(FenError::MissingField));
+        }
+        fields.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+
+    let position = Position::try_from_fen(&format!("{} 0 1", fields.join(" ")))?;
+
+    let mut operations = HashMap::new();
+    let mut chars = rest.chars().peekable();
+    loop {
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut opcode = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() || c == ';' {
+                break;
+            }
+            opcode.push(c);
+            chars.next();
+        }
+
+        let mut operands = Vec::new();
+        loop {
+            match chars.next() {
+                Some(';') => break,
+                Some(c) if c.is_whitespace() => {}
+                Some('"') => {
+                    let mut operand = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('"') => break,
+                            Some(c) => operand.push(c),
+                            None => return Err(EpdError::UnterminatedString),
+                        }
+                    }
+                    operands.push(operand);
+                }
+                Some(c) => {
+                    let mut operand = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || c == ';' {
+                            break;
+                        }
+                        operand.push(c);
+                        chars.next();
+                    }
+                    operands.push(operand);
+                }
+                None => return Err(EpdError::MissingSemicolon(opcode)),
+            }
+        }
+
+        operations.insert(opcode, operands);
+    }
+
+    Ok(EpdRecord {
+        position,
+        operations,
+    })
+}
+
+impl EpdRecord {
+    /// Returns the moves of the `bm` operation which are legal in `pos`.
+    /// Operands which don't parse as a legal move in standard algebraic
+    /// notation are skipped.
+    pub fn best_moves(&self, pos: &Position) -> Vec<Move> {
+        self.operations
+            .get("bm")
+            .map(|operands| {
+                operands
+                    .iter()
+                    .filter_map(|san| Move::from_san(pos, san))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Searches every record of an EPD test suite to a fixed depth and reports
+/// how many of the `bm` moves were found. Lines which don't parse or have no
+/// legal best move are skipped.
+pub fn run_test_suite<P: AsRef<Path>>(
+    path: P,
+    ply: Ply,
+    abort: sync::Arc<sync::atomic::AtomicBool>,
+) -> io::Result<()> {
+    let tc = TimeControl::FixedDepth(ply);
+
+    let mut solved = 0;
+    let mut total = 0;
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let record = match parse_epd(&line) {
+            Ok(record) => record,
+            Err(err) => {
+                eprintln!("Line {}: unable to parse record: {:?}", i + 1, err);
+                continue;
+            }
+        };
+        let best_moves = record.best_moves(&record.position);
+        if best_moves.is_empty() {
+            eprintln!("Line {}: no legal best move", i + 1);
+            continue;
+        }
+
+        let mut search = SearchController::new(record.position, sync::Arc::clone(&abort));
+        search.resize_tt(17); // 8 MB hash table
+        search.set_time_control(tc);
+        let mov = search.get_best_move();
+
+        total += 1;
+        let found = best_moves.contains(&mov);
+        if found {
+            solved += 1;
+        }
+        println!(
+            "Line {:>4}: {:<7} {}",
+            i + 1,
+            mov.to_algebraic(),
+            if found { "ok" } else { "missed" }
+        );
+    }
+
+    println!("Solved: {} / {}", solved, total);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_test_suite_records() {
+        crate::magic::initialize_magics();
+
+        let record = parse_epd(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(
+            record.position.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+        assert_eq!(record.operations["id"], vec![String::from("WAC.001")]);
+
+        let best_moves = record.best_moves(&record.position);
+        let best_moves: Vec<_> = best_moves.into_iter().map(Move::to_algebraic).collect();
+        assert_eq!(best_moves, vec![String::from("g3g6")]);
+
+        // Several best moves, a string containing a semicolon and an
+        // operation without operands.
+        let record =
+            parse_epd("4k3/8/8/8/8/8/8/R3K2R w KQ - bm O-O Rh8+; c0 \"a; b\"; noop;").unwrap();
+        let best_moves = record.best_moves(&record.position);
+        let best_moves: Vec<_> = best_moves.into_iter().map(Move::to_algebraic).collect();
+        assert_eq!(best_moves, vec![String::from("e1g1"), String::from("h1h8")]);
+        assert_eq!(record.operations["c0"], vec![String::from("a; b")]);
+        assert!(record.operations["noop"].is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        crate::magic::initialize_magics();
+
+        assert_eq!(
+            parse_epd("4k3/8/8/8/8/8/8/4K3 w -").err(),
+            Some(EpdError::Fen(FenError::MissingField))
+        );
+        assert_eq!(
+            parse_epd("4k3/8/8/8/8/8/8/4X3 w - - bm Kd1;").err(),
+            Some(EpdError::Fen(FenError::BadPieceChar('X')))
+        );
+        assert_eq!(
+            parse_epd("4k3/8/8/8/8/8/8/4K3 w - - id \"open;").err(),
+            Some(EpdError::UnterminatedString)
+        );
+        assert_eq!(
+            parse_epd("4k3/8/8/8/8/8/8/4K3 w - - bm Kd1").err(),
+            Some(EpdError::MissingSemicolon(String::from("bm")))
+        );
+    }
+}
@@ -14,7 +14,8 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
-use std::cmp;
+use core::cmp;
+use core::ops::Deref;
 
 use crate::bitboard::*;
 use crate::hash::*;
@@ -28,14 +29,37 @@ use crate::tune::*;
 #[derive(Clone)]
 pub struct Eval {
     material: [[u8; 5]; 2],
-    pst: [EScore; 2],
-    pawn_table: [PawnHashEntry; PAWN_TABLE_NUM_ENTRIES],
+    pst: PstAccumulator,
+}
+
+/// The working state of a single evaluation: the attack maps filled in by
+/// `mobility_for_side` and, when tuning, the trace. The `Eval` is only read.
+struct Evaluation<'a> {
+    eval: &'a Eval,
     attacked_by: [[Bitboard; 6]; 2],
     attacked_by_1: [Bitboard; 2],
     attacked_by_2: [Bitboard; 2],
+    // Pieces of each side hitting the enemy king zone, weighted by their
+    // closeness to the king
+    king_zone_proximity: [EScore; 2],
 
     #[cfg(feature = "tune")]
-    pub trace: Trace,
+    trace: Trace,
+}
+
+impl<'a> Deref for Evaluation<'a> {
+    type Target = Eval;
+
+    fn deref(&self) -> &Eval {
+        self.eval
+    }
+}
+
+/// The caches used while evaluating. Each search thread owns one.
+#[derive(Clone, Default)]
+pub struct EvalCache {
+    pawns: PawnCache,
+    material: MaterialTable,
 }
 
 const PAWN_TABLE_NUM_ENTRIES: usize = 2 * 1024;
@@ -46,6 +70,75 @@ struct PawnHashEntry {
     score: EScore,
 }
 
+/// Cache of pawn structure scores indexed by pawn hash.
+#[derive(Clone)]
+pub struct PawnCache {
+    entries: [PawnHashEntry; PAWN_TABLE_NUM_ENTRIES],
+}
+
+impl Default for PawnCache {
+    fn default() -> Self {
+        PawnCache {
+            entries: [PawnHashEntry::default(); PAWN_TABLE_NUM_ENTRIES],
+        }
+    }
+}
+
+impl PawnCache {
+    fn get(&self, pawn_hash: Hash) -> Option<EScore> {
+        let entry = &self.entries[pawn_hash as usize % PAWN_TABLE_NUM_ENTRIES];
+        if entry.hash == pawn_hash {
+            Some(entry.score)
+        } else {
+            None
+        }
+    }
+
+    fn insert(&mut self, pawn_hash: Hash, score: EScore) {
+        let entry = &mut self.entries[pawn_hash as usize % PAWN_TABLE_NUM_ENTRIES];
+        entry.hash = pawn_hash;
+        entry.score = score;
+    }
+}
+
+const MATERIAL_TABLE_NUM_ENTRIES: usize = 512;
+
+/// What is known about a configuration of material alone.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MaterialEntry {
+    hash: Hash,
+    /// Neither side can win, see `Eval::is_material_draw`.
+    pub draw: bool,
+    /// Replaces the generic endgame scale factor in known endgames.
+    pub scale_factor: Option<i32>,
+}
+
+/// Cache of `MaterialEntry`s indexed by material hash. Empty slots are
+/// `None`, bare kings have a material hash of 0.
+#[derive(Clone)]
+pub struct MaterialTable {
+    entries: [Option<MaterialEntry>; MATERIAL_TABLE_NUM_ENTRIES],
+}
+
+impl Default for MaterialTable {
+    fn default() -> Self {
+        MaterialTable {
+            entries: [None; MATERIAL_TABLE_NUM_ENTRIES],
+        }
+    }
+}
+
+impl MaterialTable {
+    pub fn probe_material(&self, material_hash: Hash) -> Option<MaterialEntry> {
+        self.entries[material_hash as usize % MATERIAL_TABLE_NUM_ENTRIES]
+            .filter(|entry| entry.hash == material_hash)
+    }
+
+    fn insert(&mut self, entry: MaterialEntry) {
+        self.entries[entry.hash as usize % MATERIAL_TABLE_NUM_ENTRIES] = Some(entry);
+    }
+}
+
 pub type Score = i16;
 pub type EScore = i32;
 
@@ -70,8 +163,20 @@ fn interpolate(score: EScore, phase: i16) -> i32 {
 }
 
 pub const MATE_SCORE: Score = 20000;
+// Outermost search bounds. Kept well below `Score::max_value()` so that
+// `alpha + 1`, `-beta` and small margins cannot overflow.
+pub const INFINITY: Score = 30000;
+pub const NEG_INFINITY: Score = -INFINITY;
+
+/// Adds `delta` to `score`, saturating at the search bounds instead of
+/// overflowing.
+pub fn bounded_add(score: Score, delta: Score) -> Score {
+    let sum = i32::from(score) + i32::from(delta);
+    cmp::max(i32::from(NEG_INFINITY), cmp::min(i32::from(INFINITY), sum)) as Score
+}
 pub const SF_NORMAL: i32 = 64;
 const SF_PAWNLESS: i32 = 32;
+const SF_OPPOSITE_BISHOPS: i32 = 32;
 
 pub const TEMPO_SCORE: EScore = S(26, 23);
 
@@ -120,6 +225,8 @@ pub const CENTER_CONTROL: EScore = S(5, 1);
 pub const DOUBLED_PAWN: EScore = S(-5, -23);
 pub const OPEN_ISOLATED_PAWN: EScore = S(-26, -11);
 pub const ISOLATED_PAWN: EScore = S(-27, 5);
+// Bonus for more pawns than the opponent on the queenside or kingside files
+pub const PAWN_MAJORITY_BONUS: EScore = S(2, 12);
 
 #[rustfmt::skip]
 pub const PASSED_PAWN_ON_RANK: [EScore; 8] = [
@@ -133,14 +240,53 @@ pub const PASSED_PAWN_ON_FILE: [EScore; 8] = [
     S(  -8,  -15), S(  -2,  -10), S(  -3,    7), S(  -6,    9), 
 ];
 
+// Bonus for a passed pawn with a friendly pawn on an adjacent file at most
+// one rank away, indexed by relative rank.
+#[rustfmt::skip]
+pub const CONNECTED_PASSED_PAWN: [EScore; 8] = [
+    S(   0,    0), S(   0,    0), S(   2,    5), S(   4,   10),
+    S(   8,   20), S(  12,   40), S(  16,   70), S(   0,    0),
+];
+
+// Bonus for a passed pawn defended by a friendly pawn, indexed by relative
+// rank.
+#[rustfmt::skip]
+pub const PROTECTED_PASSED_PAWN: [EScore; 8] = [
+    S(   0,    0), S(   0,    0), S(   2,    4), S(   3,    8),
+    S(   6,   16), S(  10,   30), S(  14,   50), S(   0,    0),
+];
+
+// Passed pawn whose path to promotion is occupied or attacked by the enemy
+pub const PASSED_PAWN_CONTESTED_PATH: EScore = S(-4, -12);
+// Passed pawn whose path to promotion is free and covered by our pieces
+pub const PASSED_PAWN_DEFENDED_PATH: EScore = S(4, 16);
+// Passed pawn with an enemy piece right in front of it
+pub const PASSED_PAWN_BLOCKED: EScore = S(0, -14);
+// Passed pawn whose promotion square is closer to our king than to theirs
+pub const PASSED_PAWN_KING_CLOSER: EScore = S(0, 12);
+
 pub const KNIGHT_OUTPOST: EScore = S(29, -8);
+pub const KNIGHT_PAIR: EScore = S(-8, -8);
+// Bonus per knight for each pawn on the board above eight
+pub const KNIGHT_PAWNS: EScore = S(2, 3);
 
 pub const XRAYED_SQUARE: EScore = S(5, 0);
 pub const BISHOP_PAIR: EScore = S(42, 48);
+// Bishop pair adjustment per pawn above eight, the pair gains in open positions
+pub const BISHOP_PAIR_PAWNS: EScore = S(-2, -3);
 
 pub const ROOK_OPEN_FILE: EScore = S(30, 8);
 pub const ROOK_HALFOPEN_FILE: EScore = S(10, 18);
+// Extra bonus for an open or half-open file that leads to the enemy king
+pub const ROOK_KING_FILE: EScore = S(15, 0);
+// Bonus for two rooks defending each other along a rank or file
+pub const CONNECTED_ROOKS_BONUS: EScore = S(10, 0);
+// Penalty for a rook shut into the corner by its own king which can no longer castle
+pub const TRAPPED_ROOK_PENALTY: EScore = S(-40, -10);
 pub const ROOK_PAIR: EScore = S(17, -58);
+// Two minor pieces traded for a rook, they coordinate better in the middlegame
+// but the rook is stronger in the endgame
+pub const MINORS_VS_ROOK: EScore = S(30, -20);
 
 #[rustfmt::skip]
 pub const KING_SAFETY: [Score; 30] = [
@@ -154,6 +300,26 @@ pub const KING_SAFETY: [Score; 30] = [
     -784, -841,
 ];
 
+// Bonus per piece for each step of closeness (7 - distance) to the enemy king
+#[rustfmt::skip]
+pub const TROPISM_WEIGHT: [EScore; 6] = [
+    S(0, 0), S(3, 0), S(1, 0), S(1, 0), S(4, 0), S(0, 0),
+];
+
+pub const KING_OPPOSITION: EScore = S(0, 25);
+
+// Penalty for a king on a central back rank square without castling rights
+pub const KING_STUCK_PENALTY: EScore = S(-30, 0);
+
+// Penalty for a castled king behind a fianchetto without its bishop
+pub const FIANCHETTO_HOLES_PENALTY: EScore = S(-18, -4);
+
+// Penalty per enemy piece hitting the king zone, scaled by its closeness to the king
+pub const KING_ATTACKER_PROXIMITY: EScore = S(-3, 0);
+
+// Penalty for a queen leaving home while minor pieces are still undeveloped
+pub const EARLY_QUEEN_PENALTY: EScore = S(-20, 0);
+
 pub const KING_CHECK_KNIGHT: EScore = S(-85, 0);
 pub const KING_CHECK_BISHOP: EScore = S(-15, 0);
 pub const KING_CHECK_ROOK: EScore = S(-52, 0);
@@ -239,7 +405,210 @@ pub const KING_PST: SquareMap<EScore> = SquareMap::from_array([
 ]);
 
 impl Eval {
-    pub fn score(&mut self, pos: &Position, pawn_hash: Hash) -> Score {
+    /// Scores the position from the side to move's point of view. This
+    /// allocates fresh caches on every call, so it's only meant for one-off
+    /// evaluations. Use `score_with_cache` when evaluating many positions.
+    pub fn score_uncached(&self, pos: &Position, pawn_hash: Hash, material_hash: Hash) -> Score {
+        self.score_with_cache(pos, pawn_hash, material_hash, &mut EvalCache::default())
+    }
+
+    /// Like `score_uncached`, but with the given caches. The `Eval` is only read, so
+    /// it can be shared while every thread keeps its own `EvalCache`.
+    pub fn score_with_cache(
+        &self,
+        pos: &Position,
+        pawn_hash: Hash,
+        material_hash: Hash,
+        cache: &mut EvalCache,
+    ) -> Score {
+        Evaluation::new(self).evaluate(pos, pawn_hash, material_hash, cache)
+    }
+
+    /// Evaluates the position and returns the trace of all terms.
+    #[cfg(feature = "tune")]
+    pub fn trace(&self, pos: &Position) -> Trace {
+        let mut evaluation = Evaluation::new(self);
+        evaluation.evaluate(pos, 0, 0, &mut EvalCache::default());
+        evaluation.trace
+    }
+
+    pub fn phase(&self) -> i16 {
+        cmp::min(
+            62,
+            self.non_pawn_material(false) + self.non_pawn_material(true),
+        )
+    }
+
+    /// Checks whether each side has a single bishop and nothing else besides
+    /// pawns, with the bishops on squares of different colors. Such endgames
+    /// are very drawish, even a pawn or two up. Unlike the material table
+    /// this depends on where the bishops stand.
+    fn only_opposite_colored_bishops(&self, pos: &Position) -> bool {
+        const BISHOP_ONLY: [u8; 4] = [0, 1, 0, 0];
+
+        let bishops = pos.bishops();
+        self.material[0][1..] == BISHOP_ONLY
+            && self.material[1][1..] == BISHOP_ONLY
+            && (bishops & LIGHT_SQUARES).popcount() == 1
+            && (bishops & !LIGHT_SQUARES).popcount() == 1
+    }
+
+    /// Bishops which all stand on squares of the same color can never mate,
+    /// no matter how many there are.
+    fn only_same_colored_bishops(&self, pos: &Position) -> bool {
+        let bishops = pos.bishops();
+        (pos.pawns() | pos.knights() | pos.rooks() | pos.queens()).is_empty()
+            && ((bishops & LIGHT_SQUARES).is_empty() || (bishops & !LIGHT_SQUARES).is_empty())
+    }
+
+    /// Looks up what is known about the current material in the material
+    /// table, classifying it first if it isn't there yet.
+    fn material_entry(&self, table: &mut MaterialTable, material_hash: Hash) -> MaterialEntry {
+        // Don't do material table lookups if we are tuning
+        if !cfg!(feature = "tune") {
+            if let Some(entry) = table.probe_material(material_hash) {
+                return entry;
+            }
+        }
+
+        let entry = MaterialEntry {
+            hash: material_hash,
+            draw: self.is_drawn_material(),
+            scale_factor: self.known_endgame_scale_factor(),
+        };
+        table.insert(entry);
+        entry
+    }
+
+    /// KBN vs K and KQ vs KR are wins for the stronger side even without
+    /// pawns, so they shouldn't be scaled down like other pawnless endgames.
+    fn known_endgame_scale_factor(&self) -> Option<i32> {
+        const KBN: [u8; 5] = [0, 1, 1, 0, 0];
+        const KQ: [u8; 5] = [0, 0, 0, 0, 1];
+        const KR: [u8; 5] = [0, 0, 0, 1, 0];
+        const K: [u8; 5] = [0; 5];
+
+        for side in 0..2 {
+            let (strong, weak) = (self.material[side], self.material[1 - side]);
+            if (strong == KBN && weak == K) || (strong == KQ && weak == KR) {
+                return Some(SF_NORMAL);
+            }
+        }
+
+        None
+    }
+
+    /// Checks whether neither side can win: too little material, or only
+    /// bishops which all stand on squares of the same color.
+    pub fn is_material_draw(&self, pos: &Position) -> bool {
+        self.is_drawn_material() || self.only_same_colored_bishops(pos)
+    }
+
+    /// The part of `is_material_draw` which only depends on the material, so
+    /// it can be kept in the material table.
+    fn is_drawn_material(&self) -> bool {
+        let material = &self.material;
+        let pawn = Piece::Pawn.index();
+        let knight = Piece::Knight.index();
+        let bishop = Piece::Bishop.index();
+        let rook = Piece::Rook.index();
+        let queen = Piece::Queen.index();
+
+        for side_mat in material.iter() {
+            if side_mat[pawn] > 0 || side_mat[rook] > 0 || side_mat[queen] > 0 {
+                return false;
+            }
+        }
+
+        for side in 0..2 {
+            if material[side][bishop] == 0 && material[side][knight] == 0 {
+                if material[1 - side][bishop] == 0 && material[1 - side][knight] < 3 {
+                    return true;
+                }
+
+                // Three or more knights, e.g. after promotions, can still mate
+                return material[1 - side][bishop] + material[1 - side][knight] <= 1;
+            }
+        }
+
+        false
+    }
+
+    /// Pure material balance from the point of view of the given side,
+    /// tapered by game phase. Much cheaper than a full evaluation.
+    pub fn material_balance(&self, white: bool) -> Score {
+        const PIECE_SCORES: [EScore; 5] = [
+            PAWN_SCORE,
+            KNIGHT_SCORE,
+            BISHOP_SCORE,
+            ROOK_SCORE,
+            QUEEN_SCORE,
+        ];
+
+        let side = white as usize;
+        let mut score = S(0, 0);
+        for (i, &piece_score) in PIECE_SCORES.iter().enumerate() {
+            let count = self.material[side][i] as EScore - self.material[1 - side][i] as EScore;
+            score += count * piece_score;
+        }
+
+        let phase = cmp::min(
+            62,
+            self.non_pawn_material(false) + self.non_pawn_material(true),
+        );
+        interpolate(score, phase) as Score
+    }
+
+    pub fn non_pawn_material(&self, white: bool) -> Score {
+        let mut material = 0;
+        let side = white as usize;
+        material += 3 * self.material[side][Piece::Knight.index()] as Score;
+        material += 3 * self.material[side][Piece::Bishop.index()] as Score;
+        material += 5 * self.material[side][Piece::Rook.index()] as Score;
+        material += 9 * self.material[side][Piece::Queen.index()] as Score;
+        material
+    }
+
+    /// Whether we have two more minor pieces and one rook less than the
+    /// opponent, with the queens balanced.
+    fn has_minors_for_rook(&self, white: bool) -> bool {
+        let us = &self.material[white as usize];
+        let them = &self.material[1 - white as usize];
+        let n = Piece::Knight.index();
+        let b = Piece::Bishop.index();
+        let r = Piece::Rook.index();
+        let q = Piece::Queen.index();
+
+        us[n] + us[b] == them[n] + them[b] + 2 && us[r] + 1 == them[r] && us[q] == them[q]
+    }
+}
+
+impl<'a> Evaluation<'a> {
+    fn new(eval: &'a Eval) -> Self {
+        Evaluation {
+            eval,
+            attacked_by: [[Bitboard::from(0); 6]; 2],
+            attacked_by_1: [Bitboard::from(0); 2],
+            attacked_by_2: [Bitboard::from(0); 2],
+            king_zone_proximity: [0; 2],
+
+            #[cfg(feature = "tune")]
+            trace: Trace::default(),
+        }
+    }
+
+    fn evaluate(
+        &mut self,
+        pos: &Position,
+        pawn_hash: Hash,
+        material_hash: Hash,
+        cache: &mut EvalCache,
+    ) -> Score {
+        let material = self.material_entry(&mut cache.material, material_hash);
+        if material.draw || self.only_same_colored_bishops(pos) {
+            return 0;
+        }
+
         let mut score = S(0, 0);
 
         score += self.pst(pos, true) - self.pst(pos, false);
@@ -248,9 +617,16 @@ impl Eval {
         score += self.knights_for_side(pos, true) - self.knights_for_side(pos, false);
         score += self.bishops_for_side(pos, true) - self.bishops_for_side(pos, false);
         score += self.rooks_for_side(pos, true) - self.rooks_for_side(pos, false);
-        score += self.material(true) - self.material(false);
+        score += self.material(pos, true) - self.material(pos, false);
         score += self.king_safety_for_side(pos, true) - self.king_safety_for_side(pos, false);
-        score += self.pawns(pos, pawn_hash);
+        score += self.tropism_for_side(pos, true) - self.tropism_for_side(pos, false);
+        score += self.stuck_king_for_side(pos, true) - self.stuck_king_for_side(pos, false);
+        score +=
+            self.fianchetto_holes_for_side(pos, true) - self.fianchetto_holes_for_side(pos, false);
+        score += self.early_queen_for_side(pos, true) - self.early_queen_for_side(pos, false);
+        score += self.passed_path_for_side(pos, true) - self.passed_path_for_side(pos, false);
+        score += self.pawns(pos, pawn_hash, &mut cache.pawns);
+        score += self.opposition(pos);
 
         if pos.white_to_move {
             score += TEMPO_SCORE;
@@ -260,14 +636,19 @@ impl Eval {
 
         #[cfg(feature = "tune")]
         {
-            self.trace.tempo[pos.white_to_move as usize] = 1;
+            self.trace.tempo[pos.side_to_move().index()] = 1;
             self.trace.base_eval = score;
         }
 
         let phase = self.phase();
+        #[cfg(feature = "tune")]
+        {
+            self.trace.phase = phase as i8;
+        }
+
         let mut score = interpolate(score, phase);
 
-        let sf = self.endgame_scale_factor(score);
+        let sf = self.endgame_scale_factor(pos, score, material.scale_factor);
         score *= sf;
         score /= SF_NORMAL;
 
@@ -286,7 +667,7 @@ impl Eval {
             self.trace_pst(pos, white);
         }
 
-        self.pst[white as usize]
+        self.pst.score(white)
     }
 
     fn mobility_for_side(&mut self, pos: &Position, white: bool) -> EScore {
@@ -314,28 +695,43 @@ impl Eval {
         self.attacked_by_2[s] |= self.attacked_by_1[s] & b;
         self.attacked_by_1[s] |= b;
 
+        let their_king = pos.king_sq(!white);
+        let their_king_area = KING_ATTACKS[their_king];
+        let mut proximity = 0;
+
         let mut score = S(0, 0);
         let their_pawns = pos.pawns() & !us;
         let their_pawn_attacks = (their_pawns.left(1) | their_pawns.right(1)).forward(!white, 1);
         for knight in (pos.knights() & us).squares() {
             let b = KNIGHT_ATTACKS[knight];
-            let mobility = b & !their_pawn_attacks;
-            score += KNIGHT_MOBILITY[mobility.popcount()];
+            // At most eight squares, so this always indexes `KNIGHT_MOBILITY`.
+            let mobility = (b & !us & !their_pawn_attacks).popcount();
+            score += KNIGHT_MOBILITY[mobility];
             self.attacked_by[s][Piece::Knight.index()] |= b;
             self.attacked_by_2[s] |= self.attacked_by_1[s] & b;
             self.attacked_by_1[s] |= b;
+            if (b & their_king_area).at_least_one() {
+                proximity += 8 - knight.chebyshev_distance(their_king) as EScore;
+            }
             #[cfg(feature = "tune")]
             {
-                self.trace.mobility_knight[mobility.popcount()][s] += 1;
+                self.trace.mobility_knight[mobility][s] += 1;
             }
         }
 
+        // Sliders see through the enemy king, so that the squares behind it
+        // count as attacked for king safety.
+        let occupied = pos.all_pieces & !(pos.kings() & them);
+
         for bishop in (pos.bishops() & us).squares() {
-            let b = get_bishop_attacks_from(bishop, pos.all_pieces);
+            let b = get_bishop_attacks_from(bishop, occupied);
             score += BISHOP_MOBILITY[b.popcount()];
             self.attacked_by[s][Piece::Bishop.index()] |= b;
             self.attacked_by_2[s] |= self.attacked_by_1[s] & b;
             self.attacked_by_1[s] |= b;
+            if (b & their_king_area).at_least_one() {
+                proximity += 8 - bishop.chebyshev_distance(their_king) as EScore;
+            }
 
             #[cfg(feature = "tune")]
             {
@@ -343,12 +739,19 @@ impl Eval {
             }
         }
 
+        // Rooks and queens see through our own rooks and queens on files and
+        // ranks, so doubled rooks or batteries don't cut each other's mobility.
+        let rook_blockers = rook_blockers(pos, white) & occupied;
+
         for rook in (pos.rooks() & us).squares() {
-            let b = get_rook_attacks_from(rook, pos.all_pieces);
+            let b = get_rook_attacks_from(rook, rook_blockers);
             score += ROOK_MOBILITY[b.popcount()];
             self.attacked_by[s][Piece::Rook.index()] |= b;
             self.attacked_by_2[s] |= self.attacked_by_1[s] & b;
             self.attacked_by_1[s] |= b;
+            if (b & their_king_area).at_least_one() {
+                proximity += 8 - rook.chebyshev_distance(their_king) as EScore;
+            }
 
             #[cfg(feature = "tune")]
             {
@@ -357,12 +760,15 @@ impl Eval {
         }
 
         for queen in (pos.queens() & us).squares() {
-            let b = get_bishop_attacks_from(queen, pos.all_pieces)
-                | get_rook_attacks_from(queen, pos.all_pieces);
+            let b = get_bishop_attacks_from(queen, occupied)
+                | get_rook_attacks_from(queen, rook_blockers);
             score += QUEEN_MOBILITY[b.popcount()];
             self.attacked_by[s][Piece::Queen.index()] |= b;
             self.attacked_by_2[s] |= self.attacked_by_1[s] & b;
             self.attacked_by_1[s] |= b;
+            if (b & their_king_area).at_least_one() {
+                proximity += 8 - queen.chebyshev_distance(their_king) as EScore;
+            }
 
             #[cfg(feature = "tune")]
             {
@@ -370,6 +776,8 @@ impl Eval {
             }
         }
 
+        self.king_zone_proximity[s] = proximity;
+
         let b = KING_ATTACKS[pos.king_sq(white)];
         self.attacked_by[s][Piece::King.index()] |= b;
         self.attacked_by_2[s] |= self.attacked_by_1[s] & b;
@@ -384,7 +792,7 @@ impl Eval {
         score
     }
 
-    fn material(&mut self, white: bool) -> EScore {
+    fn material(&mut self, pos: &Position, white: bool) -> EScore {
         let p = Piece::Pawn.index();
         let n = Piece::Knight.index();
         let b = Piece::Bishop.index();
@@ -399,14 +807,33 @@ impl Eval {
         score += self.material[side][r] as EScore * ROOK_SCORE;
         score += self.material[side][q] as EScore * QUEEN_SCORE;
 
-        if self.material[side][b] > 1 {
+        let pawns_above_eight = (self.material[0][p] + self.material[1][p]) as EScore - 8;
+
+        // Only bishops of both colors make a pair, promoted extra bishops
+        // don't add to it.
+        let bishops = pos.bishops() & pos.us(white);
+        let bishop_pair =
+            (bishops & LIGHT_SQUARES).at_least_one() && (bishops & !LIGHT_SQUARES).at_least_one();
+        if bishop_pair {
             score += BISHOP_PAIR;
+            score += BISHOP_PAIR_PAWNS * pawns_above_eight;
         }
 
         if self.material[side][r] > 1 {
             score += ROOK_PAIR;
         }
 
+        if self.material[side][n] > 1 {
+            score += KNIGHT_PAIR;
+        }
+
+        score += KNIGHT_PAWNS * (self.material[side][n] as EScore * pawns_above_eight);
+
+        let minors_vs_rook = self.has_minors_for_rook(white);
+        if minors_vs_rook {
+            score += MINORS_VS_ROOK;
+        }
+
         #[cfg(feature = "tune")]
         {
             let k = Piece::King.index();
@@ -417,8 +844,12 @@ impl Eval {
             self.trace.material[q][side] = self.material[side][q] as i8;
             self.trace.material[k][side] = 1;
 
-            self.trace.bishops_pair[side] = cmp::min(1, self.material[side][b] as i8);
+            self.trace.bishops_pair[side] = bishop_pair as i8;
             self.trace.rooks_pair[side] = cmp::min(1, self.material[side][r] as i8);
+            self.trace.knights_pair[side] = (self.material[side][n] > 1) as i8;
+            self.trace.knights_pawns[side] = self.material[side][n] as i8 * pawns_above_eight as i8;
+            self.trace.bishop_pair_pawns[side] = bishop_pair as i8 * pawns_above_eight as i8;
+            self.trace.minors_vs_rook[side] = minors_vs_rook as i8;
         }
 
         score
@@ -439,21 +870,16 @@ impl Eval {
         controlled_count as i32 * CENTER_CONTROL
     }
 
-    fn pawns(&mut self, pos: &Position, pawn_hash: Hash) -> EScore {
+    fn pawns(&mut self, pos: &Position, pawn_hash: Hash, cache: &mut PawnCache) -> EScore {
         // Don't do pawn hash lookups if we are tuning
-        #[cfg(not(feature = "tune"))]
-        {
-            let pawn_hash_entry = &self.pawn_table[pawn_hash as usize % PAWN_TABLE_NUM_ENTRIES];
-            if pawn_hash_entry.hash == pawn_hash {
-                return pawn_hash_entry.score;
+        if !cfg!(feature = "tune") {
+            if let Some(score) = cache.get(pawn_hash) {
+                return score;
             }
         }
 
         let score = self.pawns_for_side(pos, true) - self.pawns_for_side(pos, false);
-
-        let pawn_hash_entry = &mut self.pawn_table[pawn_hash as usize % PAWN_TABLE_NUM_ENTRIES];
-        pawn_hash_entry.hash = pawn_hash;
-        pawn_hash_entry.score = score;
+        cache.insert(pawn_hash, score);
         score
     }
 
@@ -496,6 +922,28 @@ impl Eval {
                     self.trace.pawns_passed[relative_rank][side] += 1;
                     self.trace.pawns_passed_file[file][side] += 1;
                 }
+
+                let rank = pawn.rank() as usize;
+                let nearby_ranks =
+                    RANKS[rank.saturating_sub(1)] | RANKS[rank] | RANKS[cmp::min(rank + 1, 7)];
+                let neighbours = (file_bb.left(1) | file_bb.right(1)) & nearby_ranks;
+                if (neighbours & pos.pawns() & us).at_least_one() {
+                    score += CONNECTED_PASSED_PAWN[relative_rank];
+
+                    #[cfg(feature = "tune")]
+                    {
+                        self.trace.pawns_connected_passed[relative_rank][side] += 1;
+                    }
+                }
+
+                if (PAWN_ATTACKS[1 - side][pawn] & pos.pawns() & us).at_least_one() {
+                    score += PROTECTED_PASSED_PAWN[relative_rank];
+
+                    #[cfg(feature = "tune")]
+                    {
+                        self.trace.pawns_protected_passed[relative_rank][side] += 1;
+                    }
+                }
             }
 
             if isolated {
@@ -517,6 +965,19 @@ impl Eval {
             }
         }
 
+        for &flank in &[QUEENSIDE, KINGSIDE] {
+            let ours = (pos.pawns() & us & flank).popcount();
+            let theirs = (pos.pawns() & them & flank).popcount();
+            if ours > theirs {
+                score += PAWN_MAJORITY_BONUS;
+
+                #[cfg(feature = "tune")]
+                {
+                    self.trace.pawn_majority[side] += 1;
+                }
+            }
+        }
+
         score
     }
 
@@ -565,6 +1026,7 @@ impl Eval {
 
     pub fn rooks_for_side(&mut self, pos: &Position, white: bool) -> EScore {
         let us = pos.us(white);
+        let king_file = pos.king_sq(!white).file();
 
         let mut score = 0;
 
@@ -582,12 +1044,69 @@ impl Eval {
                 {
                     self.trace.rooks_halfopen_file[white as usize] += 1;
                 }
+            } else {
+                continue;
+            }
+
+            if rook.file() == king_file {
+                score += ROOK_KING_FILE;
+                #[cfg(feature = "tune")]
+                {
+                    self.trace.rooks_king_file[white as usize] += 1;
+                }
+            }
+        }
+
+        let rooks = pos.rooks() & us;
+        if rooks.more_than_one() {
+            let connected = rooks
+                .squares()
+                .any(|rook| (get_rook_attacks_from(rook, pos.all_pieces) & rooks).at_least_one());
+            if connected {
+                score += CONNECTED_ROOKS_BONUS;
+                #[cfg(feature = "tune")]
+                {
+                    self.trace.connected_rooks[white as usize] = 1;
+                }
+            }
+        }
+
+        if self.rook_is_trapped(pos, white) {
+            score += TRAPPED_ROOK_PENALTY;
+            #[cfg(feature = "tune")]
+            {
+                self.trace.trapped_rook[white as usize] = 1;
             }
         }
 
         score
     }
 
+    /// Checks for a rook in the corner behind its own king on the back rank,
+    /// e.g. king on f1/g1 and rook on g1/h1, when castling to that side is no
+    /// longer possible.
+    fn rook_is_trapped(&self, pos: &Position, white: bool) -> bool {
+        let back_rank = if white { 0 } else { 7 };
+        let king_sq = pos.king_sq(white);
+        if king_sq.rank() != back_rank {
+            return false;
+        }
+
+        let (kingside, queenside) = if white {
+            (CASTLE_WHITE_KSIDE, CASTLE_WHITE_QSIDE)
+        } else {
+            (CASTLE_BLACK_KSIDE, CASTLE_BLACK_QSIDE)
+        };
+
+        let king_file = king_sq.file();
+        let rooks = pos.rooks() & pos.us(white) & RANKS[back_rank as usize];
+        rooks.squares().any(|rook| match king_file {
+            5 | 6 => rook.file() > king_file && pos.details.castling & kingside == 0,
+            1 | 2 => rook.file() < king_file && pos.details.castling & queenside == 0,
+            _ => false,
+        })
+    }
+
     fn king_safety_for_side(&mut self, pos: &Position, white: bool) -> EScore {
         let us = pos.us(white);
         let them = pos.them(white);
@@ -669,6 +1188,8 @@ impl Eval {
 
         score += attack_value * KING_DANGER_WEIGHT[attack_count] / 128;
 
+        score += self.king_attacker_proximity(white);
+
         #[cfg(feature = "tune")]
         {
             self.trace.king_safety[index][side] += 1;
@@ -718,157 +1239,251 @@ impl Eval {
         score
     }
 
-    pub fn phase(&mut self) -> i16 {
-        let phase = cmp::min(
-            62,
-            self.non_pawn_material(false) + self.non_pawn_material(true),
-        );
+    /// Penalty for enemy pieces hitting the king zone, weighted by how close
+    /// they are: an attacker right next to the king is more dangerous than
+    /// one aiming at the zone from across the board. The attackers are
+    /// collected by `mobility_for_side`.
+    fn king_attacker_proximity(&mut self, white: bool) -> EScore {
+        let proximity = self.king_zone_proximity[1 - white as usize];
 
         #[cfg(feature = "tune")]
         {
-            self.trace.phase = phase as i8;
+            self.trace.king_attacker_proximity[white as usize] += proximity as i8;
         }
 
-        phase
+        KING_ATTACKER_PROXIMITY * proximity
     }
 
-    fn endgame_scale_factor(&mut self, score: i32) -> i32 {
-        let sf = if self.material[(score > 0) as usize][Piece::Pawn.index()] == 0 {
-            SF_PAWNLESS
+    fn tropism_for_side(&mut self, pos: &Position, white: bool) -> EScore {
+        let their_king = pos.king_sq(!white);
+
+        let mut score = S(0, 0);
+
+        for &piece in &[Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            for sq in pos.pieces_of(piece, white).squares() {
+                let closeness = 7 - sq.chebyshev_distance(their_king) as EScore;
+                score += TROPISM_WEIGHT[piece.index()] * closeness;
+
+                #[cfg(feature = "tune")]
+                {
+                    self.trace.tropism[piece.index()][white as usize] += closeness as i8;
+                }
+            }
+        }
+
+        score
+    }
+
+    /// Penalty for a king which lost all castling rights while still sitting
+    /// on the central files of its back rank, where it can't reach shelter.
+    fn stuck_king_for_side(&mut self, pos: &Position, white: bool) -> EScore {
+        let rights = if white {
+            CASTLE_WHITE_KSIDE | CASTLE_WHITE_QSIDE
         } else {
-            SF_NORMAL
+            CASTLE_BLACK_KSIDE | CASTLE_BLACK_QSIDE
         };
+        if pos.details.castling & rights != 0 {
+            return S(0, 0);
+        }
+
+        let king = pos.king_sq(white);
+        let back_rank = if white { 0 } else { 7 };
+        if king.rank() != back_rank || king.file() < 3 || king.file() > 5 {
+            return S(0, 0);
+        }
 
         #[cfg(feature = "tune")]
         {
-            self.trace.sf = sf as i8;
+            self.trace.king_stuck[white as usize] = 1;
         }
 
-        sf
+        KING_STUCK_PENALTY
     }
 
-    pub fn make_move(&mut self, mov: Move, white: bool) {
+    /// Penalty for a king castled behind a fianchetto (knight pawn on the
+    /// third rank) whose bishop is gone. The squares next to the knight pawn
+    /// can't be covered by pawns anymore, only a bishop of their color kept
+    /// them safe. Only applies if the opponent attacks one of them or has a
+    /// bishop which could.
+    fn fianchetto_holes_for_side(&mut self, pos: &Position, white: bool) -> EScore {
         let side = white as usize;
-        self.pst[side] -= pst(&PST[mov.piece.index()], white, mov.from);
+        let king = pos.king_sq(white);
+        let back_rank = if white { 0 } else { 7 };
+        if king.rank() != back_rank || (king.file() > 2 && king.file() < 5) {
+            return S(0, 0);
+        }
 
-        if let Some(promoted) = mov.promoted {
-            self.material[side][Piece::Pawn.index()] -= 1;
-            self.material[side][promoted.index()] += 1;
-            self.pst[side] += pst(&PST[promoted.index()], white, mov.to);
+        let knight_file = if king.file() < 3 { 1 } else { 6 };
+        let third_rank = if white { 2 } else { 5 };
+        let pawn = Square::file_rank(knight_file, third_rank).to_bb();
+        if (pos.pieces_of(Piece::Pawn, white) & pawn).is_empty() {
+            return S(0, 0);
+        }
+
+        let holes = pawn.left(1) | pawn.right(1);
+        let hole_color = if (holes & LIGHT_SQUARES).at_least_one() {
+            LIGHT_SQUARES
         } else {
-            self.pst[side] += pst(&PST[mov.piece.index()], white, mov.to);
+            !LIGHT_SQUARES
+        };
+        if (pos.pieces_of(Piece::Bishop, white) & hole_color).at_least_one() {
+            return S(0, 0);
         }
 
-        if let Some(captured) = mov.captured {
-            self.material[1 - side][captured.index()] -= 1;
-            if mov.en_passant {
-                self.pst[1 - side] -=
-                    pst(&PST[Piece::Pawn.index()], !white, mov.to.backward(white, 1));
-            } else {
-                self.pst[1 - side] -= pst(&PST[captured.index()], !white, mov.to);
-            }
+        let targeted = (holes & self.attacked_by_1[1 - side]).at_least_one()
+            || (pos.pieces_of(Piece::Bishop, !white) & hole_color).at_least_one();
+        if !targeted {
+            return S(0, 0);
         }
 
-        if mov.piece == Piece::King {
-            if mov.is_kingside_castle() {
-                self.pst[side] -= pst(&PST[Piece::Rook.index()], white, mov.to.right(1));
-                self.pst[side] += pst(&PST[Piece::Rook.index()], white, mov.to.left(1));
-            } else if mov.is_queenside_castle() {
-                self.pst[side] -= pst(&PST[Piece::Rook.index()], white, mov.to.left(2));
-                self.pst[side] += pst(&PST[Piece::Rook.index()], white, mov.to.right(1));
-            }
+        #[cfg(feature = "tune")]
+        {
+            self.trace.fianchetto_holes[side] = 1;
         }
+
+        FIANCHETTO_HOLES_PENALTY
     }
 
-    pub fn unmake_move(&mut self, mov: Move, unmaking_white_move: bool) {
-        let side = unmaking_white_move as usize;
+    /// Penalty for developing the queen while at least two minor pieces are
+    /// still on their starting squares.
+    fn early_queen_for_side(&mut self, pos: &Position, white: bool) -> EScore {
+        let back_rank = if white { RANK_1 } else { RANK_8 };
+        let queen_home = back_rank & FILES[3];
+        let minor_homes = back_rank & (FILES[1] | FILES[2] | FILES[5] | FILES[6]);
 
-        self.pst[side] += pst(&PST[mov.piece.index()], unmaking_white_move, mov.from);
+        let queens = pos.pieces_of(Piece::Queen, white);
+        if queens.is_empty() || (queens & queen_home).at_least_one() {
+            return S(0, 0);
+        }
 
-        if let Some(captured) = mov.captured {
-            self.material[1 - side][captured.index()] += 1;
-            if mov.en_passant {
-                self.pst[1 - side] += pst(
-                    &PST[Piece::Pawn.index()],
-                    !unmaking_white_move,
-                    mov.to.backward(unmaking_white_move, 1),
-                );
-            } else {
-                self.pst[1 - side] += pst(&PST[captured.index()], !unmaking_white_move, mov.to);
-            }
+        let undeveloped =
+            (pos.pieces_of(Piece::Knight, white) & (FILES[1] | FILES[6]) & minor_homes)
+                | (pos.pieces_of(Piece::Bishop, white) & (FILES[2] | FILES[5]) & minor_homes);
+        if !undeveloped.more_than_one() {
+            return S(0, 0);
         }
 
-        if let Some(promoted) = mov.promoted {
-            self.material[side][Piece::Pawn.index()] += 1;
-            self.material[side][promoted.index()] -= 1;
-            self.pst[side] -= pst(&PST[promoted.index()], unmaking_white_move, mov.to);
-        } else {
-            self.pst[side] -= pst(&PST[mov.piece.index()], unmaking_white_move, mov.to);
-        }
-
-        if mov.piece == Piece::King {
-            if mov.is_kingside_castle() {
-                self.pst[side] += pst(
-                    &PST[Piece::Rook.index()],
-                    unmaking_white_move,
-                    mov.to.right(1),
-                );
-                self.pst[side] -= pst(
-                    &PST[Piece::Rook.index()],
-                    unmaking_white_move,
-                    mov.to.left(1),
-                );
-            } else if mov.is_queenside_castle() {
-                self.pst[side] += pst(
-                    &PST[Piece::Rook.index()],
-                    unmaking_white_move,
-                    mov.to.left(2),
-                );
-                self.pst[side] -= pst(
-                    &PST[Piece::Rook.index()],
-                    unmaking_white_move,
-                    mov.to.right(1),
-                );
-            }
+        #[cfg(feature = "tune")]
+        {
+            self.trace.early_queen[white as usize] = 1;
         }
+
+        EARLY_QUEEN_PENALTY
     }
 
-    pub fn is_material_draw(&self) -> bool {
-        let material = &self.material;
-        let pawn = Piece::Pawn.index();
-        let knight = Piece::Knight.index();
-        let bishop = Piece::Bishop.index();
-        let rook = Piece::Rook.index();
-        let queen = Piece::Queen.index();
+    /// Adjusts passed pawns by the state of the squares in front of them and
+    /// the distance of both kings to the promotion square. Relies on the
+    /// attack maps built by `mobility_for_side` and on the other pieces, which
+    /// is why it is not part of the cached pawn evaluation: the pawn table is
+    /// keyed by the pawns alone and must not hold anything that changes when a
+    /// king or piece moves.
+    fn passed_path_for_side(&mut self, pos: &Position, white: bool) -> EScore {
+        let side = white as usize;
+        let us = pos.us(white);
+        let them = pos.them(white);
 
-        for side_mat in material.iter() {
-            if side_mat[pawn] > 0 || side_mat[rook] > 0 || side_mat[queen] > 0 {
-                return false;
+        let mut score = S(0, 0);
+
+        for pawn in (pos.pawns() & us).squares() {
+            let path = PAWN_CORRIDOR[side][pawn] & FILES[pawn.file() as usize];
+            let passed = (PAWN_CORRIDOR[side][pawn] & pos.pawns() & them).is_empty()
+                && (path & pos.pawns() & us).is_empty();
+            if !passed {
+                continue;
             }
-        }
 
-        for side in 0..2 {
-            if material[side][bishop] == 0 && material[side][knight] == 0 {
-                if material[1 - side][bishop] == 0 && material[1 - side][knight] < 3 {
-                    return true;
+            if them & pawn.forward(white, 1) {
+                score += PASSED_PAWN_BLOCKED;
+
+                #[cfg(feature = "tune")]
+                {
+                    self.trace.passed_blocked[side] += 1;
                 }
+            }
+
+            let promotion_sq = Square::file_rank(pawn.file(), if white { 7 } else { 0 });
+            if promotion_sq.chebyshev_distance(pos.king_sq(white))
+                < promotion_sq.chebyshev_distance(pos.king_sq(!white))
+            {
+                score += PASSED_PAWN_KING_CLOSER;
 
-                return material[1 - side][bishop] == 0
-                    || material[1 - side][bishop] + material[1 - side][knight] <= 1;
+                #[cfg(feature = "tune")]
+                {
+                    self.trace.passed_king_closer[side] += 1;
+                }
+            }
+
+            if (path & (pos.all_pieces | self.attacked_by_1[1 - side])).at_least_one() {
+                score += PASSED_PAWN_CONTESTED_PATH;
+
+                #[cfg(feature = "tune")]
+                {
+                    self.trace.passed_contested_path[side] += 1;
+                }
+            } else if (path & !self.attacked_by_1[side]).is_empty() {
+                score += PASSED_PAWN_DEFENDED_PATH;
+
+                #[cfg(feature = "tune")]
+                {
+                    self.trace.passed_defended_path[side] += 1;
+                }
             }
         }
 
-        false
+        score
     }
 
-    pub fn non_pawn_material(&self, white: bool) -> Score {
-        let mut material = 0;
-        let side = white as usize;
-        material += 3 * self.material[side][Piece::Knight.index()] as Score;
-        material += 3 * self.material[side][Piece::Bishop.index()] as Score;
-        material += 5 * self.material[side][Piece::Rook.index()] as Score;
-        material += 9 * self.material[side][Piece::Queen.index()] as Score;
-        material
+    /// Bonus for the side holding the opposition in pawn endgames, i.e. the
+    /// kings are two squares apart on a file, rank or diagonal and the other
+    /// side has to move. Returned from white's point of view.
+    fn opposition(&mut self, pos: &Position) -> EScore {
+        if self.non_pawn_material(true) > 0 || self.non_pawn_material(false) > 0 {
+            return S(0, 0);
+        }
+
+        let white_king = pos.king_sq(true);
+        let black_king = pos.king_sq(false);
+        let file_distance = (white_king.file() as i8 - black_king.file() as i8).abs();
+        let rank_distance = (white_king.rank() as i8 - black_king.rank() as i8).abs();
+
+        let opposition = white_king.chebyshev_distance(black_king) == 2
+            && (file_distance == 0 || rank_distance == 0 || file_distance == rank_distance);
+        if !opposition {
+            return S(0, 0);
+        }
+
+        // The side which just moved holds the opposition.
+        let holder = !pos.white_to_move;
+
+        #[cfg(feature = "tune")]
+        {
+            self.trace.opposition[holder as usize] = 1;
+        }
+
+        if holder {
+            KING_OPPOSITION
+        } else {
+            -KING_OPPOSITION
+        }
+    }
+
+    fn endgame_scale_factor(&mut self, pos: &Position, score: i32, known: Option<i32>) -> i32 {
+        let sf = if let Some(sf) = known {
+            sf
+        } else if self.material[(score > 0) as usize][Piece::Pawn.index()] == 0 {
+            SF_PAWNLESS
+        } else if self.only_opposite_colored_bishops(pos) {
+            SF_OPPOSITE_BISHOPS
+        } else {
+            SF_NORMAL
+        };
+
+        #[cfg(feature = "tune")]
+        {
+            self.trace.sf = sf as i8;
+        }
+
+        sf
     }
 
     #[cfg(feature = "tune")]
@@ -926,14 +1541,58 @@ impl<'p> From<&'p Position> for Eval {
                     (pos.white_pieces() & pos.queens()).popcount() as u8,
                 ],
             ],
-            pst: init_pst_score(pos),
-            pawn_table: [PawnHashEntry::default(); PAWN_TABLE_NUM_ENTRIES],
-            attacked_by: [[Bitboard::from(0); 6]; 2],
-            attacked_by_1: [Bitboard::from(0); 2],
-            attacked_by_2: [Bitboard::from(0); 2],
+            pst: PstAccumulator::from(pos),
+        }
+    }
+}
 
-            #[cfg(feature = "tune")]
-            trace: Trace::default(),
+/// Keeps the material counts and piece-square table scores up to date while moves are made.
+impl FeatureAccumulator for Eval {
+    fn add_piece(&mut self, piece: Piece, white: bool, sq: Square) {
+        if piece != Piece::King {
+            self.material[white as usize][piece.index()] += 1;
+        }
+        self.pst.add_piece(piece, white, sq);
+    }
+
+    fn remove_piece(&mut self, piece: Piece, white: bool, sq: Square) {
+        if piece != Piece::King {
+            self.material[white as usize][piece.index()] -= 1;
+        }
+        self.pst.remove_piece(piece, white, sq);
+    }
+
+    fn move_piece(&mut self, piece: Piece, white: bool, from: Square, to: Square) {
+        self.pst.move_piece(piece, white, from, to);
+    }
+}
+
+/// The piece-square table scores of both sides, updated incrementally.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PstAccumulator {
+    scores: [EScore; 2],
+}
+
+impl PstAccumulator {
+    pub fn score(&self, white: bool) -> EScore {
+        self.scores[white as usize]
+    }
+}
+
+impl FeatureAccumulator for PstAccumulator {
+    fn add_piece(&mut self, piece: Piece, white: bool, sq: Square) {
+        self.scores[white as usize] += pst(&PST[piece.index()], white, sq);
+    }
+
+    fn remove_piece(&mut self, piece: Piece, white: bool, sq: Square) {
+        self.scores[white as usize] -= pst(&PST[piece.index()], white, sq);
+    }
+}
+
+impl<'p> From<&'p Position> for PstAccumulator {
+    fn from(pos: &'p Position) -> PstAccumulator {
+        PstAccumulator {
+            scores: init_pst_score(pos),
         }
     }
 }
@@ -994,6 +1653,12 @@ fn init_pst_score(pos: &Position) -> [EScore; 2] {
     [black, white]
 }
 
+/// Occupancy used for rook-like mobility of the given side: our own rooks and
+/// queens are transparent.
+fn rook_blockers(pos: &Position, white: bool) -> Bitboard {
+    pos.all_pieces & !((pos.rooks() | pos.queens()) & pos.us(white))
+}
+
 pub const PST: &[SquareMap<EScore>] = &[
     PAWN_PST, KNIGHT_PST, BISHOP_PST, ROOK_PST, QUEEN_PST, KING_PST,
 ];
@@ -1009,6 +1674,8 @@ pub fn pst(pst: &SquareMap<EScore>, from_white_perspective: bool, sq: Square) ->
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::format;
+    use alloc::vec::Vec;
 
     #[test]
     fn test_escore() {
@@ -1022,6 +1689,559 @@ mod tests {
         assert_eq!(eg(S(-1, -1)), -1);
     }
 
+    #[test]
+    fn queen_near_enemy_king_increases_tropism() {
+        crate::magic::initialize_magics();
+
+        let far = Position::from("6k1/5ppp/8/8/8/8/5PPP/Q5K1 w - - 0 1");
+        let near = Position::from("6k1/5ppp/8/5Q2/8/8/5PPP/6K1 w - - 0 1");
+
+        let far_tropism = Evaluation::new(&Eval::from(&far)).tropism_for_side(&far, true);
+        let near_tropism = Evaluation::new(&Eval::from(&near)).tropism_for_side(&near, true);
+        assert!(mg(near_tropism) > mg(far_tropism));
+        assert_eq!(eg(near_tropism), 0);
+    }
+
+    #[test]
+    fn material_balance_tracks_captures() {
+        crate::magic::initialize_magics();
+
+        let mut pos = Position::from("4k3/8/3p4/8/4N3/8/8/4K3 w - - 0 1");
+        let mut eval = Eval::from(&pos);
+        let before = eval.material_balance(true);
+        assert_eq!(before, -eval.material_balance(false));
+
+        let mov = Move::from_algebraic(&pos, "e4d6");
+        assert_eq!(mov.captured, Some(Piece::Pawn));
+
+        let details = pos.details;
+        pos.make_move_with(mov, &mut eval);
+        assert!(eval.material_balance(true) > before);
+        assert_eq!(
+            eval.material_balance(true),
+            Eval::from(&pos).material_balance(true)
+        );
+
+        pos.unmake_move_with(mov, details, &mut eval);
+        assert_eq!(eval.material_balance(true), before);
+    }
+
+    #[test]
+    fn chess960_castling_updates_pst() {
+        crate::magic::initialize_magics();
+
+        let mut pos = Position::from("1r4kr/pppppppp/8/8/8/8/PPPPPPPP/1R4KR w HBhb - 0 1");
+        let mut eval = Eval::from(&pos);
+        let before = eval.pst.clone();
+
+        let mov = pos.castling_move(false);
+        let details = pos.details;
+        pos.make_move_with(mov, &mut eval);
+        assert_eq!(eval.pst, Eval::from(&pos).pst);
+
+        pos.unmake_move_with(mov, details, &mut eval);
+        assert_eq!(eval.pst, before);
+    }
+
+    #[test]
+    fn pst_accumulator_matches_recomputed_scores() {
+        crate::magic::initialize_magics();
+
+        let mut pos =
+            Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let mut accumulator = PstAccumulator::from(&pos);
+        let mut played = Vec::new();
+
+        // Walk through captures, castling and promotions by picking a
+        // different legal move at every ply.
+        for i in 0..40 {
+            let moves = MoveGenerator::from(&pos).legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+
+            let mov = moves[(7 * i) % moves.len()];
+            played.push((mov, pos.details));
+            pos.make_move_with(mov, &mut accumulator);
+            assert_eq!(accumulator, PstAccumulator::from(&pos));
+            assert_eq!(accumulator.scores, init_pst_score(&pos));
+        }
+
+        while let Some((mov, details)) = played.pop() {
+            pos.unmake_move_with(mov, details, &mut accumulator);
+            assert_eq!(accumulator, PstAccumulator::from(&pos));
+        }
+    }
+
+    #[test]
+    fn material_table_knows_draws_and_won_endgames() {
+        crate::magic::initialize_magics();
+
+        let probe = |fen: &str| {
+            let pos = Position::from(fen);
+            let mut hasher = Hasher::new();
+            hasher.from_position(&pos);
+            let material_hash = hasher.get_material_hash();
+
+            let eval = Eval::from(&pos);
+            let mut cache = EvalCache::default();
+            assert_eq!(cache.material.probe_material(material_hash), None);
+            let score =
+                eval.score_with_cache(&pos, hasher.get_pawn_hash(), material_hash, &mut cache);
+            let entry = cache.material.probe_material(material_hash).unwrap();
+            (score, entry)
+        };
+
+        let (score, entry) = probe("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1");
+        assert!(entry.draw);
+        assert_eq!(score, 0);
+
+        for fen in &[
+            "4k3/8/8/8/3N4/8/8/2B1K3 w - - 0 1",
+            "4k3/8/8/8/3q4/8/8/1R2K3 w - - 0 1",
+        ] {
+            let (_, entry) = probe(fen);
+            assert!(!entry.draw);
+            assert_eq!(entry.scale_factor, Some(SF_NORMAL), "{}", fen);
+        }
+
+        let (_, entry) = probe("4k3/8/8/8/3N4/8/8/1R2K3 w - - 0 1");
+        assert_eq!(entry.scale_factor, None);
+    }
+
+    #[test]
+    fn bare_kings_are_a_draw_with_fresh_caches() {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        let mut hasher = Hasher::new();
+        hasher.from_position(&pos);
+        assert_eq!(hasher.get_material_hash(), 0);
+
+        let eval = Eval::from(&pos);
+        assert!(eval.is_material_draw(&pos));
+        let score = eval.score_uncached(&pos, hasher.get_pawn_hash(), hasher.get_material_hash());
+        assert_eq!(score, 0);
+    }
+
+    #[test]
+    fn opposite_colored_bishops_are_drawish() {
+        crate::magic::initialize_magics();
+
+        let score = |fen: &str| {
+            let pos = Position::from(fen);
+            let mut hasher = Hasher::new();
+            hasher.from_position(&pos);
+            let eval = Eval::from(&pos);
+            let score =
+                eval.score_uncached(&pos, hasher.get_pawn_hash(), hasher.get_material_hash());
+            (score, eval.only_opposite_colored_bishops(&pos))
+        };
+
+        // White is a pawn up in both, the black bishop is on f7 (light) or
+        // e7 (dark) while the white one stands on c1 (dark).
+        let (opposite, is_opposite) = score("4k3/pp3b2/8/8/8/8/PPP5/2B1K3 w - - 0 1");
+        let (same, is_same) = score("4k3/pp2b3/8/8/8/8/PPP5/2B1K3 w - - 0 1");
+        assert!(is_opposite);
+        assert!(!is_same);
+        assert!(opposite > 0);
+        assert!(opposite < same, "{} vs {}", opposite, same);
+
+        // Any other piece makes it a normal endgame again.
+        let (_, with_knight) = score("4k3/pp3b2/8/8/8/8/PPP5/2B1KN2 w - - 0 1");
+        assert!(!with_knight);
+    }
+
+    #[test]
+    fn side_to_move_gets_the_tempo_bonus() {
+        crate::magic::initialize_magics();
+
+        let score = |fen: &str| {
+            let pos = Position::from(fen);
+            let mut hasher = Hasher::new();
+            hasher.from_position(&pos);
+            Eval::from(&pos).score_uncached(
+                &pos,
+                hasher.get_pawn_hash(),
+                hasher.get_material_hash(),
+            )
+        };
+
+        // With all pieces on the board only the middlegame part counts, so
+        // no rounding gets in the way.
+        for board in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R",
+        ] {
+            let white = score(&format!("{} w - - 0 1", board));
+            let black = score(&format!("{} b - - 0 1", board));
+            assert_eq!(white + black, 2 * mg(TEMPO_SCORE));
+        }
+    }
+
+    #[test]
+    fn side_holding_the_opposition_scores_better() {
+        crate::magic::initialize_magics();
+
+        let holding = Position::from("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1");
+        let not_holding = Position::from("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1");
+        assert_eq!(
+            Evaluation::new(&Eval::from(&holding)).opposition(&holding),
+            KING_OPPOSITION
+        );
+        assert_eq!(
+            Evaluation::new(&Eval::from(&not_holding)).opposition(&not_holding),
+            -KING_OPPOSITION
+        );
+
+        let diagonal = Position::from("8/8/2k5/8/4K3/8/8/8 b - - 0 1");
+        assert_eq!(
+            Evaluation::new(&Eval::from(&diagonal)).opposition(&diagonal),
+            KING_OPPOSITION
+        );
+
+        let with_pieces = Position::from("4k3/8/4K3/4P3/8/8/8/7N b - - 0 1");
+        assert_eq!(
+            Evaluation::new(&Eval::from(&with_pieces)).opposition(&with_pieces),
+            S(0, 0)
+        );
+    }
+
+    #[test]
+    fn queenside_majority_is_an_endgame_asset() {
+        crate::magic::initialize_magics();
+
+        let white_pawns = |fen: &str| {
+            let pos = Position::from(fen);
+            Evaluation::new(&Eval::from(&pos)).pawns_for_side(&pos, true)
+        };
+
+        // Three against two on the queenside, the kingside is balanced.
+        let majority = white_pawns("4k3/pp3ppp/8/8/8/8/PPP2PPP/4K3 w - - 0 1");
+        let balanced = white_pawns("4k3/ppp2ppp/8/8/8/8/PPP2PPP/4K3 w - - 0 1");
+
+        assert_eq!(majority - balanced, PAWN_MAJORITY_BONUS);
+        assert!(eg(PAWN_MAJORITY_BONUS) > mg(PAWN_MAJORITY_BONUS));
+    }
+
+    #[test]
+    fn king_without_flight_squares_is_in_more_danger() {
+        crate::magic::initialize_magics();
+
+        let king_safety = |fen: &str| {
+            let pos = Position::from(fen);
+            let eval = Eval::from(&pos);
+            let mut eval = Evaluation::new(&eval);
+            eval.mobility_for_side(&pos, true);
+            eval.mobility_for_side(&pos, false);
+            eval.king_safety_for_side(&pos, true)
+        };
+
+        // Same pawn shelter and attackers, but in the second position the
+        // king is boxed in by its own pieces.
+        let free = king_safety("q5k1/p7/8/8/8/5PPP/6K1/8 w - - 0 1");
+        let boxed_in = king_safety("q5k1/p7/8/8/8/5PPP/5NKN/5RBR w - - 0 1");
+
+        assert!(mg(boxed_in) < mg(free) - 20);
+        assert_eq!(eg(boxed_in), eg(free));
+    }
+
+    #[test]
+    fn close_attackers_are_more_dangerous() {
+        crate::magic::initialize_magics();
+
+        let proximity = |fen: &str| {
+            let pos = Position::from(fen);
+            let eval = Eval::from(&pos);
+            let mut eval = Evaluation::new(&eval);
+            eval.mobility_for_side(&pos, false);
+            eval.king_attacker_proximity(true)
+        };
+
+        // The knight on e3 hits f1 and g2, the bishop on a7 hits f2 from
+        // across the board.
+        let knight = "7k/8/8/q7/8/4n3/5PPP/6K1 w - - 0 1";
+        let bishop = "7k/b7/8/q7/8/8/5PPP/6K1 w - - 0 1";
+        let neither = "7k/8/8/q7/8/8/5PPP/6K1 w - - 0 1";
+        assert_eq!(proximity(neither), S(0, 0));
+        assert!(mg(proximity(knight)) < mg(proximity(bishop)));
+        assert!(mg(proximity(bishop)) < 0);
+    }
+
+    #[test]
+    fn missing_fianchetto_bishop_leaves_holes() {
+        crate::magic::initialize_magics();
+
+        let holes = |fen: &str| {
+            let pos = Position::from(fen);
+            let eval = Eval::from(&pos);
+            let mut eval = Evaluation::new(&eval);
+            eval.mobility_for_side(&pos, true);
+            eval.mobility_for_side(&pos, false);
+            eval.fianchetto_holes_for_side(&pos, true)
+        };
+
+        let with_bishop = "r1bqkb1r/pppp1ppp/2n2n2/4p3/8/6P1/PPPPPPBP/RNBQ1RK1 w kq - 0 1";
+        let without_bishop = "r1bqkb1r/pppp1ppp/2n2n2/4p3/8/6P1/PPPPPP1P/RNBQ1RK1 w kq - 0 1";
+        assert_eq!(holes(with_bishop), S(0, 0));
+        assert_eq!(holes(without_bishop), FIANCHETTO_HOLES_PENALTY);
+
+        // Nothing can reach the light squares once black's light-squared
+        // bishop is gone as well.
+        let no_light_bishops = "r2qkb1r/pppp1ppp/2n2n2/4p3/8/6P1/PPPPPP1P/RNBQ1RK1 w kq - 0 1";
+        assert_eq!(holes(no_light_bishops), S(0, 0));
+
+        // Without the fianchetto there are no holes.
+        let unmoved = "r1bqkb1r/pppp1ppp/2n2n2/4p3/8/8/PPPPPP1P/RNBQ1RK1 w kq - 0 1";
+        assert_eq!(holes(unmoved), S(0, 0));
+    }
+
+    #[test]
+    fn king_stuck_in_the_center_is_penalized() {
+        crate::magic::initialize_magics();
+
+        let castled = Position::from("4k3/8/8/8/8/8/5PPP/5RK1 w - - 0 1");
+        let stuck = Position::from("4k3/8/8/8/8/8/5PPP/4K2R w - - 0 1");
+        let can_castle = Position::from("4k3/8/8/8/8/8/5PPP/4K2R w K - 0 1");
+
+        assert_eq!(
+            Evaluation::new(&Eval::from(&castled)).stuck_king_for_side(&castled, true),
+            S(0, 0)
+        );
+        assert_eq!(
+            Evaluation::new(&Eval::from(&stuck)).stuck_king_for_side(&stuck, true),
+            KING_STUCK_PENALTY
+        );
+        assert_eq!(
+            Evaluation::new(&Eval::from(&can_castle)).stuck_king_for_side(&can_castle, true),
+            S(0, 0)
+        );
+    }
+
+    #[test]
+    fn early_queen_sortie_is_penalized() {
+        crate::magic::initialize_magics();
+
+        let home = Position::from("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2");
+        let sortie =
+            Position::from("rnbqkbnr/pppp1ppp/8/4p2Q/4P3/8/PPPP1PPP/RNB1KBNR b KQkq - 1 2");
+        let developed = Position::from(
+            "r1bqk2r/pppp1ppp/2n2n2/2b1p2Q/2B1P3/2N2N2/PPPP1PPP/R1B1K2R b KQkq - 1 2",
+        );
+
+        assert_eq!(
+            Evaluation::new(&Eval::from(&home)).early_queen_for_side(&home, true),
+            S(0, 0)
+        );
+        assert_eq!(
+            Evaluation::new(&Eval::from(&sortie)).early_queen_for_side(&sortie, true),
+            EARLY_QUEEN_PENALTY
+        );
+        assert_eq!(
+            Evaluation::new(&Eval::from(&developed)).early_queen_for_side(&developed, true),
+            S(0, 0)
+        );
+    }
+
+    #[test]
+    fn rook_on_enemy_king_file_gets_extra_bonus() {
+        crate::magic::initialize_magics();
+
+        let king_file = Position::from("4k3/pp3ppp/8/8/8/8/PP3PPP/4R1K1 w - - 0 1");
+        let other_file = Position::from("4k3/pp3ppp/8/8/8/8/PP3PPP/2R3K1 w - - 0 1");
+
+        let king_file_score =
+            Evaluation::new(&Eval::from(&king_file)).rooks_for_side(&king_file, true);
+        let other_file_score =
+            Evaluation::new(&Eval::from(&other_file)).rooks_for_side(&other_file, true);
+
+        assert_eq!(other_file_score, ROOK_OPEN_FILE);
+        assert_eq!(king_file_score, ROOK_OPEN_FILE + ROOK_KING_FILE);
+        assert!(mg(king_file_score) > mg(other_file_score));
+    }
+
+    #[test]
+    fn connected_passers_are_stronger() {
+        crate::magic::initialize_magics();
+
+        let connected = Position::from("4k3/8/3PP3/8/8/8/8/4K3 w - - 0 1");
+        let separated = Position::from("4k3/8/1P4P1/8/8/8/8/4K3 w - - 0 1");
+
+        let connected_score =
+            Evaluation::new(&Eval::from(&connected)).pawns_for_side(&connected, true);
+        let separated_score =
+            Evaluation::new(&Eval::from(&separated)).pawns_for_side(&separated, true);
+        assert!(eg(connected_score) > eg(separated_score));
+    }
+
+    #[test]
+    fn pawns_on_their_own_back_rank_dont_panic() {
+        crate::magic::initialize_magics();
+
+        // A FEN can't have pawns there, so turn the bishops into pawns
+        let mut pos = Position::from("b3k3/8/8/8/8/8/8/B3K3 w - - 0 1");
+        let bishops = pos.bishops();
+        pos.bb[Piece::Bishop.index()] ^= bishops;
+        pos.bb[Piece::Pawn.index()] ^= bishops;
+
+        let mut hasher = Hasher::new();
+        hasher.from_position(&pos);
+        let eval = Eval::from(&pos);
+        eval.score_uncached(&pos, hasher.get_pawn_hash(), hasher.get_material_hash());
+        Evaluation::new(&eval).pawns_for_side(&pos, true);
+        Evaluation::new(&eval).pawns_for_side(&pos, false);
+    }
+
+    #[test]
+    fn knights_need_pawns() {
+        crate::magic::initialize_magics();
+
+        let knight_value = |with_knight: &str, without_knight: &str| {
+            let with_knight = Position::from(with_knight);
+            let without_knight = Position::from(without_knight);
+            Evaluation::new(&Eval::from(&with_knight)).material(&with_knight, true)
+                - Evaluation::new(&Eval::from(&without_knight)).material(&without_knight, true)
+        };
+
+        let closed = knight_value(
+            "4k3/ppppppp1/8/8/8/8/PPPPPPP1/4KN2 w - - 0 1",
+            "4k3/ppppppp1/8/8/8/8/PPPPPPP1/4K3 w - - 0 1",
+        );
+        let open = knight_value(
+            "4k3/pp6/8/8/8/8/PP6/4KN2 w - - 0 1",
+            "4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1",
+        );
+        assert!(mg(closed) > mg(open));
+        assert!(eg(closed) > eg(open));
+    }
+
+    #[test]
+    fn doubled_pawn_is_penalized_once() {
+        crate::magic::initialize_magics();
+
+        let doubled = Position::from("4k3/2pppp2/8/8/8/3P4/2PPP3/4K3 w - - 0 1");
+        let undoubled = Position::from("4k3/2pppp2/8/8/8/3P4/2P1PP2/4K3 w - - 0 1");
+
+        let doubled_score = Evaluation::new(&Eval::from(&doubled)).pawns_for_side(&doubled, true);
+        let undoubled_score =
+            Evaluation::new(&Eval::from(&undoubled)).pawns_for_side(&undoubled, true);
+        // The doubled pawn also gives white a queenside majority
+        assert_eq!(
+            doubled_score - undoubled_score,
+            DOUBLED_PAWN + PAWN_MAJORITY_BONUS
+        );
+    }
+
+    #[test]
+    fn doubled_pawn_penalty_is_tapered() {
+        crate::magic::initialize_magics();
+
+        // The undoubled structure has the same majorities and no extra
+        // isolated or passed pawns, so only the doubled pawn differs
+        let doubled_penalty = |pieces: &str| {
+            let fen = |pawns: &str| {
+                format!(
+                    "{}/2pppp2/8/8/8/3P4/{}/{} w - - 0 1",
+                    pieces.to_lowercase(),
+                    pawns,
+                    pieces
+                )
+            };
+            let doubled = Position::from(fen("2PPP3").as_ref());
+            let undoubled = Position::from(fen("1PP1P3").as_ref());
+
+            let eval = Eval::from(&doubled);
+            let difference = Evaluation::new(&eval).pawns_for_side(&doubled, true)
+                - Evaluation::new(&Eval::from(&undoubled)).pawns_for_side(&undoubled, true);
+            assert_eq!(difference, DOUBLED_PAWN);
+            interpolate(difference, eval.phase())
+        };
+
+        let middlegame = doubled_penalty("RNBQKBNR");
+        let endgame = doubled_penalty("4K3");
+        assert!(endgame < middlegame);
+        assert!(middlegame < 0);
+    }
+
+    #[test]
+    fn shared_eval_with_separate_caches() {
+        crate::magic::initialize_magics();
+
+        let mut hasher = Hasher::new();
+        let mut cache = EvalCache::default();
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+        ] {
+            let pos = Position::from(*fen);
+            hasher.from_position(&pos);
+            let pawn_hash = hasher.get_pawn_hash();
+            let material_hash = hasher.get_material_hash();
+
+            let eval = Eval::from(&pos);
+            let uncached = eval.score_uncached(&pos, pawn_hash, material_hash);
+            assert_eq!(
+                eval.score_with_cache(&pos, pawn_hash, material_hash, &mut cache),
+                uncached
+            );
+            // Second lookup is served from the cache.
+            assert_eq!(
+                eval.score_with_cache(&pos, pawn_hash, material_hash, &mut cache),
+                uncached
+            );
+        }
+    }
+
+    #[test]
+    fn doubled_rooks_see_through_each_other() {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from("4k3/8/8/R7/8/8/R7/4K3 w - - 0 1");
+        let blockers = rook_blockers(&pos, true);
+        for rook in (pos.rooks() & pos.white_pieces()).squares() {
+            let xray = get_rook_attacks_from(rook, blockers).popcount();
+            let blocked = get_rook_attacks_from(rook, pos.all_pieces).popcount();
+            assert!(xray > blocked);
+        }
+
+        // Enemy rooks still block.
+        let blockers = rook_blockers(&pos, false);
+        assert_eq!(blockers, pos.all_pieces);
+    }
+
+    #[test]
+    fn sliders_attack_through_the_enemy_king() {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from("8/8/8/4k3/8/8/8/3KR3 w - - 0 1");
+        let eval = Eval::from(&pos);
+        let mut eval = Evaluation::new(&eval);
+        eval.mobility_for_side(&pos, true);
+
+        let behind_king = Square::file_rank(4, 5);
+        assert!(eval.attacked_by[1][Piece::Rook.index()] & behind_king);
+    }
+
+    #[test]
+    fn knight_mobility_excludes_own_pieces() {
+        crate::magic::initialize_magics();
+
+        // The knight on a1 reaches b3 and c2, unless our own king stands on c2.
+        let free = Position::from("4k3/8/8/8/8/8/8/N3K3 w - - 0 1");
+        let cramped = Position::from("4k3/8/8/8/8/8/2K5/N7 w - - 0 1");
+        let free_score = Evaluation::new(&Eval::from(&free)).mobility_for_side(&free, true);
+        let cramped_score =
+            Evaluation::new(&Eval::from(&cramped)).mobility_for_side(&cramped, true);
+        assert_eq!(free_score, KNIGHT_MOBILITY[2]);
+        assert_eq!(cramped_score, KNIGHT_MOBILITY[1]);
+        assert!(cramped_score < free_score);
+
+        for sq in 0..64 {
+            assert!(KNIGHT_ATTACKS[Square::from(sq)].popcount() < KNIGHT_MOBILITY.len());
+        }
+    }
+
     #[test]
     fn test_escore_calculus() {
         assert_eq!(S(1, 2) + S(3, 4), S(4, 6));
@@ -1029,4 +2249,249 @@ mod tests {
         assert_eq!(S(3, 4) - S(1, 2), S(2, 2));
         assert_eq!(S(3, 0) - S(1, 2), S(2, -2));
     }
+
+    #[test]
+    fn search_bounds_do_not_overflow() {
+        assert_eq!(-NEG_INFINITY, INFINITY);
+        assert!(INFINITY.checked_add(1).is_some());
+        assert!(NEG_INFINITY.checked_sub(1).is_some());
+        assert_eq!(bounded_add(MATE_SCORE, 1), MATE_SCORE + 1);
+
+        assert_eq!(bounded_add(INFINITY, 1), INFINITY);
+        assert_eq!(bounded_add(NEG_INFINITY, -1), NEG_INFINITY);
+        assert_eq!(bounded_add(MATE_SCORE, MATE_SCORE), INFINITY);
+        assert_eq!(bounded_add(-MATE_SCORE, -MATE_SCORE), NEG_INFINITY);
+        assert_eq!(bounded_add(-bounded_add(INFINITY, 1), 1), NEG_INFINITY + 1);
+        assert_eq!(bounded_add(100, -30), 70);
+    }
+
+    #[test]
+    fn bishop_pair_is_worth_more_in_open_positions() {
+        crate::magic::initialize_magics();
+
+        let pair_bonus = |pair: &str, single: &str| {
+            let pair = Position::from(pair);
+            let single = Position::from(single);
+            Evaluation::new(&Eval::from(&pair)).material(&pair, true)
+                - Evaluation::new(&Eval::from(&single)).material(&single, true)
+                - BISHOP_SCORE
+        };
+
+        let open = pair_bonus(
+            "4k3/ppp5/8/8/8/8/PPP5/2B1KB2 w - - 0 1",
+            "4k3/ppp5/8/8/8/8/PPP5/2B1K3 w - - 0 1",
+        );
+        let closed = pair_bonus(
+            "4k3/ppppppp1/8/8/8/8/PPPPPPP1/2B1KB2 w - - 0 1",
+            "4k3/ppppppp1/8/8/8/8/PPPPPPP1/2B1K3 w - - 0 1",
+        );
+
+        assert!(mg(open) > mg(closed));
+        assert!(eg(open) > eg(closed));
+    }
+
+    #[test]
+    fn passer_with_defended_path_beats_attacked_path() {
+        crate::magic::initialize_magics();
+
+        let passed_path = |fen: &str| {
+            let pos = Position::from(fen);
+            let eval = Eval::from(&pos);
+            let mut eval = Evaluation::new(&eval);
+            eval.mobility_for_side(&pos, true);
+            eval.mobility_for_side(&pos, false);
+            eval.passed_path_for_side(&pos, true)
+        };
+
+        // The king on d7 covers e6, e7 and e8. Only the knight differs.
+        let defended = passed_path("8/3K4/8/4P3/8/1n6/8/k7 w - - 0 1");
+        let attacked = passed_path("8/3K4/8/4P3/5n2/8/8/k7 w - - 0 1");
+
+        // Our king is also the closer one to the promotion square
+        assert_eq!(
+            defended,
+            PASSED_PAWN_DEFENDED_PATH + PASSED_PAWN_KING_CLOSER
+        );
+        assert_eq!(
+            attacked,
+            PASSED_PAWN_CONTESTED_PATH + PASSED_PAWN_KING_CLOSER
+        );
+        assert!(eg(defended) > eg(attacked));
+    }
+
+    #[test]
+    fn blockaded_passer_and_king_distance() {
+        crate::magic::initialize_magics();
+
+        let passed_path = |fen: &str| {
+            let pos = Position::from(fen);
+            let eval = Eval::from(&pos);
+            let mut eval = Evaluation::new(&eval);
+            eval.mobility_for_side(&pos, true);
+            eval.mobility_for_side(&pos, false);
+            eval.passed_path_for_side(&pos, true)
+        };
+
+        let far_king = passed_path("k7/8/8/4P3/8/8/8/4K3 w - - 0 1");
+        let near_king = passed_path("k7/8/8/3KP3/8/8/8/8 w - - 0 1");
+        let blockaded = passed_path("k7/8/4n3/4P3/8/8/8/4K3 w - - 0 1");
+
+        assert_eq!(near_king - far_king, PASSED_PAWN_KING_CLOSER);
+        assert!(eg(blockaded) < eg(far_king) + eg(PASSED_PAWN_BLOCKED) / 2);
+    }
+
+    #[test]
+    fn protected_passer_is_worth_more() {
+        let pawns = |fen: &str| {
+            let pos = Position::from(fen);
+            Evaluation::new(&Eval::from(&pos)).pawns_for_side(&pos, true)
+        };
+
+        // Moving the d pawn up also makes both passers connected
+        let protected = pawns("4k3/8/8/4P3/3P4/8/8/4K3 w - - 0 1");
+        let unprotected = pawns("4k3/8/8/4P3/8/3P4/8/4K3 w - - 0 1");
+        assert_eq!(
+            protected - unprotected,
+            PROTECTED_PASSED_PAWN[4]
+                + CONNECTED_PASSED_PAWN[4]
+                + CONNECTED_PASSED_PAWN[3]
+                + PASSED_PAWN_ON_RANK[3]
+                - PASSED_PAWN_ON_RANK[2]
+        );
+    }
+
+    #[test]
+    fn connected_rooks_get_a_bonus() {
+        crate::magic::initialize_magics();
+
+        let connected = Position::from("4k3/pppppppp/8/8/8/8/PPPPPPPP/R4RK1 w - - 0 1");
+        let separated = Position::from("4k3/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w - - 0 1");
+
+        let connected_score =
+            Evaluation::new(&Eval::from(&connected)).rooks_for_side(&connected, true);
+        let separated_score =
+            Evaluation::new(&Eval::from(&separated)).rooks_for_side(&separated, true);
+
+        assert_eq!(connected_score, CONNECTED_ROOKS_BONUS);
+        assert_eq!(separated_score, S(0, 0));
+        assert!(mg(connected_score) > mg(separated_score));
+    }
+
+    #[test]
+    fn rook_trapped_by_uncastled_king_is_penalized() {
+        crate::magic::initialize_magics();
+
+        let trapped = Position::from("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R4K1R w kq - 0 1");
+        let castled = Position::from("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R4RK1 w kq - 0 1");
+        let can_castle = Position::from("r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1");
+
+        let trapped_score = Evaluation::new(&Eval::from(&trapped)).rooks_for_side(&trapped, true);
+        let castled_score = Evaluation::new(&Eval::from(&castled)).rooks_for_side(&castled, true);
+        let can_castle_score =
+            Evaluation::new(&Eval::from(&can_castle)).rooks_for_side(&can_castle, true);
+
+        assert_eq!(trapped_score, TRAPPED_ROOK_PENALTY);
+        assert_eq!(can_castle_score, S(0, 0));
+        assert!(mg(trapped_score) < mg(castled_score));
+
+        // The same pattern from black's point of view, on the queenside
+        let black_trapped = Position::from("1rk4r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQ - 0 1");
+        let black_score =
+            Evaluation::new(&Eval::from(&black_trapped)).rooks_for_side(&black_trapped, false);
+        assert_eq!(black_score, TRAPPED_ROOK_PENALTY);
+    }
+
+    #[test]
+    fn king_prefers_the_center_in_queenless_endgames() {
+        crate::magic::initialize_magics();
+
+        let score = |fen: &str| {
+            let pos = Position::from(fen);
+            let mut hasher = Hasher::new();
+            hasher.from_position(&pos);
+            Eval::from(&pos).score_uncached(
+                &pos,
+                hasher.get_pawn_hash(),
+                hasher.get_material_hash(),
+            )
+        };
+
+        let centralized = score("7k/pp4pp/8/8/4K3/8/PP4PP/8 w - - 0 1");
+        for fen in &[
+            "7k/pp4pp/8/8/K7/8/PP4PP/8 w - - 0 1",
+            "7k/pp4pp/8/8/7K/8/PP4PP/8 w - - 0 1",
+            "7k/pp4pp/8/8/8/8/PP4PP/K7 w - - 0 1",
+            "7k/pp4pp/8/8/8/8/PP4PP/4K3 w - - 0 1",
+        ] {
+            assert!(centralized > score(fen), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn two_minors_against_rook_depend_on_the_phase() {
+        crate::magic::initialize_magics();
+
+        let balance = |fen| {
+            let pos = Position::from(fen);
+            let eval = Eval::from(&pos);
+            let mut eval = Evaluation::new(&eval);
+            assert!(eval.has_minors_for_rook(true));
+            assert!(!eval.has_minors_for_rook(false));
+            eval.material(&pos, true) - eval.material(&pos, false)
+        };
+
+        // Knight and bishop against rook and pawn
+        let minors = balance("r3k3/ppppp3/8/8/8/8/PPPP4/1N2KB2 w - - 0 1");
+        assert!(mg(minors) > 0);
+        assert!(eg(minors) < 0);
+
+        // With the queens still on the board it is the same trade
+        let minors = balance("r2qk3/ppppp3/8/8/8/8/PPPP4/1N1QKB2 w - - 0 1");
+        assert!(mg(minors) > 0);
+        assert!(eg(minors) < 0);
+
+        let applies = |fen| {
+            let pos = Position::from(fen);
+            let eval = Eval::from(&pos);
+            eval.has_minors_for_rook(true) || eval.has_minors_for_rook(false)
+        };
+        assert!(applies("1nb1k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+        assert!(!applies("1n2k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+        assert!(!applies("1nbqk3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+        assert!(!applies("r3k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn promoted_minors_are_not_over_credited() {
+        crate::magic::initialize_magics();
+
+        let material = |fen| {
+            let pos = Position::from(fen);
+            Evaluation::new(&Eval::from(&pos)).material(&pos, true)
+        };
+
+        // Three light-squared bishops are no bishop pair at all
+        let same_color = material("4k3/8/8/8/8/8/8/1B1BKB2 w - - 0 1");
+        assert_eq!(same_color, 3 * BISHOP_SCORE);
+
+        // With a dark-squared one among them the pair is counted once
+        let mixed = material("4k3/8/8/8/8/8/8/1BB1KB2 w - - 0 1");
+        assert_eq!(
+            mixed,
+            3 * BISHOP_SCORE + BISHOP_PAIR - 8 * BISHOP_PAIR_PAWNS
+        );
+
+        let draw = |fen| {
+            let pos = Position::from(fen);
+            Eval::from(&pos).is_material_draw(&pos)
+        };
+        assert!(draw("4k3/8/8/8/8/8/8/1N2K1N1 w - - 0 1"));
+        assert!(!draw("4k3/8/8/8/8/8/8/1NN1K1N1 w - - 0 1"));
+        assert!(!draw("1nn1k1n1/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(draw("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(draw("4k3/8/8/8/8/8/8/1B1BK3 w - - 0 1"));
+        assert!(draw("2b1k3/8/8/8/8/8/8/1B1BK3 w - - 0 1"));
+        assert!(!draw("4k3/8/8/8/8/8/8/1BB1K3 w - - 0 1"));
+        assert!(!draw("4k3/8/8/8/8/8/8/1B1BK1N1 w - - 0 1"));
+    }
 }
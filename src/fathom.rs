@@ -107,6 +107,10 @@ pub struct ProbeResult {
     pub best_move: Move,
 }
 
+/// # Safety
+///
+/// Fathom's tables are global and unsynchronized, so this must not run while
+/// anything else probes them.
 pub unsafe fn init<P: AsRef<Path>>(path: P) -> bool {
     let pathref = path.as_ref();
     let pathstr = match pathref.to_str() {
@@ -121,10 +125,16 @@ pub unsafe fn init<P: AsRef<Path>>(path: P) -> bool {
     return c::tb_init(c_string.as_ptr());
 }
 
+/// # Safety
+///
+/// Must not run concurrently with `init`.
 pub unsafe fn max_pieces() -> usize {
     c::TB_LARGEST as usize
 }
 
+/// # Safety
+///
+/// Must not run concurrently with `init`.
 pub unsafe fn probe_wdl(board: &BoardState) -> Option<Wdl> {
     let result = c::tb_probe_wdl_wrapper(
         board.white,
@@ -191,6 +201,9 @@ fn tb_get_dtz(res: u32) -> u32 {
     (res & TB_RESULT_DTZ_MASK) >> TB_RESULT_DTZ_SHIFT
 }
 
+/// # Safety
+///
+/// Must not run concurrently with `init`.
 pub unsafe fn probe_root(board: &BoardState) -> Option<ProbeResult> {
     let result = c::tb_probe_root_wrapper(
         board.white,
@@ -0,0 +1,225 @@
+/* Asymptote, a UCI chess engine
+   Copyright (C) 2018-2020  Maximilian Lupke
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use rand::{prelude::*, prng::ChaChaRng};
+
+use crate::eval::Eval;
+use crate::hash::Hasher;
+use crate::position::{Position, STARTING_POSITION};
+use crate::repetitions::Repetitions;
+use crate::search::{Ply, Search};
+use crate::search_controller::PersistentOptions;
+use crate::time::TimeControl;
+use crate::tt::TT;
+
+// Random plies played from the start position so that games differ
+const RANDOM_PLIES: usize = 8;
+// Positions this close to the start or the end of a game are not emitted
+const SKIP_PLIES: usize = 8;
+// Games still running after this many plies are adjudicated as draws
+const MAX_PLIES: usize = 400;
+const SEARCH_DEPTH: Ply = 6;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    fn as_str(self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        }
+    }
+}
+
+/// Returns the result if the game is over: checkmate, stalemate, the fifty
+/// moves rule, threefold repetition or a material draw.
+pub fn game_result(position: &Position, repetitions: &Repetitions) -> Option<GameResult> {
+    if position.legal_moves().is_empty() {
+        if !position.in_check() {
+            return Some(GameResult::Draw);
+        } else if position.white_to_move {
+            return Some(GameResult::BlackWins);
+        } else {
+            return Some(GameResult::WhiteWins);
+        }
+    }
+
+    if position.is_fifty_move_draw_with_moves(true)
+        || position.is_repetition(repetitions.positions(), 3)
+        || Eval::from(position).is_material_draw(position)
+    {
+        return Some(GameResult::Draw);
+    }
+
+    None
+}
+
+/// Plays a single game at a fixed depth after a few random opening moves.
+/// Returns the FENs of the positions worth labelling and the game result.
+fn play_game<R: Rng>(rng: &mut R, depth: Ply, max_plies: usize) -> (Vec<String>, GameResult) {
+    let seed = rng.gen::<u64>();
+    let mut position = STARTING_POSITION;
+    for _ in 0..RANDOM_PLIES {
+        let moves = position.legal_moves();
+        if moves.is_empty() {
+            break;
+        }
+        position.make_move(moves[rng.gen_range(0, moves.len())]);
+    }
+
+    let mut hasher = Hasher::new();
+    hasher.from_position(&position);
+    let mut repetitions = Repetitions::new(100);
+    repetitions.push_position(hasher.get_hash());
+    let mut tt = TT::new(16);
+    let tt = tt.share();
+
+    // `None` marks positions in check, which are never emitted
+    let mut fens = Vec::new();
+    let mut result = GameResult::Draw;
+    for _ in 0..max_plies {
+        if let Some(r) = game_result(&position, &repetitions) {
+            result = r;
+            break;
+        }
+
+        if position.in_check() {
+            fens.push(None);
+        } else {
+            fens.push(Some(position.to_fen()));
+        }
+
+        let mut search = Search::new(
+            Arc::new(AtomicBool::new(false)),
+            hasher.clone(),
+            PersistentOptions::default(),
+            position.clone(),
+            TimeControl::FixedDepth(depth),
+            &tt,
+            repetitions.clone(),
+        );
+        // Only the main thread prints UCI info lines
+        search.id = 1;
+        search.set_seed(seed);
+        search.prepare_search();
+        let mov = search.iterative_deepening();
+
+        hasher.make_move(&position, mov);
+        position.make_move(mov);
+        if position.details.halfmove == 0 {
+            repetitions.irreversible_move();
+        }
+        repetitions.push_position(hasher.get_hash());
+    }
+
+    let end = fens.len().saturating_sub(SKIP_PLIES);
+    let fens = fens
+        .into_iter()
+        .take(end)
+        .skip(SKIP_PLIES)
+        .flatten()
+        .collect();
+    (fens, result)
+}
+
+/// Writes `num_games` games as lines of `<fen> <result>`, the format read by
+/// the tuner. Returns the number of positions written.
+fn write_games<R: Rng, W: Write>(
+    rng: &mut R,
+    num_games: usize,
+    depth: Ply,
+    max_plies: usize,
+    out: &mut W,
+) -> io::Result<usize> {
+    let mut count = 0;
+    for _ in 0..num_games {
+        let (fens, result) = play_game(rng, depth, max_plies);
+        for fen in &fens {
+            writeln!(out, "{} {}", fen, result.as_str())?;
+        }
+        count += fens.len();
+    }
+    Ok(count)
+}
+
+/// Creates the generator for the random opening moves. Runs with the same
+/// seed play the same games.
+fn seeded_rng(seed: u64) -> ChaChaRng {
+    let mut bytes = [0; 32];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    ChaChaRng::from_seed(bytes)
+}
+
+pub fn generate_data(num_games: usize, out_file: &str, seed: Option<u64>) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(out_file)?);
+    let mut rng = seed.map_or_else(ChaChaRng::from_entropy, seeded_rng);
+    let count = write_games(&mut rng, num_games, SEARCH_DEPTH, MAX_PLIES, &mut out)?;
+    println!("Wrote {} positions from {} games", count, num_games);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_positions_are_labelled_and_legal() {
+        crate::magic::initialize_magics();
+
+        let mut rng = ChaChaRng::from_seed([7; 32]);
+        let mut out = Vec::new();
+        let count = write_games(&mut rng, 2, 2, 60, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(count > 0);
+        assert_eq!(out.lines().count(), count);
+        for line in out.lines() {
+            let split = line.rfind(' ').unwrap();
+            let (fen, result) = (&line[..split], &line[split + 1..]);
+            assert!(["1-0", "0-1", "1/2-1/2"].contains(&result), "{}", line);
+
+            let position = Position::try_from_fen(fen).unwrap();
+            assert_eq!(position.to_fen(), fen);
+            assert!(!position.in_check(), "{}", line);
+            assert!(!position.legal_moves().is_empty(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn seeded_games_are_reproducible() {
+        crate::magic::initialize_magics();
+
+        let games = |seed| {
+            let mut out = Vec::new();
+            write_games(&mut seeded_rng(seed), 2, 2, 60, &mut out).unwrap();
+            out
+        };
+
+        assert_eq!(games(42), games(42));
+        assert_ne!(games(42), games(43));
+    }
+}
@@ -31,9 +31,18 @@ pub struct Hasher {
     en_passant: [Hash; 8],
     castle: [Hash; 16],
     singular: Hash,
+    // Keys for the n-th piece of a type and color, kings excluded.
+    material: [[[Hash; 16]; 5]; 2],
 
     hash: Hash,
     pawn_hash: Hash,
+    material_hash: Hash,
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Hasher::new()
+    }
 }
 
 impl Hasher {
@@ -80,9 +89,11 @@ impl Hasher {
             en_passant: [0; 8],
             castle: [0; 16],
             singular: 0,
+            material: [[[0; 16]; 5]; 2],
 
             hash: 0,
             pawn_hash: 0,
+            material_hash: 0,
         };
 
         rng.fill(&mut hasher.color);
@@ -96,6 +107,9 @@ impl Hasher {
         rng.fill(&mut hasher.en_passant);
         rng.fill(&mut hasher.castle);
         hasher.singular = rng.gen();
+        for keys in hasher.material.iter_mut().flat_map(|side| side.iter_mut()) {
+            rng.fill(keys);
+        }
 
         hasher.from_position(&STARTING_POSITION);
 
@@ -110,9 +124,15 @@ impl Hasher {
         self.pawn_hash
     }
 
-    pub fn set(&mut self, hash: Hash, pawn_hash: Hash) {
+    /// Hash of the piece counts only, independent of where the pieces are.
+    pub fn get_material_hash(&self) -> Hash {
+        self.material_hash
+    }
+
+    pub fn set(&mut self, hash: Hash, pawn_hash: Hash, material_hash: Hash) {
         self.hash = hash;
         self.pawn_hash = pawn_hash;
+        self.material_hash = material_hash;
     }
 
     pub fn toggle_singular(&mut self, mov: Move) {
@@ -123,45 +143,65 @@ impl Hasher {
         self.hash ^= (to as Hash) << 8;
     }
 
-    pub fn from_position(&mut self, pos: &Position) {
-        self.hash = 0;
-        if pos.white_to_move {
-            self.hash ^= self.white_to_move;
+    /// Returns the key of a `piece` of the given color on `sq`.
+    pub fn piece_key(&self, piece: Piece, white: bool, sq: Square) -> Hash {
+        if white {
+            self.hashes[piece.index()][sq] ^ self.color[sq]
+        } else {
+            self.hashes[piece.index()][sq]
         }
+    }
 
-        if pos.details.en_passant != 255 {
-            self.hash ^= self.en_passant[pos.details.en_passant as usize];
-        }
+    /// Returns the key which is toggled in the material hash when a side gets
+    /// its `count + 1`-th piece of a type, or loses it again.
+    pub fn material_key(&self, piece: Piece, white: bool, count: u32) -> Hash {
+        self.material[white as usize][piece.index()][count as usize]
+    }
 
-        self.hash ^= self.castle[pos.details.castling as usize];
+    /// Returns the key for a set of castling rights.
+    pub fn castle_key(&self, rights: u8) -> Hash {
+        self.castle[rights as usize]
+    }
 
-        for sq in pos.white_pieces().squares() {
-            self.hash ^= self.color[sq];
-        }
+    /// Returns the key for an en passant square on `file`.
+    pub fn ep_key(&self, file: u8) -> Hash {
+        self.en_passant[file as usize]
+    }
 
-        for sq in pos.pawns().squares() {
-            self.hash ^= self.hashes[Piece::Pawn.index()][sq];
-            self.pawn_hash ^= self.hashes[Piece::Pawn.index()][sq];
-        }
+    /// Returns the key which is toggled when white is to move.
+    pub fn side_key(&self) -> Hash {
+        self.white_to_move
+    }
 
-        for sq in pos.knights().squares() {
-            self.hash ^= self.hashes[Piece::Knight.index()][sq];
+    pub fn from_position(&mut self, pos: &Position) {
+        self.hash = 0;
+        self.pawn_hash = 0;
+        self.material_hash = 0;
+        if pos.white_to_move {
+            self.hash ^= self.side_key();
         }
 
-        for sq in pos.bishops().squares() {
-            self.hash ^= self.hashes[Piece::Bishop.index()][sq];
+        if pos.details.en_passant != 255 {
+            self.hash ^= self.ep_key(pos.details.en_passant);
         }
 
-        for sq in pos.rooks().squares() {
-            self.hash ^= self.hashes[Piece::Rook.index()][sq];
-        }
+        self.hash ^= self.castle_key(pos.details.castling);
 
-        for sq in pos.queens().squares() {
-            self.hash ^= self.hashes[Piece::Queen.index()][sq];
-        }
+        for &piece in Piece::all().iter() {
+            for white in [false, true].iter().copied() {
+                for sq in pos.pieces_of(piece, white).squares() {
+                    self.hash ^= self.piece_key(piece, white, sq);
+                    if piece == Piece::Pawn {
+                        self.pawn_hash ^= self.piece_key(piece, white, sq);
+                    }
+                }
 
-        for sq in pos.kings().squares() {
-            self.hash ^= self.hashes[Piece::King.index()][sq];
+                if piece != Piece::King {
+                    for count in 0..pos.piece_count(piece, white) {
+                        self.material_hash ^= self.material_key(piece, white, count);
+                    }
+                }
+            }
         }
     }
 
@@ -213,8 +253,38 @@ impl Hasher {
             }
         }
 
-        let mut castling = pos.details.castling;
-        self.hash ^= self.castle[castling as usize];
+        // Update Material Hash
+        if let Some(captured) = mov.captured {
+            let count = pos.piece_count(captured, !pos.white_to_move);
+            self.material_hash ^= self.material_key(captured, !pos.white_to_move, count - 1);
+        }
+
+        if let Some(promoted) = mov.promoted {
+            let pawns = pos.piece_count(Piece::Pawn, pos.white_to_move);
+            let count = pos.piece_count(promoted, pos.white_to_move);
+            self.material_hash ^= self.material_key(Piece::Pawn, pos.white_to_move, pawns - 1);
+            self.material_hash ^= self.material_key(promoted, pos.white_to_move, count);
+        }
+
+        self.hash ^= self.castle[pos.details.castling as usize];
+        self.hash ^= self.castle[pos.castling_after(mov) as usize];
+
+        if mov.castling {
+            let (king_to, rook_from, rook_to) = pos.castling_squares(mov);
+            self.hash ^= self.hashes[Piece::King.index()][mov.from];
+            self.hash ^= self.hashes[Piece::King.index()][king_to];
+            self.hash ^= self.hashes[Piece::Rook.index()][rook_from];
+            self.hash ^= self.hashes[Piece::Rook.index()][rook_to];
+            if pos.white_to_move {
+                self.hash ^= self.color[mov.from];
+                self.hash ^= self.color[king_to];
+                self.hash ^= self.color[rook_from];
+                self.hash ^= self.color[rook_to];
+            }
+
+            self.hash ^= self.white_to_move;
+            return;
+        }
 
         self.hash ^= self.hashes[mov.piece.index()][mov.from];
 
@@ -239,61 +309,197 @@ impl Hasher {
             self.hash ^= self.hashes[mov.piece.index()][mov.to];
         }
 
-        if mov.piece == Piece::King {
-            if mov.is_kingside_castle() {
-                // castle kingside
-                self.hash ^= self.hashes[Piece::Rook.index()][mov.to.right(1)];
-                self.hash ^= self.hashes[Piece::Rook.index()][mov.to.left(1)];
-                if pos.white_to_move {
-                    self.hash ^= self.color[mov.to.right(1)];
-                    self.hash ^= self.color[mov.to.left(1)];
-                }
-            } else if mov.is_queenside_castle() {
-                // castle queenside
-                self.hash ^= self.hashes[Piece::Rook.index()][mov.to.left(2)];
-                self.hash ^= self.hashes[Piece::Rook.index()][mov.to.right(1)];
-                if pos.white_to_move {
-                    self.hash ^= self.color[mov.to.left(2)];
-                    self.hash ^= self.color[mov.to.right(1)];
-                }
-            }
-
-            if pos.white_to_move {
-                castling &= CASTLE_BLACK_KSIDE | CASTLE_BLACK_QSIDE;
-            } else {
-                castling &= CASTLE_WHITE_KSIDE | CASTLE_WHITE_QSIDE;
-            }
+        if pos.white_to_move {
+            self.hash ^= self.color[mov.to];
+            self.hash ^= self.color[mov.from];
         }
 
-        if mov.from == SQUARE_A1 || mov.to == SQUARE_A1 {
-            castling &= !CASTLE_WHITE_QSIDE;
+        self.hash ^= self.white_to_move;
+    }
+
+    /// A null move only changes the side to move and clears the en passant
+    /// square. Castling rights cannot change, so their keys are left alone.
+    pub fn make_nullmove(&mut self, pos: &Position) {
+        self.hash ^= self.white_to_move;
+        if pos.details.en_passant != 255 {
+            self.hash ^= self.en_passant[pos.details.en_passant as usize];
         }
+    }
 
-        if mov.from == SQUARE_H1 || mov.to == SQUARE_H1 {
-            castling &= !CASTLE_WHITE_KSIDE;
+    /// Exact inverse of `make_nullmove`, given the details from before the
+    /// null move was made.
+    pub fn unmake_nullmove(&mut self, irreversible_details: &IrreversibleDetails) {
+        self.hash ^= self.white_to_move;
+        if irreversible_details.en_passant != 255 {
+            self.hash ^= self.en_passant[irreversible_details.en_passant as usize];
         }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_reconstruct_start_position_hash() {
+        crate::magic::initialize_magics();
+
+        let mut hasher = Hasher::new();
+        hasher.from_position(&STARTING_POSITION);
 
-        if mov.from == SQUARE_A8 || mov.to == SQUARE_A8 {
-            castling &= !CASTLE_BLACK_QSIDE;
+        let back_rank = [
+            Piece::Rook,
+            Piece::Knight,
+            Piece::Bishop,
+            Piece::Queen,
+            Piece::King,
+            Piece::Bishop,
+            Piece::Knight,
+            Piece::Rook,
+        ];
+
+        let mut hash = hasher.side_key() ^ hasher.castle_key(0xF);
+        let mut pawn_hash = 0;
+        for file in 0..8 {
+            hash ^= hasher.piece_key(back_rank[file as usize], true, Square::file_rank(file, 0));
+            hash ^= hasher.piece_key(back_rank[file as usize], false, Square::file_rank(file, 7));
+            for &(white, rank) in &[(true, 1), (false, 6)] {
+                let key = hasher.piece_key(Piece::Pawn, white, Square::file_rank(file, rank));
+                hash ^= key;
+                pawn_hash ^= key;
+            }
         }
 
-        if mov.from == SQUARE_H8 || mov.to == SQUARE_H8 {
-            castling &= !CASTLE_BLACK_KSIDE;
+        assert_eq!(hasher.get_hash(), hash);
+        assert_eq!(hasher.get_pawn_hash(), pawn_hash);
+    }
+
+    #[test]
+    fn incremental_hash_matches_from_position() {
+        crate::magic::initialize_magics();
+
+        let mut pos = STARTING_POSITION;
+        let mut hasher = Hasher::new();
+        for alg in &["e2e4", "d7d5", "e4d5", "g8f6"] {
+            let mov = Move::from_algebraic(&pos, alg);
+            hasher.make_move(&pos, mov);
+            pos.make_move(mov);
+
+            let mut expected = Hasher::new();
+            expected.from_position(&pos);
+            assert_eq!(hasher.get_hash(), expected.get_hash());
+            assert_eq!(hasher.get_pawn_hash(), expected.get_pawn_hash());
+            assert_eq!(hasher.get_material_hash(), expected.get_material_hash());
         }
+    }
 
-        if pos.white_to_move {
-            self.hash ^= self.color[mov.to];
-            self.hash ^= self.color[mov.from];
+    #[test]
+    fn material_hash_only_depends_on_piece_counts() {
+        crate::magic::initialize_magics();
+
+        let material_hash = |fen: &str| {
+            let mut hasher = Hasher::new();
+            hasher.from_position(&Position::from(fen));
+            hasher.get_material_hash()
+        };
+
+        let base = material_hash("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        assert_eq!(material_hash("4k3/8/8/3R4/8/8/8/4K3 b - - 7 30"), base);
+        assert_ne!(material_hash("r3k3/8/8/8/8/8/8/4K3 w - - 0 1"), base);
+
+        // Adding a piece toggles a single key, removing it again restores
+        // the hash.
+        let hasher = Hasher::new();
+        let with_knight = material_hash("4k3/8/8/8/8/8/8/RN2K3 w - - 0 1");
+        assert_eq!(
+            with_knight,
+            base ^ hasher.material_key(Piece::Knight, true, 0)
+        );
+        assert_eq!(
+            with_knight ^ hasher.material_key(Piece::Knight, true, 0),
+            base
+        );
+
+        // Captures and promotions update it incrementally.
+        let mut pos = Position::from("1n2k3/P7/8/8/8/8/8/R3K3 w - - 0 1");
+        let mut hasher = Hasher::new();
+        hasher.from_position(&pos);
+        let mov = Move::from_algebraic(&pos, "a7b8q");
+        assert_eq!(mov.captured, Some(Piece::Knight));
+        hasher.make_move(&pos, mov);
+        pos.make_move(mov);
+        assert_eq!(
+            hasher.get_material_hash(),
+            material_hash("1Q2k3/8/8/8/8/8/8/R3K3 b - - 0 1")
+        );
+    }
+
+    #[test]
+    fn incremental_hash_matches_after_chess960_castling() {
+        crate::magic::initialize_magics();
+
+        let mut pos = Position::from("rk2r3/4p3/8/8/8/8/4P3/RK2R3 w EAea - 0 1");
+        let mut hasher = Hasher::new();
+        hasher.from_position(&pos);
+        for alg in &["b1a1", "b8e8", "d1d2", "g8h8"] {
+            let mov = Move::from_algebraic(&pos, alg);
+            hasher.make_move(&pos, mov);
+            pos.make_move(mov);
+
+            let mut expected = Hasher::new();
+            expected.from_position(&pos);
+            assert_eq!(hasher.get_hash(), expected.get_hash());
         }
+    }
 
-        self.hash ^= self.castle[castling as usize];
-        self.hash ^= self.white_to_move;
+    #[test]
+    fn unusable_en_passant_square_is_not_hashed() {
+        crate::magic::initialize_magics();
+
+        let hash = |fen: &str| {
+            let mut hasher = Hasher::new();
+            hasher.from_position(&Position::from(fen));
+            hasher.get_hash()
+        };
+
+        assert_eq!(
+            hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+            hash("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+        );
+        assert_ne!(
+            hash("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"),
+            hash("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+        );
     }
 
-    pub fn make_nullmove(&mut self, pos: &Position) {
-        self.hash ^= self.white_to_move;
-        if pos.details.en_passant != 255 {
-            self.hash ^= self.en_passant[pos.details.en_passant as usize];
+    #[test]
+    fn unmake_nullmove_restores_hash() {
+        crate::magic::initialize_magics();
+
+        for fen in &[
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+            "4k3/8/8/8/8/8/8/4K3 b - - 0 1",
+        ] {
+            let mut pos = Position::from(*fen);
+            let mut hasher = Hasher::new();
+            hasher.from_position(&pos);
+            let hash = hasher.get_hash();
+            let pawn_hash = hasher.get_pawn_hash();
+
+            let details = pos.details;
+            hasher.make_nullmove(&pos);
+            pos.make_nullmove();
+            assert_ne!(hasher.get_hash(), hash);
+
+            let mut nullmove_hasher = Hasher::new();
+            nullmove_hasher.from_position(&pos);
+            assert_eq!(hasher.get_hash(), nullmove_hasher.get_hash(), "{}", fen);
+
+            hasher.unmake_nullmove(&details);
+            pos.unmake_nullmove(details);
+            assert_eq!(hasher.get_hash(), hash, "{}", fen);
+            assert_eq!(hasher.get_pawn_hash(), pawn_hash, "{}", fen);
         }
     }
 }
@@ -14,14 +14,34 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+use crate::bitboard::ALL_SQUARES;
 use crate::movegen::*;
 use crate::search::*;
 use crate::types::SquareMap;
 
-#[derive(Clone, Default)]
+// Once a single entry grows beyond this value all entries are halved,
+// keeping the relative order intact.
+const HISTORY_LIMIT: i64 = 1 << 24;
+
+// Ordering bonuses of quiet moves, far outside the range of history scores.
+// Killers come first, the counter move breaks ties between them and ranks
+// above all other quiets.
+const KILLER_BONUS: i64 = 1 << 40;
+const COUNTER_MOVE_BONUS: i64 = 1 << 39;
+
+#[derive(Clone)]
 pub struct History {
     piece_to: [[SquareMap<i64>; 6]; 2],
-    pub last_best_reply: [[SquareMap<Option<Move>>; 6]; 2],
+    counter_moves: [[Option<Move>; 64]; 64],
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            piece_to: Default::default(),
+            counter_moves: [[None; 64]; 64],
+        }
+    }
 }
 
 impl History {
@@ -32,7 +52,12 @@ impl History {
     pub fn increase_score(&mut self, white: bool, mov: Move, depth: Depth) {
         let d = i64::from(depth / INC_PLY);
 
-        self.piece_to[white as usize][mov.piece.index()][mov.to] += d * d;
+        let entry = &mut self.piece_to[white as usize][mov.piece.index()][mov.to];
+        *entry += d * d;
+
+        if *entry > HISTORY_LIMIT {
+            self.rescale();
+        }
     }
 
     pub fn decrease_score(&mut self, white: bool, moves: &[Option<Move>], depth: Depth) {
@@ -43,4 +68,264 @@ impl History {
             self.piece_to[white as usize][mov.piece.index()][mov.to] -= d * d;
         }
     }
+
+    /// Ordering score of a quiet move, combining its history score with
+    /// bonuses for being one of the `killers` or the counter move to
+    /// `previous_move`.
+    pub fn quiet_move_score(
+        &self,
+        white: bool,
+        mov: Move,
+        killers: [Option<Move>; 2],
+        previous_move: Option<Move>,
+    ) -> i64 {
+        let mut score = self.get_score(white, mov);
+
+        if killers.contains(&Some(mov)) {
+            score += KILLER_BONUS;
+        }
+
+        if previous_move.and_then(|prev| self.get_counter(prev)) == Some(mov) {
+            score += COUNTER_MOVE_BONUS;
+        }
+
+        score
+    }
+
+    /// The last move which caused a cutoff in reply to `prev`, keyed by its
+    /// from and to squares. Null moves and captures have no counter move.
+    pub fn get_counter(&self, prev: Move) -> Option<Move> {
+        if prev.is_null() || prev.is_capture_or_promotion() {
+            return None;
+        }
+
+        let from: u8 = prev.from.into();
+        let to: u8 = prev.to.into();
+        self.counter_moves[from as usize][to as usize]
+    }
+
+    /// Remembers `mov` as the reply to `prev` which caused a cutoff.
+    pub fn set_counter(&mut self, prev: Move, mov: Move) {
+        if prev.is_null() || prev.is_capture_or_promotion() {
+            return;
+        }
+
+        let from: u8 = prev.from.into();
+        let to: u8 = prev.to.into();
+        self.counter_moves[from as usize][to as usize] = Some(mov);
+    }
+
+    /// Ages the scores before a new search so that information from the
+    /// current position outweighs that of earlier moves in the game.
+    pub fn decay(&mut self) {
+        self.rescale();
+    }
+
+    /// Halves all scores. Counter moves can't be aged and are forgotten
+    /// instead, so that they don't outlive the scores they were found with.
+    fn rescale(&mut self) {
+        self.counter_moves = [[None; 64]; 64];
+
+        for side in self.piece_to.iter_mut() {
+            for piece in side.iter_mut() {
+                for sq in ALL_SQUARES.squares() {
+                    piece[sq] /= 2;
+                }
+            }
+        }
+    }
+}
+
+/// Two quiet moves per ply which recently caused a beta cutoff there. They
+/// are likely to refute sibling positions as well and are tried right after
+/// the good captures.
+#[derive(Clone)]
+pub struct Killers {
+    moves: [[Option<Move>; 2]; MAX_PLY as usize],
+}
+
+impl Default for Killers {
+    fn default() -> Self {
+        Killers {
+            moves: [[None; 2]; MAX_PLY as usize],
+        }
+    }
+}
+
+impl Killers {
+    pub fn get_killers(&self, ply: usize) -> [Option<Move>; 2] {
+        self.moves[ply]
+    }
+
+    /// Makes `mov` the first killer at `ply`, moving the previous first
+    /// killer to the second slot. Captures and promotions are not stored.
+    pub fn update_killer(&mut self, ply: usize, mov: Move) {
+        if !mov.is_quiet() {
+            return;
+        }
+
+        let killers = &mut self.moves[ply];
+        if killers[0] != Some(mov) {
+            killers[1] = killers[0];
+            killers[0] = Some(mov);
+        }
+    }
+
+    pub fn clear(&mut self, ply: usize) {
+        self.moves[ply] = [None; 2];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::Square;
+
+    fn quiet(piece: Piece, from: u8, to: u8) -> Move {
+        Move {
+            from: Square::from(from),
+            to: Square::from(to),
+            piece,
+            captured: None,
+            promoted: None,
+            en_passant: false,
+            castling: false,
+        }
+    }
+
+    #[test]
+    fn different_pieces_to_same_square_are_independent() {
+        let mut history = History::default();
+        let knight = quiet(Piece::Knight, 6, 21);
+        let pawn = quiet(Piece::Pawn, 13, 21);
+
+        history.increase_score(true, knight, 4 * INC_PLY);
+        assert_eq!(history.get_score(true, knight), 16);
+        assert_eq!(history.get_score(true, pawn), 0);
+        assert_eq!(history.get_score(false, knight), 0);
+
+        history.decrease_score(true, &[Some(pawn)], 2 * INC_PLY);
+        assert_eq!(history.get_score(true, knight), 16);
+        assert_eq!(history.get_score(true, pawn), -4);
+    }
+
+    #[test]
+    fn null_move_has_no_counter_move() {
+        let mut history = History::default();
+        let null = Move::NULL;
+        let previous = quiet(Piece::Knight, 6, 21);
+        let reply = quiet(Piece::Pawn, 52, 36);
+
+        assert!(null.is_null());
+        assert!(!previous.is_null());
+
+        history.counter_moves[6][21] = Some(reply);
+        history.counter_moves[0][0] = Some(reply);
+        assert_eq!(history.get_counter(previous), Some(reply));
+        assert_eq!(history.get_counter(null), None);
+    }
+
+    #[test]
+    fn rescale_halves_scores() {
+        let mut history = History::default();
+        let knight = quiet(Piece::Knight, 6, 21);
+        let pawn = quiet(Piece::Pawn, 13, 21);
+
+        history.increase_score(true, knight, 4 * INC_PLY);
+        history.increase_score(true, pawn, 2 * INC_PLY);
+        history.rescale();
+        assert_eq!(history.get_score(true, knight), 8);
+        assert_eq!(history.get_score(true, pawn), 2);
+    }
+
+    #[test]
+    fn decay_halves_scores_and_keeps_order() {
+        let mut history = History::default();
+        let knight = quiet(Piece::Knight, 6, 21);
+        let bishop = quiet(Piece::Bishop, 5, 26);
+        let pawn = quiet(Piece::Pawn, 13, 21);
+
+        history.increase_score(true, knight, 40 * INC_PLY);
+        history.increase_score(true, bishop, 10 * INC_PLY);
+        history.decrease_score(true, &[Some(pawn)], 6 * INC_PLY);
+        history.decay();
+
+        assert_eq!(history.get_score(true, knight), 800);
+        assert_eq!(history.get_score(true, bishop), 50);
+        assert_eq!(history.get_score(true, pawn), -18);
+    }
+
+    #[test]
+    fn killers_keep_the_two_latest_distinct_quiets() {
+        let mut killers = Killers::default();
+        let first = quiet(Piece::Knight, 6, 21);
+        let second = quiet(Piece::Pawn, 13, 21);
+        let capture = Move {
+            captured: Some(Piece::Pawn),
+            ..quiet(Piece::Bishop, 5, 40)
+        };
+
+        killers.update_killer(3, first);
+        killers.update_killer(3, first);
+        assert_eq!(killers.get_killers(3), [Some(first), None]);
+
+        killers.update_killer(3, second);
+        assert_eq!(killers.get_killers(3), [Some(second), Some(first)]);
+
+        killers.update_killer(3, capture);
+        assert_eq!(killers.get_killers(3), [Some(second), Some(first)]);
+        assert_eq!(killers.get_killers(4), [None, None]);
+
+        killers.clear(3);
+        assert_eq!(killers.get_killers(3), [None, None]);
+    }
+
+    #[test]
+    fn counter_moves_are_only_kept_for_quiet_previous_moves() {
+        let mut history = History::default();
+        let previous = quiet(Piece::Knight, 6, 21);
+        let reply = quiet(Piece::Pawn, 52, 36);
+
+        history.set_counter(previous, reply);
+        history.set_counter(Move::NULL, reply);
+        assert_eq!(history.get_counter(previous), Some(reply));
+        assert_eq!(history.get_counter(quiet(Piece::Knight, 6, 23)), None);
+
+        // Keyed by the squares only, whatever piece moved
+        assert_eq!(
+            history.get_counter(quiet(Piece::Bishop, 6, 21)),
+            Some(reply)
+        );
+
+        let quiet_bishop = quiet(Piece::Bishop, 5, 40);
+        let capture = Move {
+            captured: Some(Piece::Pawn),
+            ..quiet_bishop
+        };
+        history.set_counter(quiet_bishop, reply);
+        history.set_counter(capture, reply);
+        assert_eq!(history.get_counter(capture), None);
+        assert_eq!(history.get_counter(quiet_bishop), Some(reply));
+
+        history.decay();
+        assert_eq!(history.get_counter(previous), None);
+    }
+
+    #[test]
+    fn counter_moves_are_cleared_when_scores_are_rescaled() {
+        let mut history = History::default();
+        let previous = quiet(Piece::Knight, 6, 21);
+        let reply = quiet(Piece::Pawn, 52, 36);
+
+        history.set_counter(previous, reply);
+        history.increase_score(true, reply, 4 * INC_PLY);
+        assert_eq!(history.get_counter(previous), Some(reply));
+
+        // Pushing the score past the limit halves it and drops the counters
+        while history.get_counter(previous).is_some() {
+            assert!(history.get_score(true, reply) <= HISTORY_LIMIT);
+            history.increase_score(true, reply, 400 * INC_PLY);
+        }
+        assert!(history.get_score(true, reply) < HISTORY_LIMIT);
+    }
 }
@@ -0,0 +1,65 @@
+/* Asymptote, a UCI chess engine
+   Copyright (C) 2018-2020  Maximilian Lupke
+
+   This program is free software: you can redistribute it and/or modify
+   it under the terms of the GNU General Public License as published by
+   the Free Software Foundation, either version 3 of the License, or
+   (at your option) any later version.
+
+   This program is distributed in the hope that it will be useful,
+   but WITHOUT ANY WARRANTY; without even the implied warranty of
+   MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+   GNU General Public License for more details.
+
+   You should have received a copy of the GNU General Public License
+   along with this program.  If not, see <https://www.gnu.org/licenses/>.
+*/
+//! The engine as a library. Without the default `std` feature only the
+//! board representation, move generation and evaluation are built, which
+//! just need `alloc`.
+#![cfg_attr(not(feature = "std"), no_std)]
+#![warn(clippy::option_map_unwrap_or)]
+#![warn(clippy::option_map_unwrap_or_else)]
+#![warn(clippy::result_map_unwrap_or_else)]
+#![warn(clippy::single_match_else)]
+#![warn(clippy::unseparated_literal_suffix)]
+#![warn(clippy::used_underscore_binding)]
+#![warn(clippy::clone_on_ref_ptr)]
+#![warn(clippy::multiple_inherent_impl)]
+
+extern crate alloc;
+
+pub mod bitboard;
+pub mod eval;
+pub mod hash;
+pub mod magic;
+pub mod movegen;
+pub mod position;
+pub mod types;
+
+#[cfg(feature = "std")]
+pub mod bench;
+#[cfg(feature = "std")]
+pub mod epd;
+#[cfg(feature = "fathom")]
+pub mod fathom;
+#[cfg(feature = "std")]
+pub mod gendata;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod movepick;
+#[cfg(feature = "std")]
+pub mod repetitions;
+#[cfg(feature = "std")]
+pub mod search;
+#[cfg(feature = "std")]
+pub mod search_controller;
+#[cfg(feature = "std")]
+pub mod time;
+#[cfg(feature = "std")]
+pub mod tt;
+#[cfg(feature = "tune")]
+pub mod tune;
+#[cfg(feature = "std")]
+pub mod uci;
@@ -16,13 +16,39 @@
 */
 use crate::bitboard::*;
 use crate::types::SquareMap;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
 use rand::{prelude::*, prng::ChaChaRng};
 
 const SHIFT_MASK: u64 = 0xF8_00_00_00_00_00_00_00;
 
+// States of the magic tables. `std::sync::Once` isn't available without std,
+// so threads which find the tables being initialized spin until they're done.
+const UNINITIALIZED: usize = 0;
+const INITIALIZING: usize = 1;
+const INITIALIZED: usize = 2;
+
+static INIT_MAGICS: AtomicUsize = AtomicUsize::new(UNINITIALIZED);
+
 pub fn initialize_magics() {
-    let offset = initialize_bishop_attacks(0);
-    initialize_rook_attacks(offset);
+    let first = INIT_MAGICS
+        .compare_exchange(
+            UNINITIALIZED,
+            INITIALIZING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        )
+        .is_ok();
+
+    if first {
+        let offset = initialize_bishop_attacks(0);
+        initialize_rook_attacks(offset);
+        INIT_MAGICS.store(INITIALIZED, Ordering::Release);
+    } else {
+        while INIT_MAGICS.load(Ordering::Acquire) != INITIALIZED {
+            core::hint::spin_loop();
+        }
+    }
 }
 
 pub static mut MAGIC_TABLE: [Bitboard; 107_648] = [Bitboard(0); 107_648];
@@ -51,7 +77,7 @@ pub struct Magic {
 impl Magic {
     #[cfg(all(target_arch = "x86_64", target_feature = "bmi2"))]
     pub fn index(&self, occupied: Bitboard) -> usize {
-        use std::arch::x86_64::_pext_u64;
+        use core::arch::x86_64::_pext_u64;
 
         self.offset as usize + unsafe { _pext_u64(occupied.0, self.mask.0) as usize }
     }
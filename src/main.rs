@@ -14,39 +14,11 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
-#![warn(clippy::option_map_unwrap_or)]
-#![warn(clippy::option_map_unwrap_or_else)]
-#![warn(clippy::result_map_unwrap_or_else)]
-#![warn(clippy::single_match_else)]
-#![warn(clippy::unseparated_literal_suffix)]
-#![warn(clippy::used_underscore_binding)]
-#![warn(clippy::clone_on_ref_ptr)]
-#![warn(clippy::multiple_inherent_impl)]
-
-mod bench;
-mod bitboard;
-mod eval;
-#[cfg(feature = "fathom")]
-mod fathom;
-mod hash;
-mod history;
-mod magic;
-mod movegen;
-mod movepick;
-pub mod position;
-mod repetitions;
-mod search;
-mod search_controller;
-mod time;
-mod tt;
-#[cfg(feature = "tune")]
-mod tune;
-mod types;
-mod uci;
-
-use crate::bench::run_benchmark;
-use crate::magic::initialize_magics;
-use crate::uci::*;
+use asymptote::bench::run_benchmark;
+use asymptote::epd;
+use asymptote::gendata;
+use asymptote::magic::initialize_magics;
+use asymptote::uci::*;
 
 fn main() {
     initialize_magics();
@@ -61,6 +33,53 @@ fn main() {
         return;
     }
 
+    if std::env::args().nth(1) == Some(String::from("gendata")) {
+        let num_games = std::env::args()
+            .nth(2)
+            .and_then(|games| games.parse::<usize>().ok())
+            .unwrap_or(1);
+        let out_file = std::env::args()
+            .nth(3)
+            .unwrap_or_else(|| String::from("gendata.fens"));
+        let seed = match std::env::args().nth(4) {
+            Some(seed) => {
+                if let Ok(seed) = seed.parse::<u64>() {
+                    Some(seed)
+                } else {
+                    eprintln!("Unable to parse value '{}' as integer", seed);
+                    return;
+                }
+            }
+            None => None,
+        };
+        if let Err(err) = gendata::generate_data(num_games, &out_file, seed) {
+            eprintln!("Unable to write {}: {}", out_file, err);
+        }
+        return;
+    }
+
+    if std::env::args().nth(1) == Some(String::from("epd")) {
+        let path = match std::env::args().nth(2) {
+            Some(path) => path,
+            None => {
+                eprintln!("Usage: asymptote epd <file> [depth]");
+                return;
+            }
+        };
+        let depth = std::env::args()
+            .nth(3)
+            .and_then(|depth| depth.parse::<i16>().ok())
+            .unwrap_or(12);
+        if let Err(err) = epd::run_test_suite(
+            &path,
+            depth,
+            std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        ) {
+            eprintln!("Unable to read {}: {}", path, err);
+        }
+        return;
+    }
+
     let mut uci = UCI::new();
     uci.run();
 }
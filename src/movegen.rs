@@ -14,6 +14,10 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
 use crate::bitboard::*;
 use crate::eval::*;
 use crate::magic::{BISHOP_ATTACKS, MAGIC_TABLE, ROOK_ATTACKS};
@@ -23,6 +27,23 @@ pub type MoveList = arrayvec::ArrayVec<[Move; 256]>;
 pub type ShortMoveList = arrayvec::ArrayVec<[Move; 8]>;
 pub type ScoreList = arrayvec::ArrayVec<[i64; 256]>;
 
+/// Anything the move generator can push moves into.
+pub trait MoveSink {
+    fn push(&mut self, mov: Move);
+}
+
+impl MoveSink for MoveList {
+    fn push(&mut self, mov: Move) {
+        arrayvec::ArrayVec::push(self, mov);
+    }
+}
+
+impl MoveSink for Vec<Move> {
+    fn push(&mut self, mov: Move) {
+        Vec::push(self, mov);
+    }
+}
+
 pub fn get_bishop_attacks_from(from: Square, blockers: Bitboard) -> Bitboard {
     unsafe {
         let magic = &BISHOP_ATTACKS[from];
@@ -75,6 +96,18 @@ impl Piece {
         }
     }
 
+    /// Lower case letter of the piece as used in FENs for black pieces.
+    pub fn to_char(self) -> char {
+        match self {
+            Piece::Pawn => 'p',
+            Piece::Knight => 'n',
+            Piece::Bishop => 'b',
+            Piece::Rook => 'r',
+            Piece::Queen => 'q',
+            Piece::King => 'k',
+        }
+    }
+
     pub fn see_value(self) -> Score {
         match self {
             Piece::Pawn => 120,
@@ -96,6 +129,7 @@ pub struct Move {
     pub captured: Option<Piece>,
     pub promoted: Option<Piece>,
     pub en_passant: bool,
+    pub castling: bool,
 }
 
 pub struct MoveGenerator<'p> {
@@ -176,7 +210,13 @@ impl<'p> MoveGenerator<'p> {
         }
     }
 
-    pub fn quiet_moves(&self, moves: &mut MoveList) {
+    /// Checks whether `mov`, e.g. taken from the transposition table or a
+    /// killer slot, could have been generated in the current position.
+    pub fn is_pseudo_legal(&self, mov: Move) -> bool {
+        self.position.move_is_pseudo_legal(mov)
+    }
+
+    pub fn quiet_moves<L: MoveSink>(&self, moves: &mut L) {
         if self.position.details.checkers.more_than_one() {
             self.king(!self.position.all_pieces, moves);
             return;
@@ -206,7 +246,50 @@ impl<'p> MoveGenerator<'p> {
         self.king(!self.position.all_pieces, moves);
     }
 
-    pub fn all_moves(&self, moves: &mut MoveList) {
+    /// Generates the quiet moves which give check, either directly or by
+    /// moving one of our discovered check candidates off its line.
+    ///
+    /// The squares giving direct check and the discovered check candidates
+    /// are computed once. Only castling and moves of candidates fall back to
+    /// `Position::move_will_check`.
+    pub fn quiet_checks<L: MoveSink>(&self, moves: &mut L) {
+        if self.position.in_check() {
+            return;
+        }
+
+        let white = self.position.white_to_move;
+        let king_sq = self.position.king_sq(!white);
+        let king = king_sq.to_bb();
+        let pawn_checks = (king.left(1) | king.right(1)).backward(white, 1);
+        let knight_checks = KNIGHT_ATTACKS[king_sq];
+        let bishop_checks = get_bishop_attacks_from(king_sq, self.position.all_pieces);
+        let rook_checks = get_rook_attacks_from(king_sq, self.position.all_pieces);
+        let candidates = self.position.discovered_check_candidates();
+
+        let mut quiets = MoveList::new();
+        self.quiet_moves(&mut quiets);
+        for mov in quiets {
+            let gives_check = if mov.castling || candidates & mov.from {
+                self.position.move_will_check(mov)
+            } else {
+                let check_squares = match mov.piece {
+                    Piece::Pawn => pawn_checks,
+                    Piece::Knight => knight_checks,
+                    Piece::Bishop => bishop_checks,
+                    Piece::Rook => rook_checks,
+                    Piece::Queen => bishop_checks | rook_checks,
+                    Piece::King => Bitboard::from(0),
+                };
+                check_squares & mov.to
+            };
+
+            if gives_check {
+                moves.push(mov);
+            }
+        }
+    }
+
+    pub fn all_moves<L: MoveSink>(&self, moves: &mut L) {
         let us = self.position.us(self.position.white_to_move);
         let all = !us;
         self.pawn(all, moves);
@@ -217,7 +300,19 @@ impl<'p> MoveGenerator<'p> {
         self.king(all, moves);
     }
 
-    pub fn pawn(&self, targets: Bitboard, moves: &mut MoveList) {
+    /// Generates all legal moves, i.e. `all_moves` without the moves which
+    /// would leave our king in check.
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.all_moves(&mut moves);
+        moves.retain(|mov| self.position.move_is_legal(*mov));
+        moves
+    }
+
+    /// Generates pseudo-legal pawn moves to `targets`. En passant captures
+    /// are not checked for pins either, e.g. both pawns leaving a rank shared
+    /// by the king and an enemy rook; `Position::move_is_legal` rejects those.
+    pub fn pawn<L: MoveSink>(&self, targets: Bitboard, moves: &mut L) {
         let us = self.position.us(self.position.white_to_move);
         let them = self.position.them(self.position.white_to_move);
         let promoting = if self.position.white_to_move { 7 } else { 0 };
@@ -248,6 +343,7 @@ impl<'p> MoveGenerator<'p> {
                         captured: None,
                         promoted: Some(*promoted),
                         en_passant: false,
+                        castling: false,
                     });
                 }
             } else {
@@ -258,6 +354,7 @@ impl<'p> MoveGenerator<'p> {
                     captured: None,
                     promoted: None,
                     en_passant: false,
+                    castling: false,
                 });
             }
         }
@@ -270,6 +367,7 @@ impl<'p> MoveGenerator<'p> {
                 captured: None,
                 promoted: None,
                 en_passant: false,
+                castling: false,
             });
         }
 
@@ -302,6 +400,7 @@ impl<'p> MoveGenerator<'p> {
                         captured: Some(Piece::Pawn),
                         promoted: None,
                         en_passant: true,
+                        castling: false,
                     });
                 }
             }
@@ -321,6 +420,7 @@ impl<'p> MoveGenerator<'p> {
                         captured,
                         promoted: Some(*promoted),
                         en_passant: false,
+                        castling: false,
                     });
                 }
             } else {
@@ -331,6 +431,7 @@ impl<'p> MoveGenerator<'p> {
                     captured,
                     promoted: None,
                     en_passant: false,
+                    castling: false,
                 });
             }
         }
@@ -348,6 +449,7 @@ impl<'p> MoveGenerator<'p> {
                         captured,
                         promoted: Some(*promoted),
                         en_passant: false,
+                        castling: false,
                     });
                 }
             } else {
@@ -358,12 +460,13 @@ impl<'p> MoveGenerator<'p> {
                     captured,
                     promoted: None,
                     en_passant: false,
+                    castling: false,
                 });
             }
         }
     }
 
-    pub fn knight(&self, targets: Bitboard, moves: &mut MoveList) {
+    pub fn knight<L: MoveSink>(&self, targets: Bitboard, moves: &mut L) {
         let us = self.position.us(self.position.white_to_move);
         for from in (self.position.knights() & us).squares() {
             for to in (targets & self.knight_from(from)).squares() {
@@ -374,6 +477,7 @@ impl<'p> MoveGenerator<'p> {
                     captured: self.position.find_piece(to),
                     promoted: None,
                     en_passant: false,
+                    castling: false,
                 });
             }
         }
@@ -383,7 +487,7 @@ impl<'p> MoveGenerator<'p> {
         KNIGHT_ATTACKS[from]
     }
 
-    pub fn bishop(&self, targets: Bitboard, moves: &mut MoveList) {
+    pub fn bishop<L: MoveSink>(&self, targets: Bitboard, moves: &mut L) {
         let us = self.position.us(self.position.white_to_move);
         for from in (self.position.bishops() & us).squares() {
             for to in (targets & get_bishop_attacks_from(from, self.position.all_pieces)).squares()
@@ -395,12 +499,13 @@ impl<'p> MoveGenerator<'p> {
                     captured: self.position.find_piece(to),
                     promoted: None,
                     en_passant: false,
+                    castling: false,
                 });
             }
         }
     }
 
-    pub fn rook(&self, targets: Bitboard, moves: &mut MoveList) {
+    pub fn rook<L: MoveSink>(&self, targets: Bitboard, moves: &mut L) {
         let us = self.position.us(self.position.white_to_move);
         for from in (self.position.rooks() & us).squares() {
             for to in (targets & get_rook_attacks_from(from, self.position.all_pieces)).squares() {
@@ -411,12 +516,13 @@ impl<'p> MoveGenerator<'p> {
                     captured: self.position.find_piece(to),
                     promoted: None,
                     en_passant: false,
+                    castling: false,
                 });
             }
         }
     }
 
-    pub fn queen(&self, targets: Bitboard, moves: &mut MoveList) {
+    pub fn queen<L: MoveSink>(&self, targets: Bitboard, moves: &mut L) {
         let us = self.position.us(self.position.white_to_move);
         for from in (self.position.queens() & us).squares() {
             for to in (targets
@@ -431,35 +537,13 @@ impl<'p> MoveGenerator<'p> {
                     captured: self.position.find_piece(to),
                     promoted: None,
                     en_passant: false,
+                    castling: false,
                 });
             }
         }
     }
 
-    pub fn king(&self, targets: Bitboard, moves: &mut MoveList) {
-        let us = self.position.us(self.position.white_to_move);
-        let castle_kside;
-        let castle_qside;
-        if self.position.white_to_move {
-            castle_kside = (self.position.details.castling & CASTLE_WHITE_KSIDE) > 0
-                && (self.position.all_pieces & Bitboard::from(0x00_00_00_00_00_00_00_60))
-                    .is_empty()
-                && (self.position.rooks() & us & SQUARE_H1);
-            castle_qside = (self.position.details.castling & CASTLE_WHITE_QSIDE) > 0
-                && (self.position.all_pieces & Bitboard::from(0x00_00_00_00_00_00_00_0E))
-                    .is_empty()
-                && (self.position.rooks() & us & SQUARE_A1);
-        } else {
-            castle_kside = (self.position.details.castling & CASTLE_BLACK_KSIDE) > 0
-                && (self.position.all_pieces & Bitboard::from(0x60_00_00_00_00_00_00_00))
-                    .is_empty()
-                && (self.position.rooks() & us & SQUARE_H8);
-            castle_qside = (self.position.details.castling & CASTLE_BLACK_QSIDE) > 0
-                && (self.position.all_pieces & Bitboard::from(0x0E_00_00_00_00_00_00_00))
-                    .is_empty()
-                && (self.position.rooks() & us & SQUARE_A8);
-        }
-
+    pub fn king<L: MoveSink>(&self, targets: Bitboard, moves: &mut L) {
         let from = self.position.king_sq(self.position.white_to_move);
         for to in (targets & self.king_from(from)).squares() {
             moves.push(Move {
@@ -469,30 +553,20 @@ impl<'p> MoveGenerator<'p> {
                 captured: self.position.find_piece(to),
                 promoted: None,
                 en_passant: false,
+                castling: false,
             });
         }
 
-        // TODO: check king position?
-        if castle_kside && targets & from.right(2) {
-            moves.push(Move {
-                from,
-                to: from.right(2),
-                piece: Piece::King,
-                captured: None,
-                promoted: None,
-                en_passant: false,
-            });
+        // Castling is a quiet move, even if it is encoded as the king
+        // capturing its own rook in Chess960.
+        if (targets & !self.position.all_pieces).is_empty() {
+            return;
         }
 
-        if castle_qside && targets & from.left(2) {
-            moves.push(Move {
-                from,
-                to: from.left(2),
-                piece: Piece::King,
-                captured: None,
-                promoted: None,
-                en_passant: false,
-            });
+        for &kingside in &[true, false] {
+            if self.position.can_castle(kingside) {
+                moves.push(self.position.castling_move(kingside));
+            }
         }
     }
 
@@ -508,20 +582,37 @@ impl<'p> From<&'p Position> for MoveGenerator<'p> {
 }
 
 impl Move {
+    /// Placeholder for a null move, i.e. passing the turn to the opponent.
+    pub const NULL: Move = Move {
+        from: SQUARE_A1,
+        to: SQUARE_A1,
+        piece: Piece::Pawn,
+        captured: None,
+        promoted: None,
+        en_passant: false,
+        castling: false,
+    };
+
+    pub fn is_null(self) -> bool {
+        self.from == self.to
+    }
+
     pub fn is_quiet(self) -> bool {
         self.captured.is_none() && self.promoted.is_none()
     }
 
+    /// Captures (including en passant) and promotions, i.e. the moves which
+    /// change material on the board.
+    pub fn is_capture_or_promotion(self) -> bool {
+        self.captured.is_some() || self.promoted.is_some()
+    }
+
     pub fn is_kingside_castle(self) -> bool {
-        self.piece == Piece::King
-            && (self.from == SQUARE_E1 || self.from == SQUARE_E8)
-            && self.to == self.from.right(2)
+        self.castling && self.to.file() > self.from.file()
     }
 
     pub fn is_queenside_castle(self) -> bool {
-        self.piece == Piece::King
-            && (self.from == SQUARE_E1 || self.from == SQUARE_E8)
-            && self.to == self.from.left(2)
+        self.castling && self.to.file() < self.from.file()
     }
 
     pub fn mvv_lva_score(self) -> i64 {
@@ -565,6 +656,12 @@ impl Move {
         let from = Square::file_rank(from_file as u8, from_rank as u8);
         let to = Square::file_rank(to_file as u8, to_rank as u8);
         let piece = pos.find_piece(from).unwrap();
+        if piece == Piece::King {
+            if let Some(castling) = pos.castling_move_to(from, to) {
+                return castling;
+            }
+        }
+
         let captured;
 
         let en_passant;
@@ -596,7 +693,178 @@ impl Move {
             captured,
             promoted,
             en_passant,
+            castling: false,
+        }
+    }
+
+    /// Parses a move in standard algebraic notation (e.g. `Nbd7`, `O-O-O`,
+    /// `exd6`, `R1e2`, `f1=N+`). Returns `None` if the move is illegal in the
+    /// given position or does not identify a unique legal move.
+    // `map_or` instead of `is_none_or`, which needs Rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn from_san(pos: &Position, san: &str) -> Option<Move> {
+        let san = san.trim_end_matches(&['+', '#', '!', '?'][..]);
+
+        if san == "O-O" || san == "0-0" {
+            return pos
+                .legal_moves()
+                .into_iter()
+                .find(|mov| mov.is_kingside_castle());
+        }
+        if san == "O-O-O" || san == "0-0-0" {
+            return pos
+                .legal_moves()
+                .into_iter()
+                .find(|mov| mov.is_queenside_castle());
+        }
+
+        let mut chars: Vec<char> = san.chars().collect();
+
+        let piece = match chars.first() {
+            Some('N') => Piece::Knight,
+            Some('B') => Piece::Bishop,
+            Some('R') => Piece::Rook,
+            Some('Q') => Piece::Queen,
+            Some('K') => Piece::King,
+            Some(_) => Piece::Pawn,
+            None => return None,
+        };
+        if piece != Piece::Pawn {
+            chars.remove(0);
+        }
+
+        let mut promoted = None;
+        if piece == Piece::Pawn {
+            let promotion = match chars.last() {
+                Some('N') => Some(Piece::Knight),
+                Some('B') => Some(Piece::Bishop),
+                Some('R') => Some(Piece::Rook),
+                Some('Q') => Some(Piece::Queen),
+                _ => None,
+            };
+            if promotion.is_some() {
+                promoted = promotion;
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+            }
+        }
+
+        let capture = chars.contains(&'x');
+        chars.retain(|&c| c != 'x');
+
+        if chars.len() < 2 || chars.len() > 4 {
+            return None;
+        }
+        let to_file = chars[chars.len() - 2];
+        let to_rank = chars[chars.len() - 1];
+        if !('a'..='h').contains(&to_file) || !('1'..='8').contains(&to_rank) {
+            return None;
+        }
+        let to = Square::file_rank(to_file as u8 - b'a', to_rank as u8 - b'1');
+
+        let mut from_file = None;
+        let mut from_rank = None;
+        for &c in &chars[..chars.len() - 2] {
+            match c {
+                'a'..='h' if from_file.is_none() && from_rank.is_none() => {
+                    from_file = Some(c as u8 - b'a')
+                }
+                '1'..='8' if from_rank.is_none() => from_rank = Some(c as u8 - b'1'),
+                _ => return None,
+            }
+        }
+
+        // Pawn pushes never change the file, so a pawn move without an origin
+        // file has to start on the target file.
+        if piece == Piece::Pawn && from_file.is_none() {
+            from_file = Some(to.file());
+        }
+
+        let mut candidates = pos.legal_moves().into_iter().filter(|mov| {
+            mov.piece == piece
+                && !mov.castling
+                && mov.to == to
+                && mov.promoted == promoted
+                && (!capture || mov.captured.is_some())
+                && from_file.map_or(true, |file| mov.from.file() == file)
+                && from_rank.map_or(true, |rank| mov.from.rank() == rank)
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mov), None) => Some(mov),
+            _ => None,
+        }
+    }
+
+    /// Formats the move in standard algebraic notation (e.g. `Nbd2`, `exd6`,
+    /// `e8=Q+`). The move has to be legal in the given position.
+    pub fn to_san(self, pos: &mut Position) -> String {
+        let mut san = String::with_capacity(7);
+        let letters: Vec<_> = "abcdefgh".chars().collect();
+        let numbers: Vec<_> = "12345678".chars().collect();
+
+        if self.is_kingside_castle() {
+            san.push_str("O-O");
+        } else if self.is_queenside_castle() {
+            san.push_str("O-O-O");
+        } else {
+            match self.piece {
+                Piece::Pawn => {
+                    if self.captured.is_some() {
+                        san.push(letters[self.from.file() as usize]);
+                    }
+                }
+                piece => {
+                    san.push(piece.to_char().to_ascii_uppercase());
+
+                    let ambiguous: Vec<Move> = pos
+                        .legal_moves()
+                        .into_iter()
+                        .filter(|m| m.piece == piece && m.to == self.to && m.from != self.from)
+                        .collect();
+
+                    if !ambiguous.is_empty() {
+                        let same_file = ambiguous.iter().any(|m| m.from.file() == self.from.file());
+                        let same_rank = ambiguous.iter().any(|m| m.from.rank() == self.from.rank());
+                        if !same_file {
+                            san.push(letters[self.from.file() as usize]);
+                        } else if !same_rank {
+                            san.push(numbers[self.from.rank() as usize]);
+                        } else {
+                            san.push(letters[self.from.file() as usize]);
+                            san.push(numbers[self.from.rank() as usize]);
+                        }
+                    }
+                }
+            }
+
+            if self.captured.is_some() {
+                san.push('x');
+            }
+
+            san.push(letters[self.to.file() as usize]);
+            san.push(numbers[self.to.rank() as usize]);
+
+            if let Some(promoted) = self.promoted {
+                san.push('=');
+                san.push(promoted.to_char().to_ascii_uppercase());
+            }
         }
+
+        let details = pos.details;
+        pos.make_move(self);
+        if pos.in_check() {
+            if pos.legal_moves().is_empty() {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        pos.unmake_move(self, details);
+
+        san
     }
 
     pub fn to_algebraic(self) -> String {
@@ -608,13 +876,375 @@ impl Move {
         alg.push(letters[self.to.file() as usize]);
         alg.push(numbers[self.to.rank() as usize]);
         match self.promoted {
-            Some(Piece::Queen) => alg.push('q'),
-            Some(Piece::Knight) => alg.push('n'),
-            Some(Piece::Rook) => alg.push('r'),
-            Some(Piece::Bishop) => alg.push('b'),
-            Some(x) => panic!("Invalid promotion piece: {:?}", x),
+            Some(Piece::King) | Some(Piece::Pawn) => {
+                panic!("Invalid promotion piece: {:?}", self.promoted)
+            }
+            Some(piece) => alg.push(piece.to_char()),
             None => {}
         }
         alg
     }
 }
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_algebraic())
+    }
+}
+
+/// Counts the leaf nodes of the legal move tree below `pos` up to `depth`.
+pub fn perft(pos: &mut Position, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut moves = MoveList::new();
+    MoveGenerator::from(&*pos).all_moves(&mut moves);
+
+    let mut nodes = 0;
+    for &mov in &moves {
+        if !pos.move_is_legal(mov) {
+            continue;
+        }
+
+        let details = pos.details;
+        pos.make_move(mov);
+        nodes += perft(pos, depth - 1);
+        pos.unmake_move(mov, details);
+    }
+
+    nodes
+}
+
+/// Like `perft`, but prints the node count below each legal root move.
+#[cfg(feature = "std")]
+pub fn perft_divide(pos: &mut Position, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut moves = MoveList::new();
+    MoveGenerator::from(&*pos).all_moves(&mut moves);
+
+    let mut nodes = 0;
+    for &mov in &moves {
+        if !pos.move_is_legal(mov) {
+            continue;
+        }
+
+        let details = pos.details;
+        pos.make_move(mov);
+        let subtree = perft(pos, depth - 1);
+        pos.unmake_move(mov, details);
+
+        println!("{}: {}", mov.to_algebraic(), subtree);
+        nodes += subtree;
+    }
+
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::string::ToString;
+
+    #[test]
+    fn algebraic_notation_round_trips() {
+        crate::magic::initialize_magics();
+
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/8/8/2pP4/8/8/8/4K3 w - c6 0 1",
+            "4k3/8/8/8/2Pp4/8/8/4K3 b - c3 0 1",
+        ] {
+            let pos = Position::from(*fen);
+            for mov in pos.legal_moves() {
+                let alg = mov.to_algebraic();
+                assert_eq!(Move::from_algebraic(&pos, &alg), mov, "{} in {}", alg, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn san_round_trips() {
+        crate::magic::initialize_magics();
+
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/8/8/2pP4/8/8/8/4K3 w - c6 0 1",
+            "1k6/8/8/8/8/N4N2/8/R3K2R w KQ - 0 1",
+        ] {
+            let mut pos = Position::from(*fen);
+            for mov in pos.legal_moves() {
+                let san = mov.to_san(&mut pos);
+                assert_eq!(Move::from_san(&pos, &san), Some(mov), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn san_disambiguation() {
+        crate::magic::initialize_magics();
+
+        // Two knights can reach d7, two rooks share the e-file and two
+        // rooks share the second rank.
+        let pos = Position::from("7k/8/8/1N3N2/4R3/8/R3R2K/8 w - - 0 1");
+        let san = |s: &str| Move::from_san(&pos, s).map(Move::to_algebraic);
+
+        assert_eq!(san("Nd6"), None);
+        assert_eq!(san("Nbd6"), Some("b5d6".to_string()));
+        assert_eq!(san("Nfd6+"), Some("f5d6".to_string()));
+        assert_eq!(san("Re3"), None);
+        assert_eq!(san("R4e3"), Some("e4e3".to_string()));
+        assert_eq!(san("R2e3"), Some("e2e3".to_string()));
+        assert_eq!(san("Rc2"), None);
+        assert_eq!(san("Rac2"), Some("a2c2".to_string()));
+        assert_eq!(san("Rec2"), Some("e2c2".to_string()));
+        assert_eq!(san("Rxe7"), None);
+        assert_eq!(san("Ng4"), None);
+    }
+
+    #[test]
+    fn san_pawn_moves() {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from("1n2k3/P7/8/2pP4/8/8/8/4K3 w - c6 0 1");
+        let san = |s: &str| Move::from_san(&pos, s);
+
+        let en_passant = san("dxc6").unwrap();
+        assert!(en_passant.en_passant);
+        assert_eq!(en_passant.captured, Some(Piece::Pawn));
+        assert_eq!(san("c6"), None);
+        assert_eq!(san("d6").unwrap().to_algebraic(), "d5d6");
+        assert_eq!(san("axb8=Q+").unwrap().to_algebraic(), "a7b8q");
+        assert_eq!(san("a8=N").unwrap().to_algebraic(), "a7a8n");
+        assert_eq!(san("a8"), None);
+
+        let black = Position::from("4k3/8/8/8/8/8/5p2/K5N1 b - - 0 1");
+        assert_eq!(
+            Move::from_san(&black, "f1=N").unwrap().to_algebraic(),
+            "f2f1n"
+        );
+        assert_eq!(
+            Move::from_san(&black, "fxg1=Q").unwrap().to_algebraic(),
+            "f2g1q"
+        );
+        assert_eq!(Move::from_san(&black, "O-O"), None);
+        assert_eq!(Move::from_san(&black, ""), None);
+        assert_eq!(Move::from_san(&black, "Kz9"), None);
+    }
+
+    #[test]
+    fn display_is_uci_notation() {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8");
+        for mov in pos.legal_moves() {
+            assert_eq!(format!("{}", mov), mov.to_algebraic());
+        }
+    }
+
+    #[test]
+    fn quiet_checks_include_discovered_checks() {
+        crate::magic::initialize_magics();
+
+        // The knight masks the rook's battery against the black king.
+        let pos = Position::from("4k3/8/8/8/4N3/8/8/K3R3 w - - 0 1");
+        let candidates = pos.discovered_check_candidates();
+        assert_eq!(candidates, Square::file_rank(4, 3).to_bb());
+
+        let mut checks = MoveList::new();
+        MoveGenerator::from(&pos).quiet_checks(&mut checks);
+        assert_eq!(checks.len(), 8);
+
+        let king = pos.king_sq(false);
+        let discovered_only = checks
+            .iter()
+            .filter(|mov| candidates & mov.from)
+            .filter(|mov| !(KNIGHT_ATTACKS[mov.to] & king))
+            .count();
+        assert_eq!(discovered_only, 6);
+    }
+
+    #[test]
+    fn quiet_checks_match_move_will_check() {
+        crate::magic::initialize_magics();
+
+        // The bishop on e4 can discover the queen's check, while the knight
+        // on b1 has no checking move.
+        let pos = Position::from("4k3/8/8/8/4B3/8/8/1N2QK2 w - - 0 1");
+        let mut checks = Vec::new();
+        MoveGenerator::from(&pos).quiet_checks(&mut checks);
+        assert!(checks.contains(&Move::from_algebraic(&pos, "e4d3")));
+        assert!(!checks.contains(&Move::from_algebraic(&pos, "b1c3")));
+
+        for fen in &[
+            "4k3/8/8/8/4B3/8/8/1N2QK2 w - - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let pos = Position::from(*fen);
+            let mut quiets = MoveList::new();
+            MoveGenerator::from(&pos).quiet_moves(&mut quiets);
+            let expected: Vec<_> = quiets
+                .into_iter()
+                .filter(|&mov| pos.move_will_check(mov))
+                .collect();
+
+            let mut checks = Vec::new();
+            MoveGenerator::from(&pos).quiet_checks(&mut checks);
+            assert_eq!(checks, expected, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn captures_and_promotions_are_not_quiet() {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from("r3k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        let en_passant = Move::from_algebraic(&pos, "e5d6");
+        let promotion = Move::from_algebraic(&pos, "b7b8q");
+        let capture = Move::from_algebraic(&pos, "b7a8q");
+        let quiet = Move::from_algebraic(&pos, "e1d2");
+
+        assert!(en_passant.en_passant);
+        assert!(en_passant.is_capture_or_promotion());
+        assert!(promotion.is_capture_or_promotion());
+        assert!(capture.is_capture_or_promotion());
+        assert!(!quiet.is_capture_or_promotion());
+        for mov in &[en_passant, promotion, capture, quiet] {
+            assert_eq!(mov.is_quiet(), !mov.is_capture_or_promotion());
+        }
+    }
+
+    #[test]
+    fn pinned_en_passant_is_generated_but_illegal() {
+        crate::magic::initialize_magics();
+
+        // Capturing en passant removes both pawns from the fifth rank and
+        // exposes the white king to the rook.
+        let pos = Position::from("8/8/8/KPp4r/8/8/8/4k3 w - c6 0 1");
+        let mut moves = MoveList::new();
+        MoveGenerator::from(&pos).all_moves(&mut moves);
+
+        let en_passant = *moves
+            .iter()
+            .find(|mov| mov.en_passant)
+            .expect("en passant capture should be generated");
+        assert_eq!(en_passant.to_algebraic(), "b5c6");
+        assert!(!pos.move_is_legal(en_passant));
+        assert!(!pos.legal_moves().contains(&en_passant));
+
+        let push = Move::from_algebraic(&pos, "b5b6");
+        assert!(pos.move_is_legal(push));
+    }
+
+    // Like all tests of the core modules this also runs with
+    // `--no-default-features`, where the crate is `no_std` and only has `alloc`.
+    #[test]
+    fn move_generation_works_without_std() {
+        crate::magic::initialize_magics();
+
+        let mut pos = STARTING_POSITION;
+        let mut moves = MoveList::new();
+        MoveGenerator::from(&pos).all_moves(&mut moves);
+        assert_eq!(moves.len(), 20);
+        assert_eq!(pos.legal_moves().len(), 20);
+
+        let mov = Move::from_algebraic(&pos, "e2e4");
+        assert_eq!(mov.to_algebraic(), "e2e4");
+        pos.make_move(mov);
+        assert_eq!(
+            pos.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert_eq!(perft(&mut pos, 2), 600);
+    }
+
+    #[test]
+    fn perft_start_position() {
+        crate::magic::initialize_magics();
+
+        let mut pos = Position::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        for (depth, &nodes) in [1, 20, 400, 8902, 197_281].iter().enumerate() {
+            assert_eq!(perft(&mut pos, depth), nodes);
+        }
+    }
+
+    #[test]
+    fn perft_kiwipete() {
+        crate::magic::initialize_magics();
+
+        let mut pos =
+            Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let initial = pos.clone();
+        for (depth, &nodes) in [1, 48, 2039, 97_862].iter().enumerate() {
+            assert_eq!(perft(&mut pos, depth), nodes);
+        }
+        #[cfg(feature = "std")]
+        assert_eq!(perft_divide(&mut pos, 2), 2039);
+        assert_eq!(pos, initial);
+    }
+
+    #[test]
+    fn perft_chess960() {
+        crate::magic::initialize_magics();
+
+        for &(fen, counts) in &[
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [1, 21, 528, 12_189],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [1, 21, 807, 18_002],
+            ),
+            (
+                "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+                [1, 20, 479, 10_471],
+            ),
+            (
+                "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+                [1, 29, 502, 14_569],
+            ),
+        ] {
+            let mut pos = Position::from(fen);
+            assert!(pos.chess960);
+            for (depth, &nodes) in counts.iter().enumerate() {
+                assert_eq!(perft(&mut pos, depth), nodes, "{} at depth {}", fen, depth);
+            }
+            assert_eq!(pos.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn legal_moves_exclude_pinned_pieces() {
+        crate::magic::initialize_magics();
+
+        let start = Position::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(MoveGenerator::from(&start).legal_moves().len(), 20);
+
+        // The knight on e2 is pinned by the rook, only the king may move.
+        let pinned = Position::from("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1");
+        let moves = MoveGenerator::from(&pinned).legal_moves();
+        assert_eq!(moves.len(), 4);
+        assert!(moves.iter().all(|mov| mov.piece == Piece::King));
+        assert!(pinned
+            .pseudo_legal_moves()
+            .iter()
+            .any(|mov| mov.piece == Piece::Knight));
+    }
+}
@@ -42,6 +42,8 @@ enum Stage {
     Killers,
     GenerateQuietMoves,
     QuietMoves,
+    GenerateQuietChecks,
+    QuietChecks,
     GenerateBadCaptures,
     BadCaptures,
 }
@@ -60,6 +62,16 @@ const ALPHA_BETA_STAGES: &[Stage] = &[
 
 const QUIESCENCE_STAGES: &[Stage] = &[Stage::GenerateGoodCaptures, Stage::GoodCaptures];
 
+const QUIESCENCE_QUIET_CHECKS_STAGES: &[Stage] = &[
+    Stage::GenerateGoodCaptures,
+    Stage::GoodCaptures,
+    Stage::GenerateQuietChecks,
+    Stage::QuietChecks,
+];
+
+// When in check the quiescence search has to consider every evasion, not
+// just captures, otherwise it would report mate for positions with a quiet
+// escape.
 const QUIESCENCE_CHECK_STAGES: &[Stage] = &[
     Stage::GenerateGoodCaptures,
     Stage::GoodCaptures,
@@ -100,9 +112,11 @@ impl<'a> MovePicker<'a> {
         }
     }
 
-    pub fn qsearch(position: &Position) -> Self {
+    pub fn qsearch(position: &Position, quiet_checks: bool) -> Self {
         let stages = if position.in_check() {
             QUIESCENCE_CHECK_STAGES
+        } else if quiet_checks {
+            QUIESCENCE_QUIET_CHECKS_STAGES
         } else {
             QUIESCENCE_STAGES
         };
@@ -225,15 +239,11 @@ impl<'a> MovePicker<'a> {
                         .copied(),
                 );
                 if let Some(prev_move) = self.previous_move {
-                    if prev_move.is_quiet() {
-                        self.moves.extend(
-                            history.last_best_reply[position.white_to_move as usize]
-                                [prev_move.piece.index()][prev_move.to]
-                                .iter()
-                                .filter(|&&m| position.move_is_pseudo_legal(m))
-                                .copied(),
-                        );
-                    }
+                    self.moves.extend(
+                        history
+                            .get_counter(prev_move)
+                            .filter(|&m| position.move_is_pseudo_legal(m)),
+                    );
                 }
                 self.scores.extend(self.moves.iter().map(|_| 0));
                 self.index = 0;
@@ -269,8 +279,12 @@ impl<'a> MovePicker<'a> {
 
                 MoveGenerator::from(position).quiet_moves(&mut self.moves);
                 let wtm = position.white_to_move;
-                self.scores
-                    .extend(self.moves.iter().map(|&mov| history.get_score(wtm, mov)));
+                let (killers, previous_move) = (self.killers, self.previous_move);
+                self.scores.extend(
+                    self.moves
+                        .iter()
+                        .map(|&mov| history.quiet_move_score(wtm, mov, killers, previous_move)),
+                );
                 self.index = 0;
                 self.stage += 1;
                 self.next(position, history)
@@ -292,6 +306,30 @@ impl<'a> MovePicker<'a> {
                     self.next(position, history)
                 }
             }
+            Stage::GenerateQuietChecks => {
+                self.moves.clear();
+                self.scores.clear();
+
+                MoveGenerator::from(position).quiet_checks(&mut self.moves);
+                let wtm = position.white_to_move;
+                let (killers, previous_move) = (self.killers, self.previous_move);
+                self.scores.extend(
+                    self.moves
+                        .iter()
+                        .map(|&mov| history.quiet_move_score(wtm, mov, killers, previous_move)),
+                );
+                self.index = 0;
+                self.stage += 1;
+                self.next(position, history)
+            }
+            Stage::QuietChecks => {
+                if let Some(mov) = self.get_move() {
+                    Some((MoveType::Quiet, mov))
+                } else {
+                    self.stage += 1;
+                    self.next(position, history)
+                }
+            }
             Stage::GenerateBadCaptures => {
                 self.index = 0;
                 self.stage += 1;
@@ -17,7 +17,15 @@
 use crate::bitboard::*;
 #[cfg(feature = "fathom")]
 use crate::fathom::BoardState;
+use crate::hash::Hash;
 use crate::movegen::*;
+use crate::types::SquareMap;
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
 
 /// Bit indicating if white can castle kingside.
 pub const CASTLE_WHITE_KSIDE: u8 = 0x1;
@@ -31,6 +39,54 @@ pub const CASTLE_BLACK_KSIDE: u8 = 0x4;
 /// Bit indicating if black can castle queenside.
 pub const CASTLE_BLACK_QSIDE: u8 = 0x8;
 
+/// The side to move or the owner of a piece.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Color {
+    White,
+    Black,
+}
+
+impl Color {
+    /// Index into per-side arrays, which are ordered black first, matching
+    /// `white as usize`.
+    pub fn index(self) -> usize {
+        match self {
+            Color::White => 1,
+            Color::Black => 0,
+        }
+    }
+
+    pub fn opponent(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        }
+    }
+}
+
+impl From<bool> for Color {
+    fn from(white: bool) -> Self {
+        if white {
+            Color::White
+        } else {
+            Color::Black
+        }
+    }
+}
+
+/// Castling rights which remain after a piece moves from or to the respective square.
+#[rustfmt::skip]
+const CASTLE_MASK: SquareMap<u8> = SquareMap::from_array([
+    0xD, 0xF, 0xF, 0xF, 0xC, 0xF, 0xF, 0xE,
+    0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF,
+    0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF,
+    0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF,
+    0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF,
+    0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF,
+    0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF, 0xF,
+    0x7, 0xF, 0xF, 0xF, 0x3, 0xF, 0xF, 0xB,
+]);
+
 /// A `Position` holds all information to completely describe a chess position.
 ///
 /// Position does not implement Copy because moving of Copy types always involves a memcpy and we
@@ -63,6 +119,120 @@ pub struct Position {
     /// The squares the [black, white] king is occupying.
     /// Could be calculate from the `pieces` bitboard, but cached here for speed.
     pub king_sq: [Square; 2],
+
+    /// Whether castling follows the Chess960 rules. Castling moves are then encoded as the king
+    /// capturing its own rook and FENs use Shredder castling letters.
+    pub chess960: bool,
+
+    /// The squares the castling rooks start on, in the order of the castling right bits (white
+    /// kingside, white queenside, black kingside, black queenside).
+    pub castling_rooks: [Square; 4],
+}
+
+/// Index of the castling right of the given side into `Position::castling_rooks`. The right's
+/// bit is `1 << index`.
+fn castling_index(white: bool, kingside: bool) -> usize {
+    2 * (!white as usize) + !kingside as usize
+}
+
+/// All squares on the rank between `a` and `b`, both included.
+fn rank_span(a: Square, b: Square) -> Bitboard {
+    let a: u8 = a.into();
+    let b: u8 = b.into();
+    let (low, high) = if a < b { (a, b) } else { (b, a) };
+    Bitboard::from((u64::MAX >> (63 - high)) & (u64::MAX << low))
+}
+
+/// Returns the king destination, the rook origin and the rook destination of a castling move.
+/// The king always ends up on the g or c file and the rook next to it on the f or d file.
+pub fn castling_squares(mov: Move, castling_rooks: &[Square; 4]) -> (Square, Square, Square) {
+    let rank = mov.from.rank();
+    let kingside = mov.is_kingside_castle();
+    let (king_file, rook_file) = if kingside { (6, 5) } else { (2, 3) };
+    (
+        Square::file_rank(king_file, rank),
+        castling_rooks[castling_index(rank == 0, kingside)],
+        Square::file_rank(rook_file, rank),
+    )
+}
+
+/// Receives the piece changes of moves made with `Position::make_move_with`, so that features
+/// of the position (like the piece-square table scores, or the input layer of a neural network)
+/// can be updated incrementally instead of being recomputed from scratch.
+pub trait FeatureAccumulator {
+    fn add_piece(&mut self, piece: Piece, white: bool, sq: Square);
+
+    fn remove_piece(&mut self, piece: Piece, white: bool, sq: Square);
+
+    fn move_piece(&mut self, piece: Piece, white: bool, from: Square, to: Square) {
+        self.remove_piece(piece, white, from);
+        self.add_piece(piece, white, to);
+    }
+}
+
+/// Passes the inverse of every change on to the wrapped accumulator, for unmaking moves.
+struct Undo<'a, A>(&'a mut A);
+
+impl<'a, A: FeatureAccumulator> FeatureAccumulator for Undo<'a, A> {
+    fn add_piece(&mut self, piece: Piece, white: bool, sq: Square) {
+        self.0.remove_piece(piece, white, sq);
+    }
+
+    fn remove_piece(&mut self, piece: Piece, white: bool, sq: Square) {
+        self.0.add_piece(piece, white, sq);
+    }
+
+    fn move_piece(&mut self, piece: Piece, white: bool, from: Square, to: Square) {
+        self.0.move_piece(piece, white, to, from);
+    }
+}
+
+/// Reports the piece changes of `mov`, played by the given side, to `accumulator`.
+fn accumulate_move<A: FeatureAccumulator>(
+    mov: Move,
+    white: bool,
+    castling_rooks: &[Square; 4],
+    accumulator: &mut A,
+) {
+    if mov.castling {
+        let (king_to, rook_from, rook_to) = castling_squares(mov, castling_rooks);
+        accumulator.move_piece(Piece::King, white, mov.from, king_to);
+        accumulator.move_piece(Piece::Rook, white, rook_from, rook_to);
+        return;
+    }
+
+    if let Some(captured) = mov.captured {
+        let captured_sq = if mov.en_passant {
+            mov.to.backward(white, 1)
+        } else {
+            mov.to
+        };
+        accumulator.remove_piece(captured, !white, captured_sq);
+    }
+
+    if let Some(promoted) = mov.promoted {
+        accumulator.remove_piece(Piece::Pawn, white, mov.from);
+        accumulator.add_piece(promoted, white, mov.to);
+    } else {
+        accumulator.move_piece(mov.piece, white, mov.from, mov.to);
+    }
+}
+
+/// The number of pieces of each type and color, indexed by `Piece::index()`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct MaterialCount {
+    pub white: [u8; 6],
+    pub black: [u8; 6],
+}
+
+impl MaterialCount {
+    pub fn get(&self, piece: Piece, white: bool) -> u8 {
+        if white {
+            self.white[piece.index()]
+        } else {
+            self.black[piece.index()]
+        }
+    }
 }
 
 /// Some not easily reverted changes in a position.
@@ -112,6 +282,10 @@ impl Position {
         self.bb[Piece::King.index()]
     }
 
+    pub fn side_to_move(&self) -> Color {
+        Color::from(self.white_to_move)
+    }
+
     pub fn king_sq(&self, white: bool) -> Square {
         self.king_sq[white as usize]
     }
@@ -132,6 +306,27 @@ impl Position {
         self.pieces[1 - white as usize]
     }
 
+    /// Returns the pieces of the given type and color.
+    pub fn pieces_of(&self, piece: Piece, white: bool) -> Bitboard {
+        self.bb[piece.index()] & self.us(white)
+    }
+
+    /// Returns the number of pieces of the given type and color.
+    pub fn piece_count(&self, piece: Piece, white: bool) -> u32 {
+        self.pieces_of(piece, white).popcount() as u32
+    }
+
+    /// Counts the pieces of both sides. Cheaper than building an `Eval`
+    /// when only the material is of interest.
+    pub fn count_material(&self) -> MaterialCount {
+        let mut count = MaterialCount::default();
+        for &piece in &Piece::all() {
+            count.white[piece.index()] = self.pieces_of(piece, true).popcount() as u8;
+            count.black[piece.index()] = self.pieces_of(piece, false).popcount() as u8;
+        }
+        count
+    }
+
     pub fn see(&self, mov: Move, threshold: i16) -> bool {
         let mut score = mov.captured.map_or(0, Piece::see_value)
             + mov
@@ -152,27 +347,19 @@ impl Position {
 
         let mut white = !self.white_to_move;
         let mut occupancy = self.all_pieces & !(mov.from.to_bb() | mov.to.to_bb());
+        // The captured pawn is not on the target square. En passant can only
+        // be the first capture of an exchange, since the right expires with
+        // the opponent's reply, so this is the only place to handle it.
         if mov.en_passant {
             occupancy ^= mov.to.backward(self.white_to_move, 1);
         }
 
         let promotion = mov.to.rank() == 0 || mov.to.rank() == 7;
 
-        let to_bb = mov.to.to_bb();
         let bq = self.bishops() | self.queens();
         let rq = self.rooks() | self.queens();
 
-        let mut attackers = Bitboard::from(0);
-        attackers |= (to_bb.left(1) | to_bb.right(1)).backward(false, 1)
-            & self.pawns()
-            & self.black_pieces();
-        attackers |=
-            (to_bb.left(1) | to_bb.right(1)).backward(true, 1) & self.pawns() & self.white_pieces();
-        attackers |= KNIGHT_ATTACKS[mov.to] & self.knights();
-        attackers |= get_bishop_attacks_from(mov.to, occupancy) & bq;
-        attackers |= get_rook_attacks_from(mov.to, occupancy) & rq;
-        attackers |= KING_ATTACKS[mov.to] & self.kings();
-        attackers &= occupancy;
+        let mut attackers = self.attackers_to(mov.to, occupancy) & occupancy;
 
         if next_victim == Piece::King {
             // SEE test is successful if king cannot be recaptured since currently score >= 0 (see above)
@@ -258,47 +445,173 @@ impl Position {
         self.white_to_move != white
     }
 
+    /// Returns the pieces of either color which attack `sq`, with sliders
+    /// blocked by `occupancy`.
+    pub fn attackers_to(&self, sq: Square, occupancy: Bitboard) -> Bitboard {
+        (PAWN_ATTACKS[1][sq] & self.pawns() & self.black_pieces())
+            | (PAWN_ATTACKS[0][sq] & self.pawns() & self.white_pieces())
+            | (KNIGHT_ATTACKS[sq] & self.knights())
+            | (KING_ATTACKS[sq] & self.kings())
+            | (get_bishop_attacks_from(sq, occupancy) & (self.bishops() | self.queens()))
+            | (get_rook_attacks_from(sq, occupancy) & (self.rooks() | self.queens()))
+    }
+
     fn is_attacked(&self, sq: Square) -> bool {
-        let them = self.them(self.white_to_move);
-        let mg = MoveGenerator::from(self);
-        let bishop_attacks: Bitboard =
-            get_bishop_attacks_from(sq, self.all_pieces) & (self.bishops() | self.queens()) & them;
-        if bishop_attacks.at_least_one() {
-            return true;
+        (self.attackers_to(sq, self.all_pieces) & self.them(self.white_to_move)).at_least_one()
+    }
+
+    /// Checks whether the side to move still has the right to castle to the given side, its rook
+    /// is in place and all squares the king and the rook have to cross are empty. Attacks on the
+    /// king's path are left to `move_is_legal`.
+    pub fn can_castle(&self, kingside: bool) -> bool {
+        let index = castling_index(self.white_to_move, kingside);
+        let rook = self.castling_rooks[index];
+        if self.details.castling & (1 << index) == 0
+            || !(self.rooks() & self.us(self.white_to_move) & rook)
+        {
+            return false;
         }
 
-        let rook_attacks: Bitboard =
-            get_rook_attacks_from(sq, self.all_pieces) & (self.rooks() | self.queens()) & them;
-        if rook_attacks.at_least_one() {
-            return true;
+        let king = self.king_sq(self.white_to_move);
+        let (king_to, _, rook_to) = self.castling_squares(self.castling_move(kingside));
+        let path = (rank_span(king, king_to) | rank_span(rook, rook_to)) & !king.to_bb();
+        (path & !rook.to_bb() & self.all_pieces).is_empty()
+    }
+
+    /// Checks whether some castling right belongs to a king or rook which doesn't stand on its
+    /// standard square, so castling needs the Chess960 rules.
+    pub fn needs_chess960_castling(&self) -> bool {
+        (0..4).any(|index| {
+            let white = index < 2;
+            let standard_file = if index % 2 == 0 { 7 } else { 0 };
+            self.details.castling & (1 << index) != 0
+                && (self.king_sq[white as usize].file() != 4
+                    || self.castling_rooks[index].file() != standard_file)
+        })
+    }
+
+    /// The castling move of the side to move. The king moves to its destination square in
+    /// standard chess and captures its own rook in Chess960.
+    pub fn castling_move(&self, kingside: bool) -> Move {
+        let from = self.king_sq(self.white_to_move);
+        let to = if self.chess960 {
+            self.castling_rooks[castling_index(self.white_to_move, kingside)]
+        } else if kingside {
+            from.right(2)
+        } else {
+            from.left(2)
+        };
+
+        Move {
+            from,
+            to,
+            piece: Piece::King,
+            captured: None,
+            promoted: None,
+            en_passant: false,
+            castling: true,
         }
+    }
 
-        let knight_attacks: Bitboard = mg.knight_from(sq) & self.knights() & them;
-        if knight_attacks.at_least_one() {
-            return true;
+    /// Recognizes a king move from `from` to `to` as castling, written either as the king moving
+    /// two squares or as the king capturing its own rook, and returns it in this position's
+    /// encoding.
+    pub fn castling_move_to(&self, from: Square, to: Square) -> Option<Move> {
+        if from != self.king_sq(self.white_to_move) || from.rank() != to.rank() {
+            return None;
         }
 
-        let pawn_right: bool = (self.pawns() & them)
-            .backward(self.white_to_move, 1)
-            .left(1)
-            & sq;
-        if pawn_right {
-            return true;
+        let kingside = to.file() > from.file();
+        let index = castling_index(self.white_to_move, kingside);
+        let takes_rook =
+            to == self.castling_rooks[index] && self.rooks() & self.us(self.white_to_move) & to;
+        let two_squares = !self.chess960 && from.chebyshev_distance(to) == 2;
+        if takes_rook || two_squares {
+            Some(self.castling_move(kingside))
+        } else {
+            None
         }
+    }
 
-        let pawn_left: bool = (self.pawns() & them)
-            .backward(self.white_to_move, 1)
-            .right(1)
-            & sq;
-        if pawn_left {
-            return true;
+    /// See `castling_squares`.
+    pub fn castling_squares(&self, mov: Move) -> (Square, Square, Square) {
+        castling_squares(mov, &self.castling_rooks)
+    }
+
+    /// The castling rights which remain after `mov`.
+    pub fn castling_after(&self, mov: Move) -> u8 {
+        let mut castling = self.details.castling;
+        if !self.chess960 || castling == 0 {
+            return castling & CASTLE_MASK[mov.from] & CASTLE_MASK[mov.to];
         }
 
-        if (mg.king_from(sq) & self.kings() & them).at_least_one() {
+        if mov.piece == Piece::King {
+            castling &= if self.white_to_move {
+                CASTLE_BLACK_KSIDE | CASTLE_BLACK_QSIDE
+            } else {
+                CASTLE_WHITE_KSIDE | CASTLE_WHITE_QSIDE
+            };
+        }
+
+        for (index, &rook) in self.castling_rooks.iter().enumerate() {
+            if mov.from == rook || mov.to == rook {
+                castling &= !(1 << index);
+            }
+        }
+
+        castling
+    }
+
+    /// Checks whether the current position, whose hash is the last entry of
+    /// `history`, occurs at least `count` times in `history`. Only positions
+    /// since the last capture or pawn move are looked at, earlier ones cannot
+    /// repeat. Use a count of 2 inside the search and 3 for claiming draws.
+    pub fn is_repetition(&self, history: &[Hash], count: usize) -> bool {
+        let current = match history.last() {
+            Some(hash) => hash,
+            None => return false,
+        };
+
+        history
+            .iter()
+            .rev()
+            .take(self.details.halfmove as usize + 1)
+            .step_by(2)
+            .filter(|&hash| hash == current)
+            .nth(count.saturating_sub(1))
+            .is_some()
+    }
+
+    /// Checks for a draw by the fifty moves rule. The rule doesn't apply if
+    /// the side to move has no legal moves, a checkmate on the hundredth
+    /// halfmove still wins (and a stalemate is a draw anyway).
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.details.halfmove >= 100 && !self.legal_moves().is_empty()
+    }
+
+    /// Like `is_fifty_move_draw`, for callers which already know whether the
+    /// side to move has a legal move and don't want to generate them again.
+    pub fn is_fifty_move_draw_with_moves(&self, has_legal_moves: bool) -> bool {
+        self.details.halfmove >= 100 && has_legal_moves
+    }
+
+    /// Checks whether neither side has enough material left to ever mate:
+    /// bare kings, a single minor piece, or only bishops which all stand on
+    /// squares of the same color. Unlike `Eval::is_material_draw` this does
+    /// not consider positions which are merely impossible to win by force.
+    pub fn is_insufficient_material(&self) -> bool {
+        if (self.pawns() | self.rooks() | self.queens()).at_least_one() {
+            return false;
+        }
+
+        let minors = self.knights() | self.bishops();
+        if minors.popcount() <= 1 {
             return true;
         }
 
-        false
+        self.knights().is_empty()
+            && ((self.bishops() & LIGHT_SQUARES).is_empty()
+                || (self.bishops() & !LIGHT_SQUARES).is_empty())
     }
 
     /// Checks whether the current side to move is in check.
@@ -306,46 +619,60 @@ impl Position {
         self.details.checkers.at_least_one()
     }
 
+    /// Returns our pieces which stand between one of our sliders and the
+    /// enemy king. Moving such a piece off the line gives discovered check.
+    pub fn discovered_check_candidates(&self) -> Bitboard {
+        let us = self.us(self.white_to_move);
+        let king = self.king_sq(!self.white_to_move);
+        let bishops = (self.bishops() | self.queens()) & us;
+        let rooks = (self.rooks() | self.queens()) & us;
+
+        let bishop_blockers = get_bishop_attacks_from(king, self.all_pieces) & us;
+        let rook_blockers = get_rook_attacks_from(king, self.all_pieces) & us;
+
+        let bishop_snipers =
+            get_bishop_attacks_from(king, self.all_pieces ^ bishop_blockers) & bishops;
+        let rook_snipers = get_rook_attacks_from(king, self.all_pieces ^ rook_blockers) & rooks;
+
+        let mut candidates = Bitboard::from(0);
+        for sniper in bishop_snipers.squares() {
+            candidates |= get_bishop_attacks_from(sniper, self.all_pieces) & bishop_blockers;
+        }
+        for sniper in rook_snipers.squares() {
+            candidates |= get_rook_attacks_from(sniper, self.all_pieces) & rook_blockers;
+        }
+
+        candidates
+    }
+
     pub fn move_is_legal(&self, mov: Move) -> bool {
         let mut all_pieces = self.all_pieces;
         let mut king = self.king_sq(self.white_to_move);
         let mut them = self.them(self.white_to_move) & all_pieces;
 
-        if mov.piece == Piece::King {
-            king = mov.to;
+        if mov.castling {
+            let (king_to, rook_from, rook_to) = self.castling_squares(mov);
 
-            if mov.is_queenside_castle() {
-                // Queenside castling
-                if self.is_attacked(mov.from) || self.is_attacked(mov.from.left(1)) {
+            // The king may not castle out of or through check, its destination is checked below
+            for sq in rank_span(mov.from, king_to).squares() {
+                if sq != king_to && self.is_attacked(sq) {
                     return false;
                 }
+            }
 
-                all_pieces ^= mov.from;
-                all_pieces ^= mov.to;
-
-                // Rook movement
-                all_pieces ^= mov.to.left(2);
-                all_pieces ^= mov.to.right(1);
-            } else if mov.is_kingside_castle() {
-                // Kingside castling
-                if self.is_attacked(mov.from) || self.is_attacked(mov.from.right(1)) {
-                    return false;
-                }
+            king = king_to;
+            all_pieces ^= mov.from;
+            all_pieces ^= rook_from;
+            all_pieces |= king_to;
+            all_pieces |= rook_to;
+        } else if mov.piece == Piece::King {
+            king = mov.to;
+            all_pieces ^= mov.from;
 
-                all_pieces ^= mov.from;
+            if mov.captured.is_none() {
                 all_pieces ^= mov.to;
-
-                // Rook movement
-                all_pieces ^= mov.to.right(1);
-                all_pieces ^= mov.to.left(1);
             } else {
-                all_pieces ^= mov.from;
-
-                if mov.captured.is_none() {
-                    all_pieces ^= mov.to;
-                } else {
-                    them ^= mov.to;
-                }
+                them ^= mov.to;
             }
         } else if mov.en_passant {
             all_pieces ^= mov.from;
@@ -441,16 +768,13 @@ impl Position {
                 rooks |= mov.to;
             }
             Piece::King => {
-                if mov.is_kingside_castle() {
-                    rooks ^= mov.to.right(1);
-                    rooks |= mov.to.left(1);
-                    all_pieces ^= mov.to.right(1);
-                    all_pieces |= mov.to.left(1);
-                } else if mov.is_queenside_castle() {
-                    rooks ^= mov.to.left(2);
-                    rooks |= mov.to.right(1);
-                    all_pieces ^= mov.to.left(2);
-                    all_pieces |= mov.to.right(1);
+                if mov.castling {
+                    let (king_to, rook_from, rook_to) = self.castling_squares(mov);
+                    rooks ^= rook_from;
+                    rooks |= rook_to;
+                    all_pieces = self.all_pieces ^ mov.from.to_bb() ^ rook_from.to_bb();
+                    all_pieces |= king_to;
+                    all_pieces |= rook_to;
                 }
             }
         }
@@ -477,6 +801,12 @@ impl Position {
 
     /// Applies `mov` to the current board position.
     pub fn make_move(&mut self, mov: Move) {
+        debug_assert!(
+            self.move_is_pseudo_legal(mov),
+            "Move {} is not pseudo-legal",
+            mov.to_algebraic()
+        );
+
         let them = self.them(self.white_to_move);
         let rank2 = if self.white_to_move { 1 } else { 6 };
         let rank4 = if self.white_to_move { 3 } else { 4 };
@@ -490,8 +820,28 @@ impl Position {
             self.details.en_passant = mov.from.file();
         }
 
-        self.details.halfmove += 1;
+        self.details.halfmove = self.details.halfmove.saturating_add(1);
+
+        if mov.castling {
+            self.toggle_castling(mov, self.white_to_move);
+            self.king_sq[self.side_to_move().index()] = self.castling_squares(mov).0;
+        } else {
+            self.move_piece(mov);
+        }
+
+        self.details.castling = self.castling_after(mov);
 
+        if !self.white_to_move {
+            self.fullmove += 1;
+        }
+
+        self.white_to_move = !self.white_to_move;
+
+        self.details.checkers = self.compute_checkers();
+    }
+
+    /// Moves the pieces of a move other than castling, the board part of `make_move`.
+    fn move_piece(&mut self, mov: Move) {
         self.bb[mov.piece.index()] ^= mov.from;
 
         if let Some(piece) = mov.captured {
@@ -521,68 +871,35 @@ impl Position {
                 self.details.halfmove = 0;
             }
             Piece::King => {
-                self.king_sq[self.white_to_move as usize] = mov.to;
-                if mov.is_kingside_castle() {
-                    // castle kingside
-                    self.bb[Piece::Rook.index()] ^= mov.to.right(1);
-                    self.bb[Piece::Rook.index()] ^= mov.to.left(1);
-                    if self.white_to_move {
-                        self.color ^= mov.to.right(1);
-                        self.color ^= mov.to.left(1);
-                    }
-                } else if mov.is_queenside_castle() {
-                    // castle queenside
-                    self.bb[Piece::Rook.index()] ^= mov.to.left(2);
-                    self.bb[Piece::Rook.index()] ^= mov.to.right(1);
-                    if self.white_to_move {
-                        self.color ^= mov.to.left(2);
-                        self.color ^= mov.to.right(1);
-                    }
-                }
-
-                if self.white_to_move {
-                    self.details.castling &= !(CASTLE_WHITE_KSIDE | CASTLE_WHITE_QSIDE);
-                } else {
-                    self.details.castling &= !(CASTLE_BLACK_KSIDE | CASTLE_BLACK_QSIDE);
-                }
+                self.king_sq[self.side_to_move().index()] = mov.to;
             }
             _ => {}
         }
 
-        if mov.from == SQUARE_A1 || mov.to == SQUARE_A1 {
-            self.details.castling &= !CASTLE_WHITE_QSIDE;
-        }
-
-        if mov.from == SQUARE_H1 || mov.to == SQUARE_H1 {
-            self.details.castling &= !CASTLE_WHITE_KSIDE;
-        }
-
-        if mov.from == SQUARE_A8 || mov.to == SQUARE_A8 {
-            self.details.castling &= !CASTLE_BLACK_QSIDE;
-        }
-
-        if mov.from == SQUARE_H8 || mov.to == SQUARE_H8 {
-            self.details.castling &= !CASTLE_BLACK_KSIDE;
-        }
-
         if self.white_to_move {
             self.color ^= mov.to;
             self.color ^= mov.from;
-        } else {
-            self.fullmove += 1;
         }
 
-        self.white_to_move = !self.white_to_move;
-        self.all_pieces = self.pawns()
-            | self.knights()
-            | self.bishops()
-            | self.rooks()
-            | self.queens()
-            | self.kings();
-        self.pieces[1] = self.all_pieces & self.color;
-        self.pieces[0] = self.all_pieces & !self.color;
+        self.update_occupancy(mov, self.white_to_move);
+    }
+
+    /// Like `make_move`, but also reports the piece changes to `accumulator`.
+    pub fn make_move_with<A: FeatureAccumulator>(&mut self, mov: Move, accumulator: &mut A) {
+        accumulate_move(mov, self.white_to_move, &self.castling_rooks, accumulator);
+        self.make_move(mov);
+    }
 
-        self.update_checkers();
+    /// Like `unmake_move`, but also reverts the piece changes in `accumulator`.
+    pub fn unmake_move_with<A: FeatureAccumulator>(
+        &mut self,
+        mov: Move,
+        irreversible_details: IrreversibleDetails,
+        accumulator: &mut A,
+    ) {
+        let white = !self.white_to_move;
+        accumulate_move(mov, white, &self.castling_rooks, &mut Undo(accumulator));
+        self.unmake_move(mov, irreversible_details);
     }
 
     /// Undoes a previously made move (by `self.make_move(mov)`).
@@ -591,11 +908,19 @@ impl Position {
         self.white_to_move = !self.white_to_move;
         let unmaking_white_move = self.white_to_move;
 
+        if !unmaking_white_move {
+            self.fullmove -= 1;
+        }
+
+        if mov.castling {
+            self.toggle_castling(mov, unmaking_white_move);
+            self.king_sq[unmaking_white_move as usize] = mov.from;
+            return;
+        }
+
         if unmaking_white_move {
             self.color ^= mov.from;
             self.color ^= mov.to;
-        } else {
-            self.fullmove -= 1;
         }
 
         self.bb[mov.piece.index()] ^= mov.from;
@@ -622,31 +947,48 @@ impl Position {
 
         if mov.piece == Piece::King {
             self.king_sq[unmaking_white_move as usize] = mov.from;
-            if mov.is_kingside_castle() {
-                self.bb[Piece::Rook.index()] ^= mov.to.right(1);
-                self.bb[Piece::Rook.index()] ^= mov.to.left(1);
-                if unmaking_white_move {
-                    self.color ^= mov.to.right(1);
-                    self.color ^= mov.to.left(1);
-                }
-            } else if mov.is_queenside_castle() {
-                self.bb[Piece::Rook.index()] ^= mov.to.left(2);
-                self.bb[Piece::Rook.index()] ^= mov.to.right(1);
-                if unmaking_white_move {
-                    self.color ^= mov.to.left(2);
-                    self.color ^= mov.to.right(1);
-                }
-            }
         }
 
-        self.all_pieces = self.pawns()
-            | self.knights()
-            | self.bishops()
-            | self.rooks()
-            | self.queens()
-            | self.kings();
-        self.pieces[1] = self.all_pieces & self.color;
-        self.pieces[0] = self.all_pieces & !self.color;
+        self.update_occupancy(mov, unmaking_white_move);
+    }
+
+    /// Toggles the squares `mov` changes in `all_pieces` and `pieces`. Every
+    /// change is an XOR, so the same update both makes and unmakes the move.
+    fn update_occupancy(&mut self, mov: Move, white: bool) {
+        let us = white as usize;
+        let them = 1 - us;
+
+        let from_to = mov.from.to_bb() | mov.to.to_bb();
+        self.pieces[us] ^= from_to;
+
+        if mov.en_passant {
+            let captured = mov.to.backward(white, 1);
+            self.pieces[them] ^= captured;
+            self.all_pieces ^= from_to;
+            self.all_pieces ^= captured;
+        } else if mov.captured.is_some() {
+            self.pieces[them] ^= mov.to;
+            self.all_pieces ^= mov.from;
+        } else {
+            self.all_pieces ^= from_to;
+        }
+    }
+
+    /// Toggles the king and the rook of the castling move `mov` on all boards. Like
+    /// `update_occupancy` this both makes and unmakes the move, and it works when the king or
+    /// the rook stay in place or swap squares.
+    fn toggle_castling(&mut self, mov: Move, white: bool) {
+        let (king_to, rook_from, rook_to) = self.castling_squares(mov);
+        let king = mov.from.to_bb() ^ king_to.to_bb();
+        let rook = rook_from.to_bb() ^ rook_to.to_bb();
+
+        self.bb[Piece::King.index()] ^= king;
+        self.bb[Piece::Rook.index()] ^= rook;
+        self.pieces[white as usize] ^= king ^ rook;
+        self.all_pieces ^= king ^ rook;
+        if white {
+            self.color ^= king ^ rook;
+        }
     }
 
     /// Applies a null move (no move, just change side to move) allowing one side to make two
@@ -655,7 +997,7 @@ impl Position {
         self.white_to_move = !self.white_to_move;
         self.details.checkers = Bitboard::from(0);
         self.details.en_passant = 255;
-        self.details.halfmove += 1;
+        self.details.halfmove = self.details.halfmove.saturating_add(1);
     }
 
     /// Undoes a previous null move.
@@ -695,6 +1037,12 @@ impl Position {
             return false;
         }
 
+        // Castling is checked as a whole, in Chess960 its target is our own rook
+        if mov.castling {
+            let kingside = mov.is_kingside_castle();
+            return mov == self.castling_move(kingside) && self.can_castle(kingside);
+        }
+
         // Check target square is not occupied by us
         if us & mov.to {
             return false;
@@ -710,6 +1058,18 @@ impl Position {
             return false;
         }
 
+        // Kings are never captured and en passant always takes a pawn
+        if mov.captured == Some(Piece::King)
+            || (mov.en_passant && mov.captured != Some(Piece::Pawn))
+        {
+            return false;
+        }
+
+        // Pawns can only promote to a minor or major piece
+        if mov.promoted == Some(Piece::Pawn) || mov.promoted == Some(Piece::King) {
+            return false;
+        }
+
         // Check for en passant and promotion only when it's a pawn move
         if mov.piece != Piece::Pawn && (mov.en_passant || mov.promoted.is_some()) {
             return false;
@@ -745,11 +1105,7 @@ impl Position {
                     return false;
                 }
 
-                if (RANK_1 | RANK_8) & mov.to {
-                    return mov.promoted.is_some();
-                }
-
-                true
+                ((RANK_1 | RANK_8) & mov.to) == mov.promoted.is_some()
             }
             Piece::Knight => KNIGHT_ATTACKS[mov.from] & mov.to,
             Piece::Bishop => get_bishop_attacks_from(mov.from, self.all_pieces) & mov.to,
@@ -759,156 +1115,212 @@ impl Position {
                     | get_rook_attacks_from(mov.from, self.all_pieces))
                     & mov.to
             }
-            Piece::King => {
-                if mov.is_kingside_castle() {
-                    if self.white_to_move {
-                        return (self.details.castling & CASTLE_WHITE_KSIDE) > 0
-                            && (self.all_pieces & Bitboard::from(0x00_00_00_00_00_00_00_60))
-                                .is_empty()
-                            && (self.rooks() & us & SQUARE_H1);
+            Piece::King => KING_ATTACKS[mov.from] & mov.to,
+        }
+    }
+
+    /// All enemy pieces attacking the king of the side to move, which is what
+    /// `IrreversibleDetails::checkers` caches.
+    pub fn compute_checkers(&self) -> Bitboard {
+        let them = self.them(self.white_to_move);
+        let king = self.king_sq(self.white_to_move);
+
+        self.attackers_to(king, self.all_pieces) & them
+    }
+
+    /// Generates all pseudo-legal moves in the current position, i.e. moves
+    /// which may still leave our king in check.
+    pub fn pseudo_legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        MoveGenerator::from(self).all_moves(&mut moves);
+        moves
+    }
+
+    /// Generates all legal moves in the current position.
+    pub fn legal_moves(&self) -> MoveList {
+        MoveGenerator::from(self).legal_moves()
+    }
+
+    /// Counts the leaf nodes of the legal move tree up to `depth`. Moves are
+    /// generated into `buffers`, one per remaining depth, which are reused
+    /// between calls so the tree walk itself doesn't allocate.
+    pub fn perft_fast(&mut self, depth: usize, buffers: &mut Vec<Vec<Move>>) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        while buffers.len() < depth {
+            buffers.push(Vec::with_capacity(256));
+        }
+
+        let mut moves = core::mem::take(&mut buffers[depth - 1]);
+        moves.clear();
+        MoveGenerator::from(&*self).all_moves(&mut moves);
+
+        let mut nodes = 0;
+        for &mov in &moves {
+            if !self.move_is_legal(mov) {
+                continue;
+            }
+
+            if depth == 1 {
+                nodes += 1;
+                continue;
+            }
+
+            let details = self.details;
+            self.make_move(mov);
+            nodes += self.perft_fast(depth - 1, buffers);
+            self.unmake_move(mov, details);
+        }
+
+        buffers[depth - 1] = moves;
+        nodes
+    }
+
+    /// Returns all legal moves in standard algebraic notation.
+    pub fn legal_moves_san(&mut self) -> Vec<String> {
+        self.legal_moves()
+            .into_iter()
+            .map(|mov| mov.to_san(self))
+            .collect()
+    }
+
+    /// Prints the board state, prefixing every line with `pre`.
+    #[cfg(feature = "std")]
+    pub fn print(&self, pre: &str) {
+        for line in self.to_ascii().lines() {
+            println!("{}{}", pre, line);
+        }
+    }
+
+    /// Writes the position in Forsyth-Edwards notation.
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for rank in (0..8).rev() {
+            let mut empty = 0;
+            for file in 0..8 {
+                let sq = Square::file_rank(file, rank);
+                if let Some(piece) = self.find_piece(sq) {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    if self.color & sq {
+                        fen.push(piece.to_char().to_ascii_uppercase());
                     } else {
-                        return (self.details.castling & CASTLE_BLACK_KSIDE) > 0
-                            && (self.all_pieces & Bitboard::from(0x60_00_00_00_00_00_00_00))
-                                .is_empty()
-                            && (self.rooks() & us & SQUARE_H8);
+                        fen.push(piece.to_char());
                     }
+                } else {
+                    empty += 1;
                 }
+            }
+            if empty > 0 {
+                fen.push_str(&empty.to_string());
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
 
-                if mov.is_queenside_castle() {
-                    if self.white_to_move {
-                        return (self.details.castling & CASTLE_WHITE_QSIDE) > 0
-                            && (self.all_pieces & Bitboard::from(0x00_00_00_00_00_00_00_0E))
-                                .is_empty()
-                            && (self.rooks() & us & SQUARE_A1);
+        fen.push_str(if self.white_to_move { " w " } else { " b " });
+
+        if self.details.castling == 0 {
+            fen.push('-');
+        }
+        for (index, &c) in ['K', 'Q', 'k', 'q'].iter().enumerate() {
+            if self.details.castling & (1 << index) > 0 {
+                if self.chess960 {
+                    // Shredder-FEN, the file of the rook in the case of the side
+                    let file = (b'a' + self.castling_rooks[index].file()) as char;
+                    fen.push(if index < 2 {
+                        file.to_ascii_uppercase()
                     } else {
-                        return (self.details.castling & CASTLE_BLACK_QSIDE) > 0
-                            && (self.all_pieces & Bitboard::from(0x0E_00_00_00_00_00_00_00))
-                                .is_empty()
-                            && (self.rooks() & us & SQUARE_A8);
-                    }
+                        file
+                    });
+                } else {
+                    fen.push(c);
                 }
-
-                KING_ATTACKS[mov.from] & mov.to
             }
         }
-    }
 
-    fn update_checkers(&mut self) {
-        let them = self.them(self.white_to_move);
-        let king = self.king_sq(self.white_to_move);
+        if self.details.en_passant == 255 {
+            fen.push_str(" -");
+        } else {
+            fen.push(' ');
+            fen.push((b'a' + self.details.en_passant) as char);
+            fen.push(if self.white_to_move { '6' } else { '3' });
+        }
 
-        self.details.checkers = Bitboard::from(0);
-        self.details.checkers |= (king.to_bb().left(1) | king.to_bb().right(1))
-            .forward(self.white_to_move, 1)
-            & them
-            & self.pawns();
-        self.details.checkers |= KNIGHT_ATTACKS[king] & them & self.knights();
-        self.details.checkers |= get_bishop_attacks_from(king, self.all_pieces)
-            & them
-            & (self.bishops() | self.queens());
-        self.details.checkers |=
-            get_rook_attacks_from(king, self.all_pieces) & them & (self.rooks() | self.queens());
-    }
-
-    /// Prints the board state.
-    pub fn print(&self, pre: &str) {
-        println!("{}     a b c d e f g h", pre);
-        println!("{}   +-----------------+", pre);
+        fen.push_str(&format!(" {} {}", self.details.halfmove, self.fullmove));
+        fen
+    }
+
+    /// Renders the board state as a multi-line string.
+    pub fn to_ascii(&self) -> String {
+        let mut ascii = String::new();
+        ascii.push_str("     a b c d e f g h\n");
+        ascii.push_str("   +-----------------+\n");
         for rank in 0..8 {
-            print!("{} {} | ", pre, 8 - rank);
+            ascii.push_str(&format!(" {} | ", 8 - rank));
             for file in 0..8 {
                 let sq = Square::file_rank(file, 7 - rank);
                 match self.find_piece(sq) {
-                    Some(Piece::Pawn) => {
-                        if self.color & sq {
-                            print!("P ");
-                        } else {
-                            print!("p ");
-                        }
-                    }
-                    Some(Piece::Knight) => {
-                        if self.color & sq {
-                            print!("N ");
-                        } else {
-                            print!("n ");
-                        }
-                    }
-                    Some(Piece::Bishop) => {
+                    Some(piece) => {
                         if self.color & sq {
-                            print!("B ");
+                            ascii.push(piece.to_char().to_ascii_uppercase());
                         } else {
-                            print!("b ");
-                        }
-                    }
-                    Some(Piece::Rook) => {
-                        if self.color & sq {
-                            print!("R ");
-                        } else {
-                            print!("r ");
-                        }
-                    }
-                    Some(Piece::Queen) => {
-                        if self.color & sq {
-                            print!("Q ");
-                        } else {
-                            print!("q ");
-                        }
-                    }
-                    Some(Piece::King) => {
-                        if self.color & sq {
-                            print!("K ");
-                        } else {
-                            print!("k ");
+                            ascii.push(piece.to_char());
                         }
                     }
                     None => {
                         if self.color & sq {
-                            print!("# ");
+                            ascii.push('#');
                         } else if (rank + file) % 2 == 1 {
-                            print!(". ");
+                            ascii.push('.');
                         } else {
-                            print!("  ");
+                            ascii.push(' ');
                         }
                     }
                 }
+                ascii.push(' ');
             }
             if 8 - rank == 1 {
                 if self.white_to_move {
-                    println!("|  White to move");
+                    ascii.push_str("|  White to move\n");
                 } else {
-                    println!("|  Black to move");
+                    ascii.push_str("|  Black to move\n");
                 }
             } else if 8 - rank == 5 {
-                println!("|  Castling rights:");
+                ascii.push_str("|  Castling rights:\n");
             } else if 8 - rank == 4 {
-                print!("|  ");
+                ascii.push_str("|  ");
                 if self.details.castling & CASTLE_WHITE_KSIDE > 0 {
-                    print!("K");
+                    ascii.push('K');
                 }
 
                 if self.details.castling & CASTLE_WHITE_QSIDE > 0 {
-                    print!("Q");
+                    ascii.push('Q');
                 }
 
                 if self.details.castling & CASTLE_BLACK_KSIDE > 0 {
-                    print!("k");
+                    ascii.push('k');
                 }
 
                 if self.details.castling & CASTLE_BLACK_QSIDE > 0 {
-                    print!("q");
+                    ascii.push('q');
                 }
 
-                println!();
+                ascii.push('\n');
             } else {
-                println!("|");
+                ascii.push_str("|\n");
             }
         }
-        println!("{}   +-----------------+", pre);
+        ascii.push_str("   +-----------------+\n");
+        ascii
     }
-}
 
-impl<'a> From<&'a str> for Position {
-    fn from(fen: &'a str) -> Position {
+    pub fn try_from_fen(fen: &str) -> Result<Position, FenError> {
         let mut pos = Position {
             color: Bitboard::from(0x0),
             bb: [Bitboard::from(0x0); 6],
@@ -929,90 +1341,72 @@ impl<'a> From<&'a str> for Position {
 
             // probably wrong but need to initialize value
             king_sq: [SQUARE_E8, SQUARE_E1],
+
+            chess960: false,
+            castling_rooks: [SQUARE_H1, SQUARE_A1, SQUARE_H8, SQUARE_A8],
         };
 
-        let mut split = fen.split(' ').filter(|s| !s.is_empty());
+        // Only the first six fields are used, anything after them is ignored.
+        let mut split = fen.split_whitespace();
 
         let mut file = 0;
         let mut rank = 7;
-        for c in split.next().unwrap().chars() {
-            let sq;
+        for c in split.next().ok_or(FenError::MissingField)?.chars() {
             let piece;
             let white;
             match c {
                 'P' => {
                     piece = Piece::Pawn;
                     white = true;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'N' => {
                     piece = Piece::Knight;
                     white = true;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'B' => {
                     piece = Piece::Bishop;
                     white = true;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'R' => {
                     piece = Piece::Rook;
                     white = true;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'Q' => {
                     piece = Piece::Queen;
                     white = true;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'K' => {
                     piece = Piece::King;
                     white = true;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'p' => {
                     piece = Piece::Pawn;
                     white = false;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'n' => {
                     piece = Piece::Knight;
                     white = false;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'b' => {
                     piece = Piece::Bishop;
                     white = false;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'r' => {
                     piece = Piece::Rook;
                     white = false;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'q' => {
                     piece = Piece::Queen;
                     white = false;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 'k' => {
                     piece = Piece::King;
                     white = false;
-                    sq = Square::file_rank(file, rank);
-                    file += 1;
                 }
                 '/' => {
+                    if file != 8 || rank == 0 {
+                        return Err(FenError::InvalidBoard);
+                    }
                     file = 0;
                     rank -= 1;
                     continue;
@@ -1049,47 +1443,62 @@ impl<'a> From<&'a str> for Position {
                     file += 8;
                     continue;
                 }
-                x => {
-                    panic!("Unexpected character in fen position: {}", x);
-                }
+                x => return Err(FenError::BadPieceChar(x)),
+            }
+
+            if file >= 8 {
+                return Err(FenError::InvalidBoard);
             }
+            let sq = Square::file_rank(file, rank);
+            file += 1;
 
             pos.bb[piece.index()] ^= sq;
             pos.pieces[white as usize] ^= sq;
             pos.color = pos.pieces[1];
         }
 
-        pos.all_pieces = pos.white_pieces() | pos.black_pieces();
-
-        if split.next().unwrap() == "b" {
-            pos.white_to_move = false;
+        if file != 8 || rank != 0 {
+            return Err(FenError::InvalidBoard);
         }
 
-        pos.details.castling = 0;
-        for c in split.next().unwrap().chars() {
-            match c {
-                '-' => break,
-                'K' => pos.details.castling |= CASTLE_WHITE_KSIDE,
-                'Q' => pos.details.castling |= CASTLE_WHITE_QSIDE,
-                'k' => pos.details.castling |= CASTLE_BLACK_KSIDE,
-                'q' => pos.details.castling |= CASTLE_BLACK_QSIDE,
-                x => panic!("Unexpected character in fen castling: {}", x),
-            }
+        if (pos.pawns() & (RANKS[0] | RANKS[7])).at_least_one() {
+            return Err(FenError::PawnOnBackRank);
         }
 
+        pos.all_pieces = pos.white_pieces() | pos.black_pieces();
+
+        // Some tools write the side to move in upper case.
+        pos.white_to_move = match split.next() {
+            Some(side) if side.eq_ignore_ascii_case("w") => true,
+            Some(side) if side.eq_ignore_ascii_case("b") => false,
+            Some(_) => return Err(FenError::InvalidSideToMove),
+            None => return Err(FenError::MissingField),
+        };
+
+        // Castling rights refer to the kings, which are only known at the end
+        let castling = split.next().ok_or(FenError::MissingField)?;
+
         if let Some(en_passant_sq) = split.next() {
             if en_passant_sq != "-" {
-                pos.details.en_passant = match en_passant_sq.chars().nth(0) {
-                    Some('a') => 0,
-                    Some('b') => 1,
-                    Some('c') => 2,
-                    Some('d') => 3,
-                    Some('e') => 4,
-                    Some('f') => 5,
-                    Some('g') => 6,
-                    Some('h') => 7,
-                    Some(x) => panic!("Unexpected character in fen en passant: {}", x),
-                    None => panic!("Expected character for fen en passant"),
+                let bytes = en_passant_sq.as_bytes();
+                let valid = bytes.len() == 2
+                    && (b'a'..=b'h').contains(&bytes[0])
+                    && (bytes[1] == b'3' || bytes[1] == b'6');
+                if !valid {
+                    return Err(FenError::BadEnPassant(en_passant_sq.to_owned()));
+                }
+                pos.details.en_passant = bytes[0] - b'a';
+
+                // Like `make_move`, only keep the en passant square if a pawn
+                // can actually capture there. Otherwise positions which only
+                // differ in an unusable en passant square would hash differently.
+                let rank = if pos.white_to_move { 4 } else { 3 };
+                let pushed_pawn = Square::file_rank(pos.details.en_passant, rank).to_bb();
+                let capturers = (pushed_pawn.left(1) | pushed_pawn.right(1))
+                    & pos.pawns()
+                    & pos.us(pos.white_to_move);
+                if capturers.is_empty() {
+                    pos.details.en_passant = 255;
                 }
             }
         }
@@ -1100,12 +1509,96 @@ impl<'a> From<&'a str> for Position {
         pos.details.halfmove = halfmove;
         pos.fullmove = fullmove;
 
-        pos.king_sq[0] = (pos.kings() & pos.black_pieces()).squares().next().unwrap();
-        pos.king_sq[1] = (pos.kings() & pos.white_pieces()).squares().next().unwrap();
+        for &white in &[false, true] {
+            let kings = pos.pieces_of(Piece::King, white);
+            if kings.is_empty() {
+                return Err(FenError::NoKing);
+            }
+            if kings.more_than_one() {
+                return Err(FenError::TooManyKings);
+            }
+            pos.king_sq[white as usize] = kings.squares().next().unwrap();
+        }
 
-        pos.update_checkers();
+        pos.details.castling = 0;
+        for c in castling.chars() {
+            if c == '-' {
+                break;
+            }
+
+            let white = c.is_ascii_uppercase();
+            let back_rank = if white { 0 } else { 7 };
+            let king = pos.king_sq[white as usize];
+            let rooks = pos.pieces_of(Piece::Rook, white) & RANKS[back_rank as usize];
+
+            // `K` and `Q` stand for the outermost rook on that side, the letters of Shredder-FEN
+            // name the file of the rook.
+            let rook = match c.to_ascii_lowercase() {
+                'k' => rooks
+                    .squares_rev()
+                    .find(|sq| sq.file() > king.file())
+                    .unwrap_or_else(|| Square::file_rank(7, back_rank)),
+                'q' => rooks
+                    .squares()
+                    .find(|sq| sq.file() < king.file())
+                    .unwrap_or_else(|| Square::file_rank(0, back_rank)),
+                file @ 'a'..='h' => {
+                    pos.chess960 = true;
+                    Square::file_rank(file as u8 - b'a', back_rank)
+                }
+                _ => return Err(FenError::BadCastlingChar(c)),
+            };
+
+            // A king off its back rank can never castle again
+            if king.rank() != back_rank || king.file() == rook.file() {
+                continue;
+            }
+
+            let index = castling_index(white, rook.file() > king.file());
+            pos.details.castling |= 1 << index;
+            pos.castling_rooks[index] = rook;
+            if king.file() != 4 || (rook.file() != 0 && rook.file() != 7) {
+                pos.chess960 = true;
+            }
+        }
+
+        pos.details.checkers = pos.compute_checkers();
+
+        Ok(pos)
+    }
+}
+
+/// Reasons why `Position::try_from_fen` rejects a FEN string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    NoKing,
+    TooManyKings,
+    InvalidSideToMove,
+    /// The board, side to move or castling field is missing.
+    MissingField,
+    /// The board field contains a character which is neither a piece, a digit nor '/'.
+    BadPieceChar(char),
+    /// A rank of the board field does not describe exactly eight squares, or
+    /// there are not exactly eight ranks.
+    InvalidBoard,
+    /// There is a pawn on the first or the last rank.
+    PawnOnBackRank,
+    BadCastlingChar(char),
+    BadEnPassant(String),
+}
 
-        pos
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_ascii())
+    }
+}
+
+impl<'a> From<&'a str> for Position {
+    fn from(fen: &'a str) -> Position {
+        match Position::try_from_fen(fen) {
+            Ok(pos) => pos,
+            Err(err) => panic!("Invalid fen '{}': {:?}", fen, err),
+        }
     }
 }
 
@@ -1162,15 +1655,1000 @@ pub const STARTING_POSITION: Position = Position {
     all_pieces: STARTING_ALL,
 
     king_sq: [SQUARE_E8, SQUARE_E1],
+
+    chess960: false,
+    castling_rooks: [SQUARE_H1, SQUARE_A1, SQUARE_H8, SQUARE_A8],
 };
 
 #[cfg(test)]
 mod tests {
     use crate::position::*;
+    use alloc::vec;
     #[test]
     fn test_parse_start_fen() {
         let start_by_fen =
             Position::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
         assert_eq!(start_by_fen, STARTING_POSITION);
     }
+
+    #[test]
+    fn legal_moves_san_in_start_position() {
+        crate::magic::initialize_magics();
+
+        let mut pos = STARTING_POSITION;
+        let moves = pos.legal_moves_san();
+        assert_eq!(moves.len(), 20);
+        for san in &["Nf3", "Nc3", "Na3", "e4", "e3", "a4", "h3"] {
+            assert!(moves.contains(&san.to_string()), "missing {}", san);
+        }
+    }
+
+    #[test]
+    fn legal_moves_san_disambiguation_and_suffixes() {
+        crate::magic::initialize_magics();
+
+        let mut pos = Position::from("4k3/8/8/R7/8/8/8/RN2KN2 w - - 0 1");
+        let moves = pos.legal_moves_san();
+        for san in &["Nbd2", "Nfd2", "Nc3", "Ng3", "R1a3", "R5a3", "Ra8+"] {
+            assert!(moves.contains(&san.to_string()), "missing {}", san);
+        }
+
+        let mut pos = Position::from("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1");
+        assert!(pos.legal_moves_san().contains(&"Ra8#".to_string()));
+
+        let mut pos = Position::from("4k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        let moves = pos.legal_moves_san();
+        assert!(moves.contains(&"exd6".to_string()));
+        assert!(moves.contains(&"a8=Q+".to_string()));
+        assert!(moves.contains(&"a8=N".to_string()));
+    }
+
+    #[test]
+    fn castle_mask_clears_the_right_bits() {
+        crate::magic::initialize_magics();
+
+        let fen = "r3k2r/7p/8/8/8/8/7P/R3K2R w KQkq - 0 1";
+        let all = CASTLE_WHITE_KSIDE | CASTLE_WHITE_QSIDE | CASTLE_BLACK_KSIDE | CASTLE_BLACK_QSIDE;
+
+        for &(mov, remaining) in &[
+            ("a1b1", all & !CASTLE_WHITE_QSIDE),
+            ("h1g1", all & !CASTLE_WHITE_KSIDE),
+            ("e1d1", CASTLE_BLACK_KSIDE | CASTLE_BLACK_QSIDE),
+            ("e1g1", CASTLE_BLACK_KSIDE | CASTLE_BLACK_QSIDE),
+            ("a1a8", CASTLE_WHITE_KSIDE | CASTLE_BLACK_KSIDE),
+            ("h2h4", all),
+        ] {
+            let mut pos = Position::from(fen);
+            pos.make_move(Move::from_algebraic(&pos, mov));
+            assert_eq!(pos.details.castling, remaining, "after {}", mov);
+        }
+
+        let mut pos = Position::from(fen);
+        pos.make_move(Move::from_algebraic(&pos, "h2h3"));
+        pos.make_move(Move::from_algebraic(&pos, "e8c8"));
+        assert_eq!(
+            pos.details.castling,
+            CASTLE_WHITE_KSIDE | CASTLE_WHITE_QSIDE
+        );
+    }
+
+    #[test]
+    fn fen_without_king_is_rejected() {
+        let missing_black_king = "8/8/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            Position::try_from_fen(missing_black_king),
+            Err(FenError::NoKing)
+        );
+
+        let two_white_kings = "4k3/8/8/8/8/8/8/3KK3 w - - 0 1";
+        assert_eq!(
+            Position::try_from_fen(two_white_kings),
+            Err(FenError::TooManyKings)
+        );
+    }
+
+    #[test]
+    fn wrong_tt_moves_are_not_pseudo_legal() {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from("4k3/1P6/8/8/8/8/3p4/R3K1N1 w - - 0 1");
+        let knight = Move {
+            from: Square::file_rank(6, 0),
+            to: Square::file_rank(5, 2),
+            piece: Piece::Knight,
+            captured: None,
+            promoted: None,
+            en_passant: false,
+            castling: false,
+        };
+        assert!(pos.move_is_pseudo_legal(knight));
+
+        let wrong_piece = Move {
+            piece: Piece::Bishop,
+            ..knight
+        };
+        assert!(!pos.move_is_pseudo_legal(wrong_piece));
+
+        let wrong_capture = Move {
+            captured: Some(Piece::Pawn),
+            ..knight
+        };
+        assert!(!pos.move_is_pseudo_legal(wrong_capture));
+
+        let pawn_push = Move {
+            from: Square::file_rank(1, 6),
+            to: Square::file_rank(1, 7),
+            piece: Piece::Pawn,
+            captured: None,
+            promoted: Some(Piece::Queen),
+            en_passant: false,
+            castling: false,
+        };
+        assert!(pos.move_is_pseudo_legal(pawn_push));
+        assert!(!pos.move_is_pseudo_legal(Move {
+            promoted: Some(Piece::King),
+            ..pawn_push
+        }));
+
+        let rook_move = Move {
+            from: Square::file_rank(0, 0),
+            to: Square::file_rank(0, 1),
+            piece: Piece::Rook,
+            captured: None,
+            promoted: Some(Piece::Queen),
+            en_passant: false,
+            castling: false,
+        };
+        assert!(!pos.move_is_pseudo_legal(rook_move));
+        assert!(pos.move_is_pseudo_legal(Move {
+            promoted: None,
+            ..rook_move
+        }));
+    }
+
+    #[test]
+    fn display_matches_ascii() {
+        let pos = Position::from("r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1");
+        assert_eq!(format!("{}", pos), pos.to_ascii());
+        assert!(pos.to_ascii().contains(" 8 | r .   . k .   r |\n"));
+        assert!(pos.to_ascii().contains("|  Kq\n"));
+        assert!(pos.to_ascii().contains("|  Black to move\n"));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "not pseudo-legal")]
+    fn making_a_move_from_an_empty_square_panics() {
+        crate::magic::initialize_magics();
+
+        let mut pos = STARTING_POSITION;
+        pos.make_move(Move {
+            from: Square::file_rank(4, 3),
+            to: Square::file_rank(4, 4),
+            piece: Piece::Pawn,
+            captured: None,
+            promoted: None,
+            en_passant: false,
+            castling: false,
+        });
+    }
+
+    #[test]
+    fn pieces_of_combines_type_and_color() {
+        let pos = STARTING_POSITION;
+        assert_eq!(
+            pos.pieces_of(Piece::Pawn, true),
+            STARTING_PAWNS & STARTING_COLOR
+        );
+        assert_eq!(
+            pos.pieces_of(Piece::Knight, false),
+            STARTING_KNIGHTS & STARTING_BLACK
+        );
+        assert_eq!(pos.piece_count(Piece::Pawn, false), 8);
+        assert_eq!(pos.piece_count(Piece::Queen, true), 1);
+    }
+
+    #[test]
+    fn fen_with_trailing_tokens_parses() {
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 3 17";
+        let expected = Position::from(fen);
+        let with_garbage = Position::from("r3k2r/8/8/8/8/8/8/R3K2R\tb Kq -  3 17 c0 \"comment\"\n");
+        assert_eq!(with_garbage, expected);
+        assert_eq!(with_garbage.details.halfmove, 3);
+        assert_eq!(with_garbage.fullmove, 17);
+    }
+
+    const P: i16 = 120;
+    const N: i16 = 300;
+    const B: i16 = 300;
+    const R: i16 = 550;
+    const Q: i16 = 1000;
+
+    /// Checks that the static exchange evaluation of `uci_move` in `fen` is
+    /// exactly `expected`, i.e. it reaches `expected` but not `expected + 1`.
+    fn see_test(fen: &str, uci_move: &str, expected: i16) {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from(fen);
+        let mov = Move::from_algebraic(&pos, uci_move);
+        assert!(
+            pos.see(mov, expected),
+            "{} in {} should reach {}",
+            uci_move,
+            fen,
+            expected
+        );
+        assert!(
+            !pos.see(mov, expected + 1),
+            "{} in {} should not exceed {}",
+            uci_move,
+            fen,
+            expected
+        );
+    }
+
+    #[test]
+    fn see_simple_exchanges() {
+        for &(fen, mov, expected) in &[
+            (
+                "6k1/1pp4p/p1pb4/6q1/3P1pRr/2P4P/PP1Br1P1/5RKN w - - 0 1",
+                "f1f4",
+                P - R + B,
+            ),
+            (
+                "5rk1/1pp2q1p/p1pb4/8/3P1NP1/2P5/1P1BQ1P1/5RK1 b - - 0 1",
+                "d6f4",
+                N - B,
+            ),
+            (
+                "4r1k1/5pp1/nbp4p/1p2p2q/1P2P1b1/1BP2N1P/1B2QPPK/3R4 b - - 0 1",
+                "g4f3",
+                N - B,
+            ),
+            (
+                "2r1r1k1/pp1bppbp/3p1np1/q3P3/2P2P2/1P2B3/P1N1B1PP/2RQ1RK1 b - - 0 1",
+                "d6e5",
+                P,
+            ),
+            (
+                "r2qk1nr/pp2ppbp/2b3p1/2p1p3/8/2N2N2/PPPP1PPP/R1BQR1K1 w kq - 0 1",
+                "f3e5",
+                P,
+            ),
+            (
+                "6r1/4kq2/b2p1p2/p1pPb3/p1P2B1Q/2P4P/2B1R1P1/6K1 w - - 0 1",
+                "f4e5",
+                0,
+            ),
+            (
+                "2r4k/2r4p/p7/2b2p1b/4pP2/1BR5/P1R3PP/2Q4K w - - 0 1",
+                "c3c5",
+                B,
+            ),
+            (
+                "8/pp6/2pkp3/4bp2/2R3b1/2P5/PP4B1/1K6 w - - 0 1",
+                "g2c6",
+                P - B,
+            ),
+            (
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5",
+                P - N,
+            ),
+            (
+                "rnb2b1r/ppp2kpp/5n2/4P3/q2P3B/5R2/PPP2PPP/RN1QKB2 w Q - 0 1",
+                "h4f6",
+                N - B + P,
+            ),
+            (
+                "r2q1rk1/2p1bppp/p2p1n2/1p2P3/4P1b1/1nP1BN2/PP3PPP/RN1QR1K1 b - - 0 1",
+                "g4f3",
+                N - B,
+            ),
+            (
+                "r1bqkb1r/2pp1ppp/p1n5/1p2p3/3Pn3/1B3N2/PPP2PPP/RNBQ1RK1 b kq - 0 1",
+                "c6d4",
+                0,
+            ),
+            (
+                "r1bq1r2/pp1ppkbp/4N1p1/n3P1B1/8/2N5/PPP2PPP/R2QK2R w KQ - 0 1",
+                "e6g7",
+                B - N,
+            ),
+            (
+                "r1bq1r2/pp1ppkbp/4N1pB/n3P3/8/2N5/PPP2PPP/R2QK2R w KQ - 0 1",
+                "e6g7",
+                B,
+            ),
+            (
+                "rnq1k2r/1b3ppp/p2bpn2/1p1p4/3N4/1BN1P3/PPP2PPP/R1BQR1K1 b kq - 0 1",
+                "d6h2",
+                P - B,
+            ),
+            (
+                "rn2k2r/1bq2ppp/p2bpn2/1p1p4/3N4/1BN1P3/PPP2PPP/R1BQR1K1 b kq - 0 1",
+                "d6h2",
+                P,
+            ),
+            (
+                "r2qkbn1/ppp1pp1p/3p1rp1/3Pn3/4P1b1/2N2N2/PPP2PPP/R1BQKB1R b KQq - 0 1",
+                "g4f3",
+                N - B + P,
+            ),
+            (
+                "1k3r2/1p1bP3/2p2p1Q/Ppb5/4Rp1P/2q2N1P/5PB1/6K1 b - - 0 1",
+                "c3e1",
+                -Q,
+            ),
+        ] {
+            see_test(fen, mov, expected);
+        }
+    }
+
+    #[test]
+    fn see_x_rays() {
+        for &(fen, mov, expected) in &[
+            (
+                "4R3/2r3p1/5bk1/1p1r3p/p2PR1P1/P1BK1P2/1P6/8 b - - 0 1",
+                "h5g4",
+                0,
+            ),
+            (
+                "4R3/2r3p1/5bk1/1p1r1p1p/p2PR1P1/P1BK1P2/1P6/8 b - - 0 1",
+                "h5g4",
+                0,
+            ),
+            (
+                "7r/5qpk/p1Qp1b1p/3r3n/BB3p2/5p2/P1P2P2/4RK1R w - - 0 1",
+                "e1e8",
+                0,
+            ),
+            (
+                "6rr/6pk/p1Qp1b1p/2n5/1B3p2/5p2/P1P2P2/4RK1R w - - 0 1",
+                "e1e8",
+                -R,
+            ),
+            (
+                "7r/5qpk/2Qp1b1p/1N1r3n/BB3p2/5p2/P1P2P2/4RK1R w - - 0 1",
+                "e1e8",
+                -R,
+            ),
+            (
+                "8/4kp2/2npp3/1Nn5/1p2PQP1/7q/1PP1B3/4KR1r b - - 0 1",
+                "h1f1",
+                0,
+            ),
+            (
+                "8/4kp2/2npp3/1Nn5/1p2P1P1/7q/1PP1B3/4KR1r b - - 0 1",
+                "h1f1",
+                0,
+            ),
+            (
+                "2r2r1k/6bp/p7/2q2p1Q/3PpP2/1B6/P5PP/2RR3K b - - 0 1",
+                "c5c1",
+                2 * R - Q,
+            ),
+            (
+                "2r4r/1P4pk/p2p1b1p/7n/BB3p2/2R2p2/P1P2P2/4RK2 w - - 0 1",
+                "c3c8",
+                R,
+            ),
+            (
+                "2r5/1P4pk/p2p1b1p/5b1n/BB3p2/2R2p2/P1P2P2/4RK2 w - - 0 1",
+                "c3c8",
+                R,
+            ),
+            (
+                "3r3k/3r4/2n1n3/8/3p4/2PR4/1B1Q4/3R3K w - - 0 1",
+                "d3d4",
+                N + R - Q,
+            ),
+            (
+                "1k1r4/1ppn3p/p4b2/4n3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5",
+                N - N + B - R + N,
+            ),
+        ] {
+            see_test(fen, mov, expected);
+        }
+    }
+
+    #[test]
+    fn see_promotions() {
+        for &(fen, mov, expected) in &[
+            ("6RR/4bP2/8/8/5r2/3K4/5p2/4k3 w - - 0 1", "f7f8q", B - P),
+            ("6RR/4bP2/8/8/5r2/3K4/5p2/4k3 w - - 0 1", "f7f8n", N - P),
+            ("7R/5P2/8/8/6r1/3K4/5p2/4k3 w - - 0 1", "f7f8q", Q - P),
+            ("7R/5P2/8/8/6r1/3K4/5p2/4k3 w - - 0 1", "f7f8b", B - P),
+            ("7R/4bP2/8/8/1q6/3K4/5p2/4k3 w - - 0 1", "f7f8r", -P),
+            ("4k3/8/8/8/8/8/4p3/3RK3 b - - 0 1", "e2d1q", R + Q - P - Q),
+        ] {
+            see_test(fen, mov, expected);
+        }
+    }
+
+    #[test]
+    fn see_en_passant() {
+        for &(fen, mov, expected) in &[
+            (
+                "3q2nk/pb1r1p2/np6/3P2Pp/2p1P3/2R4B/PQ3P1P/3R2K1 w - h6 0 1",
+                "g5h6",
+                0,
+            ),
+            (
+                "3q2nk/pb1r1p2/np6/3P2Pp/2p1P3/2R1B2B/PQ3P1P/3R2K1 w - h6 0 1",
+                "g5h6",
+                P,
+            ),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", P),
+            ("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", 0),
+            // The captured pawn leaves d5 (d4), which opens the file for our
+            // rook behind it to defend the en passant square.
+            ("3r2k1/8/8/3pP3/8/8/8/3R2K1 w - d6 0 1", "e5d6", P),
+            ("3r2k1/8/8/8/3Pp3/8/8/3R2K1 b - d3 0 1", "e4d3", P),
+            // Without the rook the pawn is simply lost again.
+            ("3r2k1/8/8/3pP3/8/8/8/6K1 w - d6 0 1", "e5d6", 0),
+        ] {
+            see_test(fen, mov, expected);
+        }
+    }
+
+    #[test]
+    fn see_king_captures() {
+        for &(fen, mov, expected) in &[
+            ("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1", "e1e2", R),
+            ("4k3/8/8/8/8/8/3q4/3RK3 b - - 0 1", "d2d1", R - Q),
+            ("3rk3/8/8/8/8/8/8/3RK3 w - - 0 1", "d1d8", 0),
+            ("3rk3/8/8/8/8/8/3R4/3RK3 w - - 0 1", "d2d8", R),
+        ] {
+            see_test(fen, mov, expected);
+        }
+    }
+
+    #[test]
+    fn side_to_move_index_matches_bool() {
+        for fen in &[
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 b - - 0 1",
+        ] {
+            let pos = Position::from(*fen);
+            let color = pos.side_to_move();
+            assert_eq!(color.index(), pos.white_to_move as usize);
+            assert_eq!(color.opponent().index(), !pos.white_to_move as usize);
+            assert_eq!(color.opponent().opponent(), color);
+        }
+        assert_eq!(STARTING_POSITION.side_to_move(), Color::White);
+    }
+
+    #[test]
+    fn side_to_move_is_case_insensitive() {
+        let lower = Position::from("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        let upper = Position::from("r3k2r/8/8/8/8/8/8/R3K2R W KQkq - 0 1");
+        assert!(upper.white_to_move);
+        assert_eq!(upper, lower);
+
+        let black = Position::from("r3k2r/8/8/8/8/8/8/R3K2R B KQkq - 0 1");
+        assert!(!black.white_to_move);
+
+        assert_eq!(
+            Position::try_from_fen("r3k2r/8/8/8/8/8/8/R3K2R x KQkq - 0 1"),
+            Err(FenError::InvalidSideToMove)
+        );
+    }
+
+    #[test]
+    fn malformed_fens_are_rejected_without_panicking() {
+        let cases = [
+            ("", FenError::MissingField),
+            ("4k3/8/8/8/8/8/8/4K3", FenError::MissingField),
+            ("4k3/8/8/8/8/8/8/4K3 w", FenError::MissingField),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", FenError::BadPieceChar('X')),
+            ("4k3/8/8/8/8/8/8/4K4 w - - 0 1", FenError::InvalidBoard),
+            ("4k3/8/8/8/8/8/8/4K w - - 0 1", FenError::InvalidBoard),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::InvalidBoard),
+            ("4k3/8/8/8/8/8/8/8/4K3 w - - 0 1", FenError::InvalidBoard),
+            ("4k3/8/8/8/8/8/8/P3K3 w - - 0 1", FenError::PawnOnBackRank),
+            ("4k2P/8/8/8/8/8/8/4K3 w - - 0 1", FenError::PawnOnBackRank),
+            ("p3k3/8/8/8/8/8/8/4K3 b - - 0 1", FenError::PawnOnBackRank),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w KX - 0 1",
+                FenError::BadCastlingChar('X'),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - z3 0 1",
+                FenError::BadEnPassant(String::from("z3")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e4 0 1",
+                FenError::BadEnPassant(String::from("e4")),
+            ),
+        ];
+
+        for (fen, error) in cases.iter() {
+            assert_eq!(Position::try_from_fen(fen), Err(error.clone()), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+            "4k3/8/8/2pP4/8/8/8/4K3 w - c6 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 7 42",
+            "r3k3/8/8/8/8/8/8/4K2R b Kq - 12 60",
+        ] {
+            assert_eq!(Position::from(*fen).to_fen(), *fen);
+        }
+    }
+
+    fn assert_occupancy_matches_rebuild(pos: &Position) {
+        let all_pieces =
+            pos.pawns() | pos.knights() | pos.bishops() | pos.rooks() | pos.queens() | pos.kings();
+        assert_eq!(pos.all_pieces, all_pieces, "\n{}", pos);
+        assert_eq!(pos.pieces[1], all_pieces & pos.color, "\n{}", pos);
+        assert_eq!(pos.pieces[0], all_pieces & !pos.color, "\n{}", pos);
+    }
+
+    #[test]
+    fn incremental_occupancy_matches_rebuild() {
+        use rand::{prelude::*, prng::ChaChaRng};
+
+        crate::magic::initialize_magics();
+
+        let mut rng = ChaChaRng::from_seed([42; 32]);
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "4k3/8/8/2pP4/8/8/8/4K3 w - c6 0 1",
+        ] {
+            for _ in 0..20 {
+                let mut pos = Position::from(*fen);
+                let initial = pos.clone();
+                let mut history = Vec::new();
+
+                for _ in 0..40 {
+                    let moves = pos.legal_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    let mov = moves[rng.gen_range(0, moves.len())];
+                    history.push((mov, pos.details));
+                    pos.make_move(mov);
+                    assert_occupancy_matches_rebuild(&pos);
+                }
+
+                while let Some((mov, details)) = history.pop() {
+                    pos.unmake_move(mov, details);
+                    assert_occupancy_matches_rebuild(&pos);
+                }
+                assert_eq!(pos, initial);
+            }
+        }
+    }
+
+    fn assert_board_is_consistent(pos: &Position) {
+        assert_occupancy_matches_rebuild(pos);
+
+        for (i, &a) in pos.bb.iter().enumerate() {
+            for &b in &pos.bb[i + 1..] {
+                assert!((a & b).is_empty(), "\n{}", pos);
+            }
+        }
+
+        for &white in &[false, true] {
+            let king = pos.kings() & pos.us(white);
+            assert_eq!(king.popcount(), 1, "\n{}", pos);
+            assert_eq!(king, pos.king_sq(white).to_bb(), "\n{}", pos);
+        }
+    }
+
+    #[test]
+    fn fen_round_trip_keeps_the_board_consistent() {
+        crate::magic::initialize_magics();
+
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+            "4k3/8/8/2pP4/8/8/8/4K3 w - c6 0 1",
+            "4k3/8/8/8/2Pp4/8/8/4K3 b - c3 0 1",
+            "8/8/8/KPp4r/8/8/8/4k3 w - c6 0 1",
+            "r3k3/8/8/8/8/8/8/4K2R b Kq - 12 60",
+            "1k6/1P6/8/8/8/8/6p1/K7 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/8 b - - 99 120",
+        ] {
+            let mut pos = Position::from(*fen);
+            assert_board_is_consistent(&pos);
+            assert_eq!(Position::from(pos.to_fen().as_str()), pos, "{}", fen);
+
+            // Every child position has to survive the round trip as well.
+            for mov in pos.legal_moves() {
+                let details = pos.details;
+                pos.make_move(mov);
+                assert_board_is_consistent(&pos);
+                assert_eq!(
+                    Position::from(pos.to_fen().as_str()),
+                    pos,
+                    "{} after {}",
+                    fen,
+                    mov
+                );
+                pos.unmake_move(mov, details);
+            }
+        }
+    }
+
+    #[test]
+    fn double_check_has_two_checkers() {
+        crate::magic::initialize_magics();
+
+        let pos = Position::from("4k3/8/5N2/8/8/8/8/4R1K1 b - - 0 1");
+        assert!(pos.details.checkers.more_than_one());
+        assert_eq!(pos.details.checkers, pos.compute_checkers());
+
+        // A knight check discovering the rook
+        let mut pos = Position::from("4k3/8/8/8/4N3/8/8/4R1K1 w - - 0 1");
+        let details = pos.details;
+        let mov = Move::from_algebraic(&pos, "e4d6");
+        pos.make_move(mov);
+        assert!(pos.details.checkers.more_than_one());
+        assert_eq!(pos.details.checkers.popcount(), 2);
+
+        pos.unmake_move(mov, details);
+        assert!(pos.details.checkers.is_empty());
+    }
+
+    #[test]
+    fn promotion_capture_updates_both_pieces() {
+        crate::magic::initialize_magics();
+
+        let mut pos = Position::from("r3k3/1P6/8/8/8/8/8/4K3 w q - 0 1");
+        let initial = pos.clone();
+        let details = pos.details;
+        let mov = Move::from_algebraic(&pos, "b7a8q");
+        assert_eq!(mov.captured, Some(Piece::Rook));
+        assert_eq!(mov.promoted, Some(Piece::Queen));
+
+        pos.make_move(mov);
+        assert!(pos.pawns().is_empty());
+        assert_eq!(pos.pieces_of(Piece::Queen, true), SQUARE_A8.to_bb());
+        assert!(pos.rooks().is_empty());
+        assert_eq!(pos.white_pieces(), SQUARE_A8.to_bb() | SQUARE_E1.to_bb());
+        assert_eq!(pos.black_pieces(), SQUARE_E8.to_bb());
+        assert_eq!(pos.details.castling, 0);
+        assert_eq!(pos.to_fen(), "Q3k3/8/8/8/8/8/8/4K3 b - - 0 1");
+        assert_board_is_consistent(&pos);
+
+        pos.unmake_move(mov, details);
+        assert_eq!(pos, initial);
+        assert_board_is_consistent(&pos);
+    }
+
+    #[test]
+    fn chess960_castling() {
+        crate::magic::initialize_magics();
+
+        let fen = "rk2r3/4p3/8/8/8/8/4P3/RK2R3 w KQkq - 0 1";
+        let mut pos = Position::from(fen);
+        let initial = pos.clone();
+        assert!(pos.chess960);
+        assert_eq!(pos.to_fen(), "rk2r3/4p3/8/8/8/8/4P3/RK2R3 w EAea - 0 1");
+        assert_eq!(
+            Position::from("rk2r3/4p3/8/8/8/8/4P3/RK2R3 w EAea - 0 1"),
+            initial
+        );
+
+        let kingside = Move::from_algebraic(&pos, "b1e1");
+        assert!(kingside.castling && kingside.is_kingside_castle());
+        assert!(pos.move_is_pseudo_legal(kingside) && pos.move_is_legal(kingside));
+        let white_details = pos.details;
+        pos.make_move(kingside);
+        assert_eq!(pos.to_fen(), "rk2r3/4p3/8/8/8/8/4P3/R4RK1 b ea - 1 1");
+        assert_board_is_consistent(&pos);
+
+        let queenside = Move::from_algebraic(&pos, "b8a8");
+        assert!(queenside.castling && queenside.is_queenside_castle());
+        let black_details = pos.details;
+        pos.make_move(queenside);
+        assert_eq!(pos.to_fen(), "2krr3/4p3/8/8/8/8/4P3/R4RK1 w - - 2 2");
+        assert_board_is_consistent(&pos);
+
+        pos.unmake_move(queenside, black_details);
+        pos.unmake_move(kingside, white_details);
+        assert_eq!(pos, initial);
+
+        // The king may not pass the square attacked by the knight, the rook may
+        let pos = Position::from("rk2r3/8/8/8/8/4n3/8/RK2R3 w EA - 0 1");
+        let castles: Vec<_> = pos
+            .legal_moves()
+            .into_iter()
+            .filter(|m| m.castling)
+            .collect();
+        assert_eq!(castles, vec![Move::from_algebraic(&pos, "b1a1")]);
+
+        // The king may already stand on its destination square
+        let mut pos = Position::from("6kr/8/8/8/8/8/8/6KR w Hh - 0 1");
+        let castle = pos.castling_move(true);
+        assert!(pos.move_is_legal(castle));
+        pos.make_move(castle);
+        assert_eq!(pos.to_fen(), "6kr/8/8/8/8/8/8/5RK1 b h - 1 1");
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let insufficient = |fen| Position::from(fen).is_insufficient_material();
+
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/2B1K3 b - - 0 1"));
+        assert!(insufficient("2b1k3/8/8/8/8/8/8/3BK3 w - - 0 1"));
+        assert!(insufficient("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/1B6/2B1K3 w - - 0 1"));
+
+        assert!(!insufficient("2b1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/1BB1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/1NB1K3 w - - 0 1"));
+        assert!(!insufficient("2n1k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn attackers_to_finds_both_colors() {
+        crate::magic::initialize_magics();
+
+        let pos =
+            Position::from("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let squares = |squares: &[(u8, u8)]| {
+            squares.iter().fold(Bitboard::from(0), |bb, &(file, rank)| {
+                bb | Square::file_rank(file, rank).to_bb()
+            })
+        };
+
+        let e6 = Square::file_rank(4, 5);
+        assert_eq!(
+            pos.attackers_to(e6, pos.all_pieces),
+            squares(&[(3, 4), (3, 6), (5, 6), (4, 6)])
+        );
+
+        // The white pawn e4 and the knights b6, c3 and f6 defend d5, the queen
+        // on f3 only joins once the pawn is gone.
+        let d5 = Square::file_rank(3, 4);
+        let e4 = Square::file_rank(4, 3);
+        let f3 = Square::file_rank(5, 2);
+        let attackers = pos.attackers_to(d5, pos.all_pieces);
+        assert_eq!(
+            attackers,
+            squares(&[(4, 3), (2, 2), (4, 5), (1, 5), (5, 5)])
+        );
+        assert_eq!(attackers & pos.white_pieces(), squares(&[(4, 3), (2, 2)]));
+        assert_eq!(
+            pos.attackers_to(d5, pos.all_pieces & !e4.to_bb()) & pos.queens(),
+            f3.to_bb()
+        );
+    }
+
+    /// The shift-based attack detection `is_attacked` used before it was
+    /// rewritten on top of the attack tables.
+    fn is_attacked_by_shifts(pos: &Position, sq: Square) -> bool {
+        let them = pos.them(pos.white_to_move);
+        let pawns = pos.pawns() & them;
+        (get_bishop_attacks_from(sq, pos.all_pieces) & (pos.bishops() | pos.queens()) & them)
+            .at_least_one()
+            || (get_rook_attacks_from(sq, pos.all_pieces) & (pos.rooks() | pos.queens()) & them)
+                .at_least_one()
+            || (KNIGHT_ATTACKS[sq] & pos.knights() & them).at_least_one()
+            || pawns.backward(pos.white_to_move, 1).left(1) & sq
+            || pawns.backward(pos.white_to_move, 1).right(1) & sq
+            || (KING_ATTACKS[sq] & pos.kings() & them).at_least_one()
+    }
+
+    fn check_attacks_recursive(pos: &mut Position, depth: usize) {
+        for sq in 0..64 {
+            let sq = Square::from(sq);
+            assert_eq!(pos.is_attacked(sq), is_attacked_by_shifts(pos, sq));
+        }
+        assert_eq!(
+            pos.in_check(),
+            is_attacked_by_shifts(pos, pos.king_sq(pos.white_to_move))
+        );
+
+        if depth == 0 {
+            return;
+        }
+
+        for mov in pos.legal_moves() {
+            let details = pos.details;
+            pos.make_move(mov);
+            check_attacks_recursive(pos, depth - 1);
+            pos.unmake_move(mov, details);
+        }
+    }
+
+    #[test]
+    fn attack_tables_agree_with_shifts() {
+        crate::magic::initialize_magics();
+
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            check_attacks_recursive(&mut Position::from(*fen), 2);
+        }
+    }
+
+    #[test]
+    fn count_material_in_the_starting_position() {
+        let count = STARTING_POSITION.count_material();
+        assert_eq!(count.white, [8, 2, 2, 2, 1, 1]);
+        assert_eq!(count.black, count.white);
+        assert_eq!(count.get(Piece::Queen, false), 1);
+
+        let pos = Position::from("4k3/8/8/8/8/8/8/RN2K3 w - - 0 1");
+        let count = pos.count_material();
+        assert_eq!(count.white, [0, 1, 0, 1, 0, 1]);
+        assert_eq!(count.black, [0, 0, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn fifty_move_draw_needs_a_legal_move() {
+        crate::magic::initialize_magics();
+
+        let draw = Position::from("4k3/8/8/8/8/8/8/4K2R w - - 100 80");
+        assert!(draw.is_fifty_move_draw());
+        assert!(draw.is_fifty_move_draw_with_moves(true));
+
+        let early = Position::from("4k3/8/8/8/8/8/8/4K2R w - - 99 80");
+        assert!(!early.is_fifty_move_draw());
+
+        let mate = Position::from("R5k1/5ppp/8/8/8/8/8/6K1 b - - 100 80");
+        assert!(mate.in_check());
+        assert!(!mate.is_fifty_move_draw());
+        assert!(!mate.is_fifty_move_draw_with_moves(false));
+
+        let stalemate = Position::from("7k/5Q2/6K1/8/8/8/8/8 b - - 100 80");
+        assert!(!stalemate.in_check());
+        assert!(!stalemate.is_fifty_move_draw());
+    }
+
+    #[test]
+    fn lost_castling_or_en_passant_rights_break_repetitions() {
+        crate::magic::initialize_magics();
+
+        let play = |fen: &str, moves: &[&str]| {
+            let mut pos = Position::from(fen);
+            let mut hasher = crate::hash::Hasher::new();
+            hasher.from_position(&pos);
+            let mut history = vec![hasher.get_hash()];
+            for uci_move in moves {
+                let mov = Move::from_algebraic(&pos, uci_move);
+                hasher.make_move(&pos, mov);
+                pos.make_move(mov);
+                history.push(hasher.get_hash());
+            }
+            (pos, history)
+        };
+
+        // Walking the kings away and back gives the same board without the
+        // castling rights, which is a different position
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let shuffle = ["e1d1", "e8d8", "d1e1", "d8e8"];
+        let (pos, history) = play(fen, &shuffle);
+        assert_eq!(pos.details.castling, 0);
+        assert_ne!(history[0], history[4]);
+        assert!(!pos.is_repetition(&history, 2));
+
+        let (pos, history) = play(fen, &[&shuffle[..], &shuffle[..]].concat());
+        assert_eq!(history[4], history[8]);
+        assert!(pos.is_repetition(&history, 2));
+
+        // Likewise for an en passant capture which is no longer possible
+        let fen = "4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1";
+        let (pos, history) = play(fen, &["e2e4", "e8d8", "e1d1", "d8e8", "d1e1"]);
+        assert_eq!(pos.details.en_passant, 255);
+        assert_ne!(history[1], history[5]);
+        assert!(!pos.is_repetition(&history, 2));
+    }
+
+    #[test]
+    fn repetitions_are_counted_since_the_last_irreversible_move() {
+        crate::magic::initialize_magics();
+
+        let mut pos = Position::from("4k1n1/8/8/8/8/8/P7/4K1N1 w - - 0 1");
+        let mut hasher = crate::hash::Hasher::new();
+        hasher.from_position(&pos);
+        let mut history = vec![hasher.get_hash()];
+
+        let mut play = |pos: &mut Position, moves: &[&str]| {
+            for uci_move in moves {
+                let mov = Move::from_algebraic(pos, uci_move);
+                hasher.make_move(pos, mov);
+                pos.make_move(mov);
+                history.push(hasher.get_hash());
+            }
+            history.clone()
+        };
+
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        let history = play(&mut pos, &shuffle);
+        assert!(pos.is_repetition(&history, 2));
+        assert!(!pos.is_repetition(&history, 3));
+
+        let history = play(&mut pos, &shuffle);
+        assert!(pos.is_repetition(&history, 3));
+
+        // The pawn move starts over, so the next repetition is a two-fold one
+        let history = play(&mut pos, &["a2a3", "g8f6", "g1f3", "f6g8"]);
+        assert!(!pos.is_repetition(&history, 2));
+        let history = play(&mut pos, &["f3g1"]);
+        assert!(pos.is_repetition(&history, 2));
+        assert!(!pos.is_repetition(&history, 3));
+
+        // Hashes from before the last irreversible move are not looked at
+        pos.details.halfmove = 1;
+        assert!(!pos.is_repetition(&[1, 2, 1], 2));
+        pos.details.halfmove = 2;
+        assert!(pos.is_repetition(&[1, 2, 1], 2));
+    }
+
+    #[test]
+    fn pseudo_legal_moves_match_the_generator() {
+        crate::magic::initialize_magics();
+
+        for fen in &[
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/8/8/KPp4r/8/8/8/4k3 w - c6 0 1",
+        ] {
+            let pos = Position::from(*fen);
+            let mut expected = MoveList::new();
+            MoveGenerator::from(&pos).all_moves(&mut expected);
+            assert_eq!(pos.pseudo_legal_moves(), expected);
+
+            let legal = pos.legal_moves();
+            assert!(legal.iter().all(|mov| expected.contains(mov)));
+            assert_eq!(
+                legal.len(),
+                expected
+                    .iter()
+                    .filter(|&&mov| pos.move_is_legal(mov))
+                    .count()
+            );
+        }
+    }
+
+    #[test]
+    fn perft_fast_matches_perft() {
+        crate::magic::initialize_magics();
+
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+
+        for fen in fens.iter() {
+            let mut position = Position::from(*fen);
+            let mut buffers = Vec::new();
+            let nodes = position.perft_fast(3, &mut buffers);
+            assert_eq!(nodes, perft(&mut Position::from(*fen), 3));
+
+            // A second run reuses the buffers without reallocating them.
+            let pointers = buffers.iter().map(|b| b.as_ptr()).collect::<Vec<_>>();
+            assert_eq!(position.perft_fast(3, &mut buffers), nodes);
+            assert!(buffers.iter().zip(pointers).all(|(b, p)| b.as_ptr() == p));
+        }
+    }
 }
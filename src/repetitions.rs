@@ -37,7 +37,10 @@ impl Repetitions {
     }
 
     pub fn clear(&mut self) {
-        self.past_positions.iter_mut().for_each(Vec::clear);
+        // Segments after `index` are always empty.
+        self.past_positions[..=self.index]
+            .iter_mut()
+            .for_each(Vec::clear);
         self.index = 0;
     }
 
@@ -59,6 +62,12 @@ impl Repetitions {
         }
     }
 
+    /// Returns the hashes of the positions since the last irreversible move,
+    /// the current position last.
+    pub fn positions(&self) -> &[Hash] {
+        &self.past_positions[self.index]
+    }
+
     pub fn has_repeated(&self, ply: Ply) -> bool {
         let current = self.past_positions[self.index].last().unwrap();
         let repeated_since_root = self.past_positions[self.index]
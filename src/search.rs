@@ -17,6 +17,7 @@
 use std::cmp;
 use std::sync;
 
+use crate::bitboard::*;
 use crate::eval::*;
 #[cfg(feature = "fathom")]
 use crate::fathom;
@@ -36,6 +37,11 @@ pub type Depth = i16;
 pub const INC_PLY: Depth = 64;
 pub const MAX_PLY: Ply = 128;
 
+/// Initial half-width of the aspiration window.
+pub const ASPIRATION_DELTA: Score = 30;
+/// Percentage by which the aspiration window widens after a fail.
+pub const ASPIRATION_GROWTH: Score = 50;
+
 const CHECK_EXTENSION_DEPTH: Depth = 3 * INC_PLY;
 const FUTILITY_MARGIN: Score = 200;
 const HISTORY_PRUNING_DEPTH: Depth = 2 * INC_PLY;
@@ -47,6 +53,10 @@ const SEE_PRUNING_MARGIN_QUIET: Score = -100;
 const STATIC_BETA_DEPTH: Depth = 5 * INC_PLY;
 const STATIC_BETA_MARGIN: Score = 128;
 const QS_FUTILITY_MARGIN: Score = 200;
+const QS_DELTA_MARGIN: Score = 400;
+const QS_MAX_DEPTH: Depth = 32 * INC_PLY;
+const QS_QUIET_CHECKS: bool = false;
+const NULLMOVE_VERIFICATION_DEPTH: Depth = 10 * INC_PLY;
 const LMP_MAX_DEPTH: Depth = 5 * INC_PLY;
 const LMP_MOVES: [i16; (LMP_MAX_DEPTH / INC_PLY) as usize] = [0, 4, 8, 16, 32];
 
@@ -57,11 +67,15 @@ pub struct Search<'a> {
 
     // Required for (efficient) search
     stack: [PlyDetails; MAX_PLY as usize],
-    history: History,
+    pub history: History,
+    killers: Killers,
     eval: Eval,
+    eval_cache: Box<EvalCache>,
     hasher: Hasher,
     tt: &'a SharedTT<'a>,
     repetitions: Repetitions,
+    // Set while verifying a null move cutoff, disables further null moves
+    verifying_nullmove: bool,
 
     // Time Management
     time_control: TimeControl,
@@ -75,6 +89,7 @@ pub struct Search<'a> {
 
     // Misc
     options: PersistentOptions,
+    seed: u64,
     quiets: [[Option<Move>; 256]; MAX_PLY as usize],
     lmr: [[Depth; 64]; 64],
 }
@@ -83,10 +98,10 @@ pub struct Search<'a> {
 pub struct PlyDetails {
     irreversible_details: IrreversibleDetails,
     current_move: Option<Move>,
-    pub killers_moves: [Option<Move>; 2],
     exclude_move: Option<Move>,
     hash: Hash,
     pawn_hash: Hash,
+    material_hash: Hash,
 }
 
 impl<'a> Search<'a> {
@@ -121,10 +136,13 @@ impl<'a> Search<'a> {
 
             stack: [PlyDetails::default(); MAX_PLY as usize],
             history: History::default(),
+            killers: Killers::default(),
             eval: Eval::from(&position),
+            eval_cache: Box::default(),
             hasher,
             tt,
             repetitions,
+            verifying_nullmove: false,
 
             time_control,
             time_manager: TimeManager::new(&position, time_control, options.move_overhead, abort),
@@ -135,6 +153,7 @@ impl<'a> Search<'a> {
             pv,
 
             options,
+            seed: 0,
             quiets: [[None; 256]; MAX_PLY as usize],
             lmr,
         }
@@ -147,6 +166,7 @@ impl<'a> Search<'a> {
             .for_each(|pv| pv.iter_mut().for_each(|i| *i = None));
         self.stack[0].hash = self.hasher.get_hash();
         self.stack[0].pawn_hash = self.hasher.get_pawn_hash();
+        self.stack[0].material_hash = self.hasher.get_material_hash();
     }
 
     pub fn iterative_deepening(&mut self) -> Move {
@@ -267,22 +287,26 @@ impl<'a> Search<'a> {
         moves: &mut [(Move, i64)],
         depth: Depth,
     ) -> Option<Score> {
-        let mut delta = 30;
-        let mut alpha = cmp::max(last_score - delta, -MATE_SCORE);
-        let mut beta = cmp::min(last_score + delta, MATE_SCORE);
+        let mut delta = self.options.aspiration_delta;
+        let mut alpha = cmp::max(bounded_add(last_score, -delta), -MATE_SCORE);
+        let mut beta = cmp::min(bounded_add(last_score, delta), MATE_SCORE);
 
         loop {
             let (score, index) = self.search_root(moves, alpha, beta, depth)?;
             (&mut moves[0..index + 1]).rotate_right(1);
 
-            delta += delta / 2;
+            let growth = i32::from(delta) * i32::from(self.options.aspiration_growth) / 100;
+            delta = cmp::min(
+                i32::from(MATE_SCORE),
+                i32::from(delta) + cmp::max(1, growth),
+            ) as Score;
             if score >= beta {
-                beta = cmp::min(MATE_SCORE, score + delta);
+                beta = cmp::min(MATE_SCORE, bounded_add(score, delta));
                 if self.time_manager.elapsed_millis() > 5000 {
                     self.uci_info(depth, score, LOWER_BOUND);
                 }
             } else if score <= alpha {
-                alpha = cmp::max(score - delta, -MATE_SCORE);
+                alpha = cmp::max(bounded_add(score, -delta), -MATE_SCORE);
                 if self.time_manager.elapsed_millis() > 5000 {
                     self.uci_info(depth, score, UPPER_BOUND);
                 }
@@ -310,7 +334,7 @@ impl<'a> Search<'a> {
 
             // We already filtered all illegal moves. No need to check for move
             // legality here.
-            self.make_move(Some(mov), 0);
+            self.make_move(mov, 0);
 
             let mut new_depth = depth - INC_PLY;
             if self.position.in_check() {
@@ -329,7 +353,7 @@ impl<'a> Search<'a> {
 
             *subtree_size = ((self.visited_nodes - num_nodes_before) / 2) as i64;
 
-            self.unmake_move(Some(mov), 0);
+            self.unmake_move(mov, 0);
 
             match value {
                 None => {
@@ -365,6 +389,10 @@ impl<'a> Search<'a> {
         Some((best_score, best_move_index))
     }
 
+    /// Searches the current position with an alpha-beta window. Fails soft: a
+    /// score outside of the window is still the best one found, an upper bound
+    /// if it is at most `alpha` and a lower bound if it is at least `beta`.
+    /// Returns `None` if the search was aborted.
     pub fn search(&mut self, ply: Ply, alpha: Score, beta: Score, depth: Depth) -> Option<Score> {
         if self.time_manager.should_stop() {
             return None;
@@ -383,7 +411,7 @@ impl<'a> Search<'a> {
 
         // Check if there is a draw by insufficient mating material or threefold repetition.
         if self.is_draw(ply) {
-            return Some(0);
+            return Some(self.draw_score());
         }
 
         // Check if the fifty moves rule applies and if so, return the apropriate score.
@@ -392,7 +420,7 @@ impl<'a> Search<'a> {
         }
 
         if ply == MAX_PLY {
-            return Some(self.eval.score(&self.position, self.hasher.get_pawn_hash()));
+            return Some(self.static_eval());
         }
 
         let has_excluded_move = self.stack[ply as usize].exclude_move.is_some();
@@ -501,11 +529,12 @@ impl<'a> Search<'a> {
         }
 
         if eval.is_none() && !is_pv {
-            eval = Some(self.eval.score(&self.position, self.hasher.get_pawn_hash()));
+            eval = Some(self.static_eval());
         }
 
         let previous_move = self.stack[ply as usize - 1].current_move;
-        let nullmove_reply = previous_move == None;
+        let nullmove_reply = previous_move == Some(Move::NULL);
+        let previous_move = previous_move.filter(|mov| !mov.is_null());
         let in_check = !nullmove_reply && self.position.in_check();
         let mut skip_quiets = false;
 
@@ -524,25 +553,54 @@ impl<'a> Search<'a> {
                 && depth < STATIC_BETA_DEPTH
                 && eval - STATIC_BETA_MARGIN * (depth / INC_PLY) > beta
             {
-                return Some(beta);
+                return Some(eval - STATIC_BETA_MARGIN * (depth / INC_PLY));
             }
 
             // Nullmove pruning
             //
             // Prune nodes that are so good that we could pass without the opponent
             // catching up.
-            if !has_excluded_move && !in_check && self.eval.phase() > 0 && eval >= beta {
+            //
+            // At high depth a cutoff is verified by a reduced search without
+            // null moves, so we don't prune in zugzwang positions.
+            if !has_excluded_move
+                && !in_check
+                && !self.verifying_nullmove
+                && self.eval.phase() > 0
+                && eval >= beta
+            {
                 let r = INC_PLY + depth / 4 + cmp::min(2 * INC_PLY, (eval - beta) / 2);
-                self.make_move(None, ply);
+                self.make_move(Move::NULL, ply);
                 let score = self
                     .search(ply + 1, -alpha - 1, -alpha, depth - INC_PLY - r)
                     .map(|v| -v);
-                self.unmake_move(None, ply);
+                self.unmake_move(Move::NULL, ply);
                 match score {
                     None => return None,
                     Some(score) => {
                         if score >= beta {
-                            return Some(beta);
+                            // Passing can't prove a mate, so don't return one.
+                            let score = if score >= MATE_SCORE - MAX_PLY {
+                                beta
+                            } else {
+                                score
+                            };
+
+                            if depth < NULLMOVE_VERIFICATION_DEPTH {
+                                return Some(score);
+                            }
+
+                            self.verifying_nullmove = true;
+                            let verified = self.search(ply, beta - 1, beta, depth - INC_PLY - r);
+                            self.verifying_nullmove = false;
+                            match verified {
+                                None => return None,
+                                Some(verified) => {
+                                    if verified >= beta {
+                                        return Some(score);
+                                    }
+                                }
+                            }
                         }
                     }
                 }
@@ -570,7 +628,7 @@ impl<'a> Search<'a> {
         // If a capture or promotion leads to a beta cutoff in a reduced-depth search with increased beta bound, we
         // assume it will also lead to a cutoff in a full-depth search with the original beta bound.
         if !in_check && !is_pv && !has_excluded_move && depth >= 6 * INC_PLY {
-            let mut moves = MovePicker::qsearch(&self.position);
+            let mut moves = MovePicker::qsearch(&self.position, false);
 
             let probcut_beta = beta + 100;
 
@@ -579,7 +637,7 @@ impl<'a> Search<'a> {
                     continue;
                 }
 
-                self.make_move(Some(mov), ply);
+                self.make_move(mov, ply);
                 let value = self
                     .search(
                         ply + 1,
@@ -588,7 +646,7 @@ impl<'a> Search<'a> {
                         depth - 4 * INC_PLY,
                     )
                     .map(|v| -v);
-                self.unmake_move(Some(mov), ply);
+                self.unmake_move(mov, ply);
 
                 match value {
                     None => return None,
@@ -603,7 +661,7 @@ impl<'a> Search<'a> {
 
         let mut moves = MovePicker::new(
             ttmove,
-            self.stack[ply as usize].killers_moves,
+            self.killers.get_killers(ply as usize),
             previous_move,
         );
 
@@ -654,11 +712,11 @@ impl<'a> Search<'a> {
                     // depth.
                     if depth < HISTORY_PRUNING_DEPTH
                         && mtype == MoveType::Quiet
-                        && self.history.get_score(self.position.white_to_move, mov)
+                        && self.quiet_move_score(mov, ply, previous_move)
                             < HISTORY_PRUNING_THRESHOLD
                     {
                         // We can skip the remaining quiet moves because quiet moves
-                        // are ordered by history score.
+                        // are ordered by this score.
                         moves.skip_quiets(true);
                         pruned = true;
                         continue;
@@ -769,7 +827,7 @@ impl<'a> Search<'a> {
             let new_depth = depth - INC_PLY + extension;
             reduction = cmp::max(0, cmp::min(reduction, new_depth - INC_PLY));
 
-            self.make_move(Some(mov), ply);
+            self.make_move(mov, ply);
 
             let mut value = Some(Score::max_value());
             if !(is_pv && num_moves_searched == 0) {
@@ -788,7 +846,7 @@ impl<'a> Search<'a> {
                 value = self.search(ply + 1, -beta, -alpha, new_depth).map(|v| -v);
             }
 
-            self.unmake_move(Some(mov), ply);
+            self.unmake_move(mov, ply);
 
             num_moves_searched += 1;
             if mov.is_quiet() {
@@ -910,11 +968,13 @@ impl<'a> Search<'a> {
             return None;
         }
 
-        if ply == MAX_PLY {
-            return Some(self.eval.score(&self.position, self.hasher.get_pawn_hash()));
+        // Stop long forcing sequences and return the static eval instead.
+        if ply == MAX_PLY || depth <= -QS_MAX_DEPTH {
+            return Some(self.static_eval());
         }
 
         self.visited_nodes += 1;
+        self.max_ply_searched = cmp::max(ply, self.max_ply_searched);
 
         let in_check = self.position.in_check();
         let mut alpha = alpha;
@@ -923,7 +983,22 @@ impl<'a> Search<'a> {
             // Don't do any cutoffs or prunings when in check.
             None
         } else {
-            let e = self.eval.score(&self.position, self.hasher.get_pawn_hash());
+            // Delta pruning
+            //
+            // If we are so far behind in material that not even winning a
+            // queen gets us close to alpha, don't bother with a full
+            // evaluation.
+            let white = self.position.white_to_move;
+            let seventh_rank = if white { RANK_7 } else { RANK_2 };
+            let can_promote =
+                (self.position.pawns() & self.position.us(white) & seventh_rank).at_least_one();
+            let optimistic =
+                self.eval.material_balance(white) + Piece::Queen.value() + QS_DELTA_MARGIN;
+            if !can_promote && optimistic < alpha {
+                return Some(optimistic);
+            }
+
+            let e = self.static_eval();
             if e >= beta {
                 return Some(e);
             }
@@ -957,10 +1032,11 @@ impl<'a> Search<'a> {
             }
         }
 
-        let mut moves = MovePicker::qsearch(&self.position);
+        let mut moves = MovePicker::qsearch(&self.position, QS_QUIET_CHECKS && depth == 0);
 
         let mut best_move = None;
-        let mut best_score = -MATE_SCORE;
+        // Standing pat is the score to beat when not in check.
+        let mut best_score = eval.unwrap_or(-MATE_SCORE);
 
         let mut num_moves_searched = 0;
         while let Some((_mtype, mov)) = moves.next(&self.position, &self.history) {
@@ -968,23 +1044,20 @@ impl<'a> Search<'a> {
                 continue;
             }
 
+            // Skip captures which cannot raise alpha even if we win the
+            // captured piece for free.
             if let Some(eval) = eval {
-                let capture = mov.captured.map_or(0, Piece::value);
-                let promote = mov.promoted.map_or(0, |p| p.value() - Piece::Pawn.value());
-                let mscore = capture + promote;
-
-                if eval + mscore + QS_FUTILITY_MARGIN < alpha && !self.position.move_will_check(mov)
-                {
+                if qsearch_futile(eval, mov, alpha) && !self.position.move_will_check(mov) {
                     continue;
                 }
             }
 
-            self.make_move(Some(mov), ply);
+            self.make_move(mov, ply);
 
             let value = self
                 .qsearch(ply + 1, -beta, -alpha, depth - INC_PLY)
                 .map(|v| -v);
-            self.unmake_move(Some(mov), ply);
+            self.unmake_move(mov, ply);
 
             num_moves_searched += 1;
 
@@ -1007,20 +1080,10 @@ impl<'a> Search<'a> {
             }
         }
 
-        if num_moves_searched == 0 {
-            if in_check {
-                return Some(-MATE_SCORE + ply);
-            } else {
-                return Some(alpha);
-            }
+        if num_moves_searched == 0 && in_check {
+            return Some(-MATE_SCORE + ply);
         }
 
-        let score = if best_score >= beta {
-            best_score
-        } else {
-            alpha
-        };
-
         if depth == 0 {
             let bound = if best_score >= beta {
                 LOWER_BOUND
@@ -1031,14 +1094,14 @@ impl<'a> Search<'a> {
             self.tt.insert(
                 self.hasher.get_hash(),
                 0,
-                TTScore::from_score(score, ply),
+                TTScore::from_score(best_score, ply),
                 best_move,
                 bound,
                 eval,
             );
         }
 
-        Some(score)
+        Some(best_score)
     }
 
     fn get_tt_entry(&mut self, hash: Hash) -> (Option<TTEntry>, Option<Move>) {
@@ -1050,7 +1113,7 @@ impl<'a> Search<'a> {
             let mov = ttentry
                 .best_move
                 .expand(&self.position)
-                .filter(|&mov| self.position.move_is_pseudo_legal(mov));
+                .filter(|&mov| MoveGenerator::from(&self.position).is_pseudo_legal(mov));
 
             (mov.map(|_| ttentry), mov)
         } else {
@@ -1058,36 +1121,68 @@ impl<'a> Search<'a> {
         }
     }
 
+    /// Seeds the pseudo random parts of the search, i.e. the draw score
+    /// jitter. Searches with the same seed and inputs are reproducible.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Evaluates the current position from the side to move's point of view.
+    fn static_eval(&mut self) -> Score {
+        self.eval.score_with_cache(
+            &self.position,
+            self.hasher.get_pawn_hash(),
+            self.hasher.get_material_hash(),
+            &mut self.eval_cache,
+        )
+    }
+
+    /// Score for draws by repetition or insufficient material. With the
+    /// `DrawJitter` option this varies slightly around zero, so the search
+    /// doesn't consider every repetition to be exactly as good as the
+    /// alternatives.
+    fn draw_score(&self) -> Score {
+        if self.options.draw_jitter {
+            // Mix the seed (SplitMix64) so that all of its bits affect the two
+            // used here
+            let mut seed = self.seed;
+            seed = (seed ^ (seed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            seed = (seed ^ (seed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            seed ^= seed >> 31;
+            2 - ((self.visited_nodes ^ seed) & 3) as Score
+        } else {
+            0
+        }
+    }
+
     /// Checks for draws by fifty moves rule.
     ///
     /// Returns `None` if the halfmove clock did not reach move 100 yet.
     /// Returns the mate score for `ply` if checkmate and a draw score otherwise.
     fn fifty_moves_rule(&mut self, ply: Ply) -> Option<Score> {
-        if self.position.details.halfmove == 100 {
-            if self.checkmate() {
-                return Some(-MATE_SCORE + ply);
-            } else {
-                return Some(0);
-            }
+        if self.position.details.halfmove < 100 {
+            return None;
         }
 
-        None
-    }
-
-    fn checkmate(&mut self) -> bool {
-        if !self.position.in_check() {
-            return false;
+        // Stalemate is a draw as well, so only look for moves when in check.
+        let has_legal_moves = !self.position.in_check() || self.has_legal_moves();
+        if self.position.is_fifty_move_draw_with_moves(has_legal_moves) {
+            Some(0)
+        } else {
+            Some(-MATE_SCORE + ply)
         }
+    }
 
+    fn has_legal_moves(&mut self) -> bool {
         let mut moves = MovePicker::new(None, [None; 2], None);
 
         while let Some((_, mov)) = moves.next(&self.position, &self.history) {
             if self.position.move_is_legal(mov) {
-                return false;
+                return true;
             }
         }
 
-        true
+        false
     }
 
     fn uci_info(&self, d: Depth, alpha: Score, bound: Bound) {
@@ -1175,22 +1270,23 @@ impl<'a> Search<'a> {
             depth,
         );
 
-        if let Some(previous_move) = self.stack[ply as usize - 1].current_move {
-            if previous_move.is_quiet() {
-                self.history.last_best_reply[self.position.white_to_move as usize]
-                    [previous_move.piece.index()][previous_move.to] = Some(mov);
-            } else {
-                self.history.last_best_reply[self.position.white_to_move as usize]
-                    [previous_move.piece.index()][previous_move.to] = None;
-            }
+        let previous_move = self.stack[ply as usize - 1].current_move;
+        if let Some(previous_move) = previous_move {
+            self.history.set_counter(previous_move, mov);
         }
 
-        let killers = &mut self.stack[ply as usize].killers_moves;
+        self.killers.update_killer(ply as usize, mov);
+    }
 
-        if killers[0] != Some(mov) {
-            killers[1] = killers[0];
-            killers[0] = Some(mov);
-        }
+    /// Ordering score of the quiet move `mov` at `ply`, played in reply to
+    /// `previous_move`. See `History::quiet_move_score`.
+    pub fn quiet_move_score(&self, mov: Move, ply: Ply, previous_move: Option<Move>) -> i64 {
+        self.history.quiet_move_score(
+            self.position.white_to_move,
+            mov,
+            self.killers.get_killers(ply as usize),
+            previous_move,
+        )
     }
 
     fn add_pv_move(&mut self, mov: Move, ply: Ply) {
@@ -1211,8 +1307,8 @@ impl<'a> Search<'a> {
 
     fn is_draw(&self, ply: Ply) -> bool {
         if let Some(last_move) = self.stack[ply as usize - 1].current_move {
-            if last_move.captured.is_some() || last_move.promoted.is_some() {
-                return self.eval.is_material_draw();
+            if last_move.is_capture_or_promotion() {
+                return self.eval.is_material_draw(&self.position);
             } else if last_move.piece != Piece::Pawn {
                 return self.repetitions.has_repeated(ply);
             }
@@ -1225,24 +1321,11 @@ impl<'a> Search<'a> {
         self.time_manager
             .update(&self.position, TimeControl::Infinite);
 
-        let mut num_moves = 0;
-
-        let mut moves = MoveList::new();
-        MoveGenerator::from(&self.position).all_moves(&mut moves);
-
-        if depth > 0 {
-            for &mov in &moves {
-                if !self.position.move_is_legal(mov) {
-                    continue;
-                }
-
-                self.make_move(Some(mov), depth as Ply);
-                let perft = self.internal_perft(depth - 1);
-                num_moves += perft;
-                println!("{}: {}", mov.to_algebraic(), perft);
-                self.unmake_move(Some(mov), depth as Ply);
-            }
-        }
+        let num_moves = if depth > 0 {
+            perft_divide(&mut self.position, depth)
+        } else {
+            0
+        };
 
         let elapsed = self.time_manager.elapsed_millis();
 
@@ -1252,46 +1335,23 @@ impl<'a> Search<'a> {
         println!();
     }
 
-    fn internal_perft(&mut self, depth: usize) -> usize {
-        if depth == 0 {
-            return 1;
-        }
-
-        let mut num_moves = 0;
-        let mut moves = MoveList::new();
-        MoveGenerator::from(&self.position).all_moves(&mut moves);
-
-        for &mov in &moves {
-            if !self.position.move_is_legal(mov) {
-                continue;
-            }
-
-            self.make_move(Some(mov), depth as Ply);
-            num_moves += self.internal_perft(depth - 1);
-            self.unmake_move(Some(mov), depth as Ply);
-        }
-
-        num_moves
-    }
-
-    fn make_move(&mut self, mov: Option<Move>, ply: Ply) {
-        let white_move = self.position.white_to_move;
-
+    /// Makes the move and pushes it onto the search stack. Pass `Move::NULL`
+    /// to make a null move.
+    fn make_move(&mut self, mov: Move, ply: Ply) {
         let current_ply = &mut self.stack[ply as usize];
         current_ply.irreversible_details = self.position.details;
-        current_ply.current_move = mov;
+        current_ply.current_move = Some(mov);
 
         if ply + 2 < MAX_PLY {
-            self.stack[2 + ply as usize].killers_moves = [None; 2];
+            self.killers.clear(2 + ply as usize);
         }
 
-        if let Some(mov) = mov {
-            self.hasher.make_move(&self.position, mov);
-            self.eval.make_move(mov, white_move);
-            self.position.make_move(mov);
-        } else {
+        if mov.is_null() {
             self.hasher.make_nullmove(&self.position);
             self.position.make_nullmove();
+        } else {
+            self.hasher.make_move(&self.position, mov);
+            self.position.make_move_with(mov, &mut self.eval);
         }
 
         if self.position.details.halfmove == 0 {
@@ -1302,21 +1362,22 @@ impl<'a> Search<'a> {
         let next_ply = &mut self.stack[1 + ply as usize];
         next_ply.hash = self.hasher.get_hash();
         next_ply.pawn_hash = self.hasher.get_pawn_hash();
+        next_ply.material_hash = self.hasher.get_material_hash();
     }
 
-    fn unmake_move(&mut self, mov: Option<Move>, ply: Ply) {
-        let white_move = !self.position.white_to_move;
-
+    fn unmake_move(&mut self, mov: Move, ply: Ply) {
         let prev_ply = &self.stack[ply as usize];
         let irreversible = prev_ply.irreversible_details;
 
-        if let Some(mov) = mov {
-            self.eval.unmake_move(mov, white_move);
-            self.position.unmake_move(mov, irreversible);
-            self.hasher.set(prev_ply.hash, prev_ply.pawn_hash);
-        } else {
+        if mov.is_null() {
             self.position.unmake_nullmove(irreversible);
-            self.hasher.set(prev_ply.hash, prev_ply.pawn_hash);
+            self.hasher.unmake_nullmove(&irreversible);
+            debug_assert_eq!(self.hasher.get_hash(), prev_ply.hash);
+        } else {
+            self.position
+                .unmake_move_with(mov, irreversible, &mut self.eval);
+            self.hasher
+                .set(prev_ply.hash, prev_ply.pawn_hash, prev_ply.material_hash);
         }
 
         self.repetitions.pop_position();
@@ -1327,3 +1388,355 @@ impl<'a> Search<'a> {
         self.time_manager.update(&self.position, self.time_control);
     }
 }
+
+fn qsearch_futile(eval: Score, mov: Move, alpha: Score) -> bool {
+    let capture = mov.captured.map_or(0, Piece::value);
+    let promote = mov.promoted.map_or(0, |p| p.value() - Piece::Pawn.value());
+    eval + capture + promote + QS_FUTILITY_MARGIN < alpha
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gendata::{game_result, GameResult};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    fn with_search<F: FnOnce(&mut Search)>(fen: &str, f: F) {
+        crate::magic::initialize_magics();
+
+        let position = Position::from(fen);
+        let mut hasher = Hasher::new();
+        hasher.from_position(&position);
+        let mut tt = TT::new(10);
+        let tt = tt.share();
+        let mut repetitions = Repetitions::new(100);
+        repetitions.push_position(hasher.get_hash());
+
+        let mut search = Search::new(
+            Arc::new(AtomicBool::new(false)),
+            hasher,
+            PersistentOptions::default(),
+            position,
+            TimeControl::Infinite,
+            &tt,
+            repetitions,
+        );
+        search.prepare_search();
+        f(&mut search);
+    }
+
+    #[test]
+    fn qsearch_skips_captures_which_cannot_raise_alpha() {
+        with_search("7k/8/q7/3p4/1N6/8/8/6K1 w - - 0 1", |search| {
+            let pawn_capture = Move::from_algebraic(&search.position, "b4d5");
+            let queen_capture = Move::from_algebraic(&search.position, "b4a6");
+
+            let eval = search.static_eval();
+            let alpha = eval + 350;
+            assert!(qsearch_futile(eval, pawn_capture, alpha));
+            assert!(!qsearch_futile(eval, queen_capture, alpha));
+
+            // Only the root and the queen capture are visited.
+            search.qsearch(0, alpha, alpha + 1, 0);
+            assert_eq!(search.visited_nodes, 2);
+        });
+    }
+
+    #[test]
+    fn nullmove_verification_finds_zugzwang_move() {
+        // The winning move Rf1 relies on putting black in zugzwang.
+        with_search("8/8/p1p5/1p5p/1P5p/8/PPP2K1p/4R1rk w - - 0 1", |search| {
+            search.set_time_control(TimeControl::FixedDepth(12));
+            let best_move = search.iterative_deepening();
+            assert_eq!(best_move.to_algebraic(), "e1f1");
+        });
+    }
+
+    #[test]
+    fn static_eval_is_taken_from_tt() {
+        // Store a bogus static eval which is far above beta. If the search
+        // reused it instead of evaluating, static beta pruning cuts the node
+        // without searching any moves.
+        with_search("4k3/8/8/8/8/8/8/R3K3 b - - 0 1", |search| {
+            let hash = search.hasher.get_hash();
+            search.tt.insert(
+                hash,
+                0,
+                TTScore::from_score(0, 1),
+                None,
+                UPPER_BOUND,
+                Some(5000),
+            );
+
+            // The search fails soft, returning the pruning's own estimate.
+            assert_eq!(
+                search.search(1, -1, 0, 2 * INC_PLY),
+                Some(5000 - 2 * STATIC_BETA_MARGIN)
+            );
+            assert_eq!(search.visited_nodes, 1);
+        });
+    }
+
+    /// Plays a game between two low depth searches, checking every move and
+    /// the incrementally updated hashes along the way.
+    fn play_game(fen: &str, depth: Ply, max_plies: usize) -> Option<GameResult> {
+        crate::magic::initialize_magics();
+
+        let mut position = Position::from(fen);
+        let mut hasher = Hasher::new();
+        hasher.from_position(&position);
+        let mut repetitions = Repetitions::new(100);
+        repetitions.push_position(hasher.get_hash());
+        let mut tt = TT::new(16);
+        let tt = tt.share();
+
+        for _ in 0..max_plies {
+            if let Some(result) = game_result(&position, &repetitions) {
+                return Some(result);
+            }
+
+            let mut search = Search::new(
+                Arc::new(AtomicBool::new(false)),
+                hasher.clone(),
+                PersistentOptions::default(),
+                position.clone(),
+                TimeControl::FixedDepth(depth),
+                &tt,
+                repetitions.clone(),
+            );
+            search.prepare_search();
+            let mov = search.iterative_deepening();
+            assert!(
+                position.legal_moves().contains(&mov),
+                "Illegal move {} in\n{}",
+                mov.to_algebraic(),
+                position
+            );
+
+            hasher.make_move(&position, mov);
+            position.make_move(mov);
+            if position.details.halfmove == 0 {
+                repetitions.irreversible_move();
+            }
+            repetitions.push_position(hasher.get_hash());
+
+            let mut expected = Hasher::new();
+            expected.from_position(&position);
+            assert_eq!(hasher.get_hash(), expected.get_hash());
+            assert_eq!(hasher.get_pawn_hash(), expected.get_pawn_hash());
+        }
+
+        None
+    }
+
+    #[test]
+    fn full_game_reaches_a_terminal_state() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(play_game(fen, 3, 1000).is_some());
+    }
+
+    #[test]
+    fn wider_aspiration_window_needs_fewer_researches() {
+        let fen = "4k3/8/8/8/8/8/PPP5/RNB1K3 w - - 0 1";
+        let nodes = |delta| {
+            let mut nodes = 0;
+            with_search(fen, |search| {
+                search.options.aspiration_delta = delta;
+                let mut moves = search
+                    .position
+                    .legal_moves()
+                    .into_iter()
+                    .map(|mov| (mov, 0))
+                    .collect::<Vec<_>>();
+
+                // The score is far away from the expected one of zero.
+                search.aspiration(0, &mut moves, 3 * INC_PLY).unwrap();
+                nodes = search.visited_nodes;
+            });
+            nodes
+        };
+
+        assert!(nodes(1000) < nodes(10));
+    }
+
+    #[test]
+    fn draw_score_jitter_stays_close_to_zero() {
+        with_search("4k3/8/8/8/8/8/8/4K3 w - - 0 1", |search| {
+            assert_eq!(search.draw_score(), 0);
+
+            search.options.draw_jitter = true;
+            let mut seen = Vec::new();
+            for nodes in 0..16 {
+                search.visited_nodes = nodes;
+                let score = search.draw_score();
+                assert!(score.abs() <= 2);
+                seen.push(score);
+            }
+            seen.sort();
+            seen.dedup();
+            assert_eq!(seen, vec![-1, 0, 1, 2]);
+        });
+    }
+
+    #[test]
+    fn draw_score_jitter_depends_on_the_seed() {
+        let jitter = |seed| {
+            let mut scores = Vec::new();
+            with_search("4k3/8/8/8/8/8/8/4K3 w - - 0 1", |search| {
+                search.options.draw_jitter = true;
+                search.set_seed(seed);
+                for nodes in 0..16 {
+                    search.visited_nodes = nodes;
+                    scores.push(search.draw_score());
+                }
+            });
+            scores
+        };
+
+        assert_eq!(jitter(7), jitter(7));
+        assert!((1..8).any(|seed| jitter(seed) != jitter(0)));
+
+        // Seeds which only differ in their high bits change the jitter too
+        assert!((1..8).any(|i| jitter(i << 40) != jitter(0)));
+    }
+
+    #[test]
+    fn qsearch_depth_is_capped() {
+        let fen = "q1r1k1r1/1b1n1b2/n7/1Q1R1R2/3B1N2/8/1R1N4/1Q2K3 w - - 0 1";
+        with_search(fen, |search| {
+            assert!(search.qsearch(1, -MATE_SCORE, MATE_SCORE, 0).is_some());
+            assert!(search.max_ply_searched > 3);
+        });
+
+        // Starting two plies above the cap, the capture sequences are cut
+        // off after the first capture
+        with_search(fen, |search| {
+            let depth = -QS_MAX_DEPTH + 2 * INC_PLY;
+            assert!(search.qsearch(1, -MATE_SCORE, MATE_SCORE, depth).is_some());
+            assert_eq!(search.max_ply_searched, 2);
+        });
+    }
+
+    #[test]
+    fn qsearch_searches_quiet_evasions_when_in_check() {
+        // Kf2 is the only legal move and not a capture.
+        with_search("7k/8/8/8/8/8/6PP/r5K1 w - - 0 1", |search| {
+            let score = search.qsearch(1, -MATE_SCORE, MATE_SCORE, 0).unwrap();
+            assert!(score > -MATE_SCORE + MAX_PLY, "score {}", score);
+        });
+
+        // With the f2 pawn in place it is a back rank mate.
+        with_search("7k/8/8/8/8/8/5PPP/r5K1 w - - 0 1", |search| {
+            let score = search.qsearch(1, -MATE_SCORE, MATE_SCORE, 0).unwrap();
+            assert_eq!(score, -MATE_SCORE + 1);
+        });
+    }
+
+    #[test]
+    fn killers_and_counter_moves_outrank_history() {
+        with_search(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            |search| {
+                let mov = |alg| Move::from_algebraic(&search.position, alg);
+                let (killer, other_killer, popular) = (mov("g1f3"), mov("b1c3"), mov("e2e4"));
+                let prev = Move {
+                    from: SQUARE_E8,
+                    to: SQUARE_E8.left(1),
+                    piece: Piece::King,
+                    ..Move::NULL
+                };
+
+                for _ in 0..10 {
+                    search.history.increase_score(true, popular, 20 * INC_PLY);
+                }
+                search.killers.update_killer(3, killer);
+                search.killers.update_killer(3, other_killer);
+
+                let score = |search: &Search, mov| search.quiet_move_score(mov, 3, Some(prev));
+                assert!(score(search, killer) > score(search, popular));
+                assert_eq!(score(search, killer), score(search, other_killer));
+
+                search.history.set_counter(prev, killer);
+                assert!(score(search, killer) > score(search, other_killer));
+                assert_eq!(
+                    search.quiet_move_score(killer, 3, None),
+                    score(search, other_killer)
+                );
+            },
+        );
+    }
+
+    #[test]
+    fn fail_low_returns_the_best_score() {
+        let fen = "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QKB1R w KQ - 0 8";
+        let alpha = 1000;
+
+        with_search(fen, |search| {
+            search.stack[1].hash = search.hasher.get_hash();
+            search.stack[1].pawn_hash = search.hasher.get_pawn_hash();
+            search.stack[1].material_hash = search.hasher.get_material_hash();
+
+            let score = search.search(1, alpha, alpha + 1, 3 * INC_PLY).unwrap();
+            assert!(score < alpha, "score {}", score);
+
+            let ttentry = search.tt.get(search.hasher.get_hash()).unwrap();
+            assert_eq!(ttentry.bound, UPPER_BOUND);
+            assert_eq!(ttentry.score.to_score(1), score);
+        });
+
+        // Without any captures, quiescence search falls back to the static
+        // evaluation instead of alpha.
+        with_search("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", |search| {
+            let eval = search.static_eval();
+            assert_eq!(search.qsearch(1, alpha, alpha + 1, 0), Some(eval));
+        });
+    }
+
+    #[test]
+    fn shallow_tt_move_is_still_used_for_ordering() {
+        let fen = "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP2BPPP/R2QKB1R w KQ - 0 8";
+        let depth = 6 * INC_PLY;
+
+        // Searches the position as a child of the root, which is where
+        // `search` restores the hashes from when unmaking moves.
+        let search_from_ply_one = |search: &mut Search, alpha, beta| {
+            search.stack[1].hash = search.hasher.get_hash();
+            search.stack[1].pawn_hash = search.hasher.get_pawn_hash();
+            search.stack[1].material_hash = search.hasher.get_material_hash();
+            search.search(1, alpha, beta, depth)
+        };
+
+        let mut best = None;
+        with_search(fen, |search| {
+            let score = search_from_ply_one(search, -MATE_SCORE, MATE_SCORE).unwrap();
+            let mov = search.get_tt_entry(search.hasher.get_hash()).1.unwrap();
+            best = Some((mov, score));
+        });
+        let (best_move, best_score) = best.unwrap();
+
+        // A depth 0 entry can't cause a cutoff, only its move is of use. In a
+        // null window just below the best score, the best move fails high
+        // right away when it is tried first.
+        let nodes_with_tt_move = |mov: Option<&str>| {
+            let mut nodes = 0;
+            with_search(fen, |search| {
+                let mov = mov.map_or(best_move, |m| Move::from_algebraic(&search.position, m));
+                search.tt.insert(
+                    search.hasher.get_hash(),
+                    0,
+                    TTScore::from_score(0, 1),
+                    Some(mov),
+                    UPPER_BOUND,
+                    None,
+                );
+                search_from_ply_one(search, best_score - 1, best_score);
+                nodes = search.visited_nodes;
+            });
+            nodes
+        };
+
+        // A poor TT move has to be refuted first
+        assert!(nodes_with_tt_move(None) < nodes_with_tt_move(Some("f3g1")));
+    }
+}
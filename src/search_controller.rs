@@ -14,18 +14,21 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
+use std::mem;
 use std::sync::atomic::AtomicBool;
 use std::sync::{self, Arc};
 
 use crossbeam::thread;
 
+use crate::eval::{Eval, Score};
 #[cfg(feature = "fathom")]
 use crate::fathom;
 use crate::hash::Hasher;
+use crate::history::History;
 use crate::movegen::{Move, MoveGenerator, MoveList};
 use crate::position::{Position, STARTING_POSITION};
 use crate::repetitions::Repetitions;
-use crate::search::{Depth, Search, INC_PLY};
+use crate::search::{Depth, Search, ASPIRATION_DELTA, ASPIRATION_GROWTH, INC_PLY};
 use crate::time::TimeControl;
 use crate::tt::{self, TT};
 use crate::uci::{GoParams, UciCommand};
@@ -38,6 +41,11 @@ pub struct PersistentOptions {
     pub move_overhead: u64,
     pub syzygy_directories: Vec<String>,
     pub syzygy_probe_depth: Depth,
+    pub aspiration_delta: Score,
+    pub aspiration_growth: Score,
+    pub draw_jitter: bool,
+    pub history_aging: bool,
+    pub chess960: bool,
 }
 
 impl Default for PersistentOptions {
@@ -49,6 +57,11 @@ impl Default for PersistentOptions {
             move_overhead: 10,
             syzygy_directories: Vec::new(),
             syzygy_probe_depth: 0,
+            aspiration_delta: ASPIRATION_DELTA,
+            aspiration_growth: ASPIRATION_GROWTH,
+            draw_jitter: false,
+            history_aging: false,
+            chess960: false,
         }
     }
 }
@@ -62,6 +75,7 @@ pub struct SearchController {
     time_control: TimeControl,
     tt: TT,
     repetitions: Repetitions,
+    history: History,
 }
 
 impl SearchController {
@@ -75,9 +89,10 @@ impl SearchController {
             time_control: TimeControl::Infinite,
             tt: TT::new(14),
             repetitions: Repetitions::new(100),
+            history: History::default(),
         };
 
-        controller.handle_position(position, vec![]);
+        controller.handle_position(Some(position), vec![]);
         controller
     }
 
@@ -97,6 +112,13 @@ impl SearchController {
             self.repetitions.clone(),
         );
 
+        // Keep the history of the previous search but age it, instead of
+        // starting from scratch.
+        if self.options.history_aging {
+            self.history.decay();
+            main_thread.history = mem::take(&mut self.history);
+        }
+
         let mov = thread::scope(|s| {
             main_thread.prepare_search();
 
@@ -116,6 +138,9 @@ impl SearchController {
         .unwrap();
 
         self.node_count = main_thread.visited_nodes;
+        if self.options.history_aging {
+            self.history = main_thread.history;
+        }
 
         mov
     }
@@ -128,8 +153,11 @@ impl SearchController {
         self.hasher.make_move(&self.position, mov);
         self.position.make_move(mov);
 
+        // Positions before an irreversible move can never repeat, so there is
+        // no need to keep them around. This keeps the history bounded even
+        // for very long move lists.
         if self.position.details.halfmove == 0 {
-            self.repetitions.irreversible_move();
+            self.repetitions.clear();
         }
         self.repetitions.push_position(self.hasher.get_hash());
     }
@@ -168,6 +196,7 @@ impl SearchController {
         self.tt = TT::new(self.options.hash_bits);
         self.repetitions = Repetitions::new(100);
         self.repetitions.push_position(self.hasher.get_hash());
+        self.history = History::default();
     }
 
     fn handle_uci(&mut self) {
@@ -179,6 +208,17 @@ impl SearchController {
         println!("option name MoveOverhead type spin default 10 min 0 max 10000");
         println!("option name SyzygyPath type string default <empty>");
         println!("option name SyzygyProbeDepth type spin default 0 min 0 max 127");
+        println!(
+            "option name AspirationDelta type spin default {} min 1 max 1000",
+            ASPIRATION_DELTA
+        );
+        println!(
+            "option name AspirationGrowth type spin default {} min 1 max 1000",
+            ASPIRATION_GROWTH
+        );
+        println!("option name DrawJitter type check default false");
+        println!("option name HistoryAging type check default false");
+        println!("option name UCI_Chess960 type check default false");
         self.handle_ucinewgame();
         println!("uciok");
     }
@@ -193,8 +233,12 @@ impl SearchController {
         println!("bestmove {}", bestmove.to_algebraic());
     }
 
-    fn handle_position(&mut self, pos: Position, moves: Vec<String>) {
-        self.position = pos;
+    /// Sets up `pos`, or the starting position for `None`, and plays `moves`
+    /// from it. The standard starting position is also a valid Chess960 one.
+    fn handle_position(&mut self, pos: Option<Position>, moves: Vec<String>) {
+        self.position = pos.unwrap_or(STARTING_POSITION);
+        // Castling in Chess960 FENs is detected anyway, the option also covers the start position
+        self.position.chess960 |= self.options.chess960;
         self.hasher.from_position(&self.position);
 
         self.repetitions.clear();
@@ -210,11 +254,8 @@ impl SearchController {
         match name.as_ref() {
             "hash" => {
                 if let Ok(mb) = value.parse::<usize>() {
-                    let hash_buckets = 1024 * 1024 * mb / 64; // 64 bytes per hash bucket
-                    let power_of_two = (hash_buckets + 1).next_power_of_two() / 2;
-                    let bits = power_of_two.trailing_zeros();
-                    self.tt = TT::new(u64::from(bits));
-                    self.options.hash_bits = u64::from(bits);
+                    self.tt.resize(mb);
+                    self.options.hash_bits = TT::bits_for_megabytes(mb);
                 } else {
                     eprintln!("Unable to parse value '{}' as integer", value);
                 }
@@ -260,6 +301,32 @@ impl SearchController {
                     eprintln!("Unable to parse value '{}' as integer", value);
                 }
             }
+            "aspirationdelta" => {
+                if let Ok(delta) = value.parse::<Score>() {
+                    self.options.aspiration_delta = delta.clamp(1, 1000);
+                } else {
+                    eprintln!("Unable to parse value '{}' as integer", value);
+                }
+            }
+            "aspirationgrowth" => {
+                if let Ok(growth) = value.parse::<Score>() {
+                    self.options.aspiration_growth = growth.clamp(1, 1000);
+                } else {
+                    eprintln!("Unable to parse value '{}' as integer", value);
+                }
+            }
+            "drawjitter" => {
+                self.options.draw_jitter = value.eq_ignore_ascii_case("true");
+            }
+            "historyaging" => {
+                self.options.history_aging = value.eq_ignore_ascii_case("true");
+            }
+            "uci_chess960" => {
+                self.options.chess960 = value.eq_ignore_ascii_case("true");
+                // Castling rights of displaced kings or rooks still need Chess960 rules
+                self.position.chess960 =
+                    self.options.chess960 || self.position.needs_chess960_castling();
+            }
             _ => {
                 eprintln!("Unrecognized option {}", name);
             }
@@ -287,7 +354,7 @@ impl SearchController {
     }
 
     fn handle_d(&self) {
-        self.position.print("");
+        print!("{}", self.describe_position());
 
         #[cfg(feature = "fathom")]
         {
@@ -299,6 +366,25 @@ impl SearchController {
         }
     }
 
+    /// The board, FEN, hash and static evaluation (from the side to move's
+    /// point of view) of the current position, as printed by `d`.
+    fn describe_position(&self) -> String {
+        let eval = Eval::from(&self.position);
+        let score = eval.score_uncached(
+            &self.position,
+            self.hasher.get_pawn_hash(),
+            self.hasher.get_material_hash(),
+        );
+
+        format!(
+            "{}\nFen:  {}\nHash: 0x{:0>8x}\nEval: {}\n",
+            self.position.to_ascii(),
+            self.position.to_fen(),
+            self.hasher.get_hash(),
+            score
+        )
+    }
+
     fn handle_tt(&mut self) {
         println!("Current hash: 0x{:0>8x}", self.hasher.get_hash());
         let tt = self.tt.get(self.hasher.get_hash());
@@ -336,3 +422,144 @@ impl SearchController {
         thread.perft(depth);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn d_shows_fen_and_eval() {
+        crate::magic::initialize_magics();
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let controller = SearchController::new(STARTING_POSITION, abort);
+        let output = controller.describe_position();
+
+        assert!(output.contains("Fen:  rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+        let eval = output
+            .lines()
+            .find(|line| line.starts_with("Eval: "))
+            .unwrap();
+        assert!(eval["Eval: ".len()..].parse::<Score>().is_ok());
+    }
+
+    #[test]
+    fn long_move_lists_keep_the_history_bounded() {
+        crate::magic::initialize_magics();
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let mut controller = SearchController::new(STARTING_POSITION, abort);
+
+        // Shuffling knights never resets the halfmove clock.
+        let shuffle: Vec<String> = ["g1f3", "g8f6", "f3g1", "f6g8"]
+            .iter()
+            .cycle()
+            .take(200)
+            .map(|mov| mov.to_string())
+            .collect();
+        controller.handle_position(Some(STARTING_POSITION), shuffle);
+        assert_eq!(
+            controller.position.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 200 101"
+        );
+        assert_eq!(controller.repetitions.positions().len(), 201);
+
+        // A line with pawn moves and captures only keeps the positions since
+        // the last of them.
+        let mut pos = STARTING_POSITION;
+        let mut line = Vec::new();
+        for i in 0..200 {
+            let moves = pos.legal_moves();
+            if moves.is_empty() {
+                break;
+            }
+            let mov = moves[(7 * i) % moves.len()];
+            line.push(mov.to_algebraic());
+            pos.make_move(mov);
+        }
+        controller.handle_position(Some(STARTING_POSITION), line);
+        assert_eq!(controller.position.to_fen(), pos.to_fen());
+        assert_eq!(
+            controller.repetitions.positions().len(),
+            pos.details.halfmove as usize + 1
+        );
+    }
+
+    #[test]
+    fn chess960_option_changes_castling_notation() {
+        crate::magic::initialize_magics();
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let mut controller = SearchController::new(STARTING_POSITION, abort);
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        let castle =
+            |controller: &SearchController| controller.position.castling_move(true).to_algebraic();
+
+        controller.handle_position(Some(Position::from(fen)), vec![]);
+        assert_eq!(castle(&controller), "e1g1");
+        controller.handle_position(Some(Position::from(fen)), vec![String::from("e1g1")]);
+        assert_eq!(
+            controller.position.to_fen(),
+            "r3k2r/8/8/8/8/8/8/R4RK1 b kq - 1 1"
+        );
+
+        controller.handle_setoption(String::from("uci_chess960"), String::from("true"));
+        controller.handle_position(Some(Position::from(fen)), vec![]);
+        assert_eq!(castle(&controller), "e1h1");
+        assert_eq!(
+            controller.position.to_fen(),
+            "r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1"
+        );
+        controller.handle_position(Some(Position::from(fen)), vec![String::from("e1h1")]);
+        assert_eq!(
+            controller.position.to_fen(),
+            "r3k2r/8/8/8/8/8/8/R4RK1 b ha - 1 1"
+        );
+
+        // The standard starting position is a Chess960 one as well
+        controller.handle_position(None, vec![String::from("e2e4")]);
+        assert_eq!(
+            controller.position.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b HAha - 0 1"
+        );
+
+        // Switching the option off clears the flag of the current position
+        controller.handle_position(Some(Position::from(fen)), vec![]);
+        controller.handle_setoption(String::from("uci_chess960"), String::from("false"));
+        assert!(!controller.position.chess960);
+        assert_eq!(castle(&controller), "e1g1");
+
+        controller.handle_position(None, vec![]);
+        assert_eq!(controller.position, STARTING_POSITION);
+        assert_eq!(castle(&controller), "e1g1");
+
+        // unless its castling rights only make sense in Chess960
+        let displaced = Position::from("1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1");
+        controller.handle_position(Some(displaced), vec![]);
+        controller.handle_setoption(String::from("uci_chess960"), String::from("false"));
+        assert!(controller.position.chess960);
+        assert_eq!(castle(&controller), "e1g1");
+    }
+
+    #[test]
+    fn aspiration_options_are_clamped() {
+        crate::magic::initialize_magics();
+
+        let abort = Arc::new(AtomicBool::new(false));
+        let mut controller = SearchController::new(STARTING_POSITION, abort);
+
+        controller.handle_setoption(String::from("aspirationdelta"), String::from("0"));
+        assert_eq!(controller.options.aspiration_delta, 1);
+        controller.handle_setoption(String::from("aspirationdelta"), String::from("-20"));
+        assert_eq!(controller.options.aspiration_delta, 1);
+        controller.handle_setoption(String::from("aspirationdelta"), String::from("5000"));
+        assert_eq!(controller.options.aspiration_delta, 1000);
+        controller.handle_setoption(String::from("aspirationdelta"), String::from("25"));
+        assert_eq!(controller.options.aspiration_delta, 25);
+
+        controller.handle_setoption(String::from("aspirationgrowth"), String::from("0"));
+        assert_eq!(controller.options.aspiration_growth, 1);
+        controller.handle_setoption(String::from("aspirationgrowth"), String::from("abc"));
+        assert_eq!(controller.options.aspiration_growth, 1);
+    }
+}
@@ -104,7 +104,16 @@ impl TimeManager {
             };
             let inc = if self.searching_for_white { winc } else { binc }.unwrap_or(0);
 
-            let initial = cmp::min(time, time / movestogo.unwrap_or(40) + inc);
+            let initial = match movestogo {
+                // Sudden death, assume the game lasts another 40 moves
+                None => cmp::min(time, time / 40 + inc),
+                // Spread the time over the moves until the next time control,
+                // keeping a small reserve so the last move isn't played on zero
+                Some(moves) => {
+                    let usable = time - time / 20;
+                    cmp::min(time, usable / cmp::max(moves, 1) + inc)
+                }
+            };
             let target = initial;
             let maximum = target + (time - target) / 4;
 
@@ -201,3 +210,58 @@ impl TimeManager {
         self.dynamic.target = cmp::min(self.dynamic.maximum, self.dynamic.target * 3 / 2);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::STARTING_POSITION;
+
+    #[test]
+    fn increment_is_part_of_the_allocation() {
+        let abort = sync::Arc::new(sync::atomic::AtomicBool::new(false));
+        let one_plus_one = |wtime| TimeControl::Variable {
+            wtime,
+            btime: 60_000,
+            winc: Some(1000),
+            binc: Some(1000),
+            movestogo: None,
+        };
+
+        let mut tm = TimeManager::new(&STARTING_POSITION, one_plus_one(60_000), 10, abort);
+        assert!(tm.dynamic.target > 1000);
+
+        for &wtime in &[10_000, 2000, 1000, 300, 50] {
+            tm.update(&STARTING_POSITION, one_plus_one(wtime));
+            assert!(tm.dynamic.target > 0);
+            assert!(tm.dynamic.maximum <= wtime);
+            if wtime >= 2000 {
+                // The increment is spent as long as there is enough time left.
+                assert!(tm.dynamic.target >= 1000);
+            }
+        }
+    }
+
+    #[test]
+    fn movestogo_divides_the_remaining_time() {
+        let abort = sync::Arc::new(sync::atomic::AtomicBool::new(false));
+        let moves_to_go = |movestogo| TimeControl::Variable {
+            wtime: 60_000,
+            btime: 60_000,
+            winc: None,
+            binc: None,
+            movestogo: Some(movestogo),
+        };
+
+        let mut tm = TimeManager::new(&STARTING_POSITION, moves_to_go(1), 10, abort);
+        assert!(tm.dynamic.target >= 54_000);
+        assert!(tm.dynamic.maximum < 60_000);
+
+        tm.update(&STARTING_POSITION, moves_to_go(40));
+        assert!(tm.dynamic.target >= 60_000 / 45);
+        assert!(tm.dynamic.target <= 60_000 / 40);
+
+        // A GUI sending movestogo 0 must not make us divide by zero.
+        tm.update(&STARTING_POSITION, moves_to_go(0));
+        assert!(tm.dynamic.maximum < 60_000);
+    }
+}
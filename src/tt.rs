@@ -23,6 +23,7 @@ use crate::search::*;
 
 use std::cell;
 use std::cmp;
+use std::mem;
 
 pub struct TT {
     table: Vec<Bucket>,
@@ -45,6 +46,19 @@ impl TT {
         }
     }
 
+    /// Replaces the table with an empty one of at most `megabytes` size.
+    pub fn resize(&mut self, megabytes: usize) {
+        *self = TT::new(TT::bits_for_megabytes(megabytes));
+    }
+
+    /// Number of index bits of the largest table fitting into `megabytes`.
+    /// The table always has at least one bucket.
+    pub fn bits_for_megabytes(megabytes: usize) -> u64 {
+        let buckets = 1024 * 1024 * megabytes / mem::size_of::<Bucket>();
+        let power_of_two = (cmp::max(buckets, 1) + 1).next_power_of_two() / 2;
+        u64::from(power_of_two.trailing_zeros())
+    }
+
     pub fn usage(&self) -> u64 {
         let n = cmp::min(300, self.table.len());
         let total = n * NUM_CLUSTERS;
@@ -263,8 +277,15 @@ impl TTMove {
             captured: None,
             promoted: None,
             en_passant: self.from & EN_PASSANT_FLAG > 0,
+            castling: false,
         };
 
+        if result.piece == Piece::King {
+            if let Some(castling) = pos.castling_move_to(result.from, result.to) {
+                return Some(castling);
+            }
+        }
+
         if self.from & CAPTURE_FLAG > 0 {
             if result.en_passant {
                 result.captured = Some(Piece::Pawn);
@@ -362,4 +383,43 @@ mod tests {
         assert_eq!(::std::mem::align_of::<TTEntry>(), 16);
         assert_eq!(::std::mem::align_of::<Bucket>(), 64);
     }
+
+    #[test]
+    fn hash_size_is_rounded_down_to_a_power_of_two() {
+        assert_eq!(TT::bits_for_megabytes(0), 0);
+        assert_eq!(TT::bits_for_megabytes(1), 14);
+        assert_eq!(TT::bits_for_megabytes(3), 15);
+        assert_eq!(TT::bits_for_megabytes(64), 20);
+
+        let hash = 0xABCD_0000_0001;
+        let mut tt = TT::new(1);
+        tt.insert(hash, INC_PLY, TTScore(0), None, LOWER_BOUND, None);
+        assert!(tt.get(hash).is_some());
+        tt.resize(1);
+        assert_eq!(tt.table.len(), 1 << 14);
+        assert_eq!(tt.get(hash), None);
+    }
+
+    #[test]
+    fn entries_store_static_eval() {
+        let mut tt = TT::new(1);
+        tt.insert(1, INC_PLY, TTScore(0), None, LOWER_BOUND, Some(-123));
+        tt.insert(2, INC_PLY, TTScore(0), None, LOWER_BOUND, None);
+
+        assert_eq!(tt.get(1).and_then(|entry| entry.get_eval()), Some(-123));
+        assert_eq!(tt.get(2).and_then(|entry| entry.get_eval()), None);
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_node() {
+        // Mate five plies after a node at ply 2 is a mate at ply 7 from the root.
+        let mate = TTScore::from_score(MATE_SCORE - 7, 2);
+        assert_eq!(mate.to_score(4), MATE_SCORE - 9);
+
+        let mated = TTScore::from_score(-MATE_SCORE + 7, 2);
+        assert_eq!(mated.to_score(4), -MATE_SCORE + 9);
+
+        let normal = TTScore::from_score(150, 2);
+        assert_eq!(normal.to_score(4), 150);
+    }
 }
@@ -67,6 +67,7 @@ const TUNE_PAWNS_DOUBLED: bool = false;
 const TUNE_PAWNS_ISOLATED: bool = false;
 const TUNE_PAWNS_OPEN_ISOLATED: bool = false;
 const TUNE_PAWNS_PASSED: bool = false;
+const TUNE_PAWNS_CONNECTED_PASSED: bool = false;
 
 const TUNE_KNIGHT_OUTPOST: bool = false;
 
@@ -77,6 +78,25 @@ const TUNE_ROOKS_OPEN_FILE: bool = false;
 const TUNE_ROOKS_HALFOPEN_FILE: bool = false;
 const TUNE_ROOKS_PAIR: bool = false;
 
+const TUNE_TROPISM: bool = false;
+const TUNE_KING_OPPOSITION: bool = false;
+const TUNE_KING_STUCK: bool = false;
+const TUNE_KNIGHTS_PAIR: bool = false;
+const TUNE_KNIGHTS_PAWNS: bool = false;
+const TUNE_EARLY_QUEEN: bool = false;
+const TUNE_ROOKS_KING_FILE: bool = false;
+const TUNE_BISHOP_PAIR_PAWNS: bool = false;
+const TUNE_PASSED_PAWN_PATH: bool = false;
+const TUNE_CONNECTED_ROOKS: bool = false;
+const TUNE_TRAPPED_ROOK: bool = false;
+const TUNE_PAWN_MAJORITY: bool = false;
+const TUNE_PAWNS_PROTECTED_PASSED: bool = false;
+const TUNE_PASSED_PAWN_BLOCKED: bool = false;
+const TUNE_PASSED_PAWN_KING_CLOSER: bool = false;
+const TUNE_FIANCHETTO_HOLES: bool = false;
+const TUNE_KING_ATTACKER_PROXIMITY: bool = false;
+const TUNE_MINORS_VS_ROOK: bool = false;
+
 const TUNE_KING_SAFETY: bool = false;
 const TUNE_KING_CHECK_KNIGHT: bool = false;
 const TUNE_KING_CHECK_BISHOP: bool = false;
@@ -125,6 +145,27 @@ pub struct Trace {
     pub rooks_halfopen_file: [i8; 2],
     pub rooks_pair: [i8; 2],
 
+    pub tropism: [[i8; 2]; 6],
+    pub opposition: [i8; 2],
+    pub pawns_connected_passed: [[i8; 2]; 8],
+    pub king_stuck: [i8; 2],
+    pub knights_pair: [i8; 2],
+    pub knights_pawns: [i8; 2],
+    pub early_queen: [i8; 2],
+    pub rooks_king_file: [i8; 2],
+    pub bishop_pair_pawns: [i8; 2],
+    pub passed_contested_path: [i8; 2],
+    pub passed_defended_path: [i8; 2],
+    pub connected_rooks: [i8; 2],
+    pub trapped_rook: [i8; 2],
+    pub pawn_majority: [i8; 2],
+    pub pawns_protected_passed: [[i8; 2]; 8],
+    pub passed_blocked: [i8; 2],
+    pub passed_king_closer: [i8; 2],
+    pub fianchetto_holes: [i8; 2],
+    pub king_attacker_proximity: [i8; 2],
+    pub minors_vs_rook: [i8; 2],
+
     pub king_safety: [[i8; 2]; 30],
     pub king_check_knight: [i8; 2],
     pub king_check_bishop: [i8; 2],
@@ -268,6 +309,89 @@ impl From<Trace> for CompactTrace {
             linear.push(t.rooks_pair[1] - t.rooks_pair[0]);
         }
 
+        if TUNE_TROPISM {
+            for i in 0..6 {
+                linear.push(t.tropism[i][1] - t.tropism[i][0]);
+            }
+        }
+
+        if TUNE_KING_OPPOSITION {
+            linear.push(t.opposition[1] - t.opposition[0]);
+        }
+
+        if TUNE_PAWNS_CONNECTED_PASSED {
+            for i in 0..8 {
+                linear.push(t.pawns_connected_passed[i][1] - t.pawns_connected_passed[i][0]);
+            }
+        }
+
+        if TUNE_KING_STUCK {
+            linear.push(t.king_stuck[1] - t.king_stuck[0]);
+        }
+
+        if TUNE_KNIGHTS_PAIR {
+            linear.push(t.knights_pair[1] - t.knights_pair[0]);
+        }
+
+        if TUNE_KNIGHTS_PAWNS {
+            linear.push(t.knights_pawns[1] - t.knights_pawns[0]);
+        }
+
+        if TUNE_EARLY_QUEEN {
+            linear.push(t.early_queen[1] - t.early_queen[0]);
+        }
+
+        if TUNE_ROOKS_KING_FILE {
+            linear.push(t.rooks_king_file[1] - t.rooks_king_file[0]);
+        }
+
+        if TUNE_BISHOP_PAIR_PAWNS {
+            linear.push(t.bishop_pair_pawns[1] - t.bishop_pair_pawns[0]);
+        }
+
+        if TUNE_PASSED_PAWN_PATH {
+            linear.push(t.passed_contested_path[1] - t.passed_contested_path[0]);
+            linear.push(t.passed_defended_path[1] - t.passed_defended_path[0]);
+        }
+
+        if TUNE_CONNECTED_ROOKS {
+            linear.push(t.connected_rooks[1] - t.connected_rooks[0]);
+        }
+
+        if TUNE_TRAPPED_ROOK {
+            linear.push(t.trapped_rook[1] - t.trapped_rook[0]);
+        }
+
+        if TUNE_PAWN_MAJORITY {
+            linear.push(t.pawn_majority[1] - t.pawn_majority[0]);
+        }
+
+        if TUNE_PAWNS_PROTECTED_PASSED {
+            for i in 0..8 {
+                linear.push(t.pawns_protected_passed[i][1] - t.pawns_protected_passed[i][0]);
+            }
+        }
+
+        if TUNE_PASSED_PAWN_BLOCKED {
+            linear.push(t.passed_blocked[1] - t.passed_blocked[0]);
+        }
+
+        if TUNE_PASSED_PAWN_KING_CLOSER {
+            linear.push(t.passed_king_closer[1] - t.passed_king_closer[0]);
+        }
+
+        if TUNE_FIANCHETTO_HOLES {
+            linear.push(t.fianchetto_holes[1] - t.fianchetto_holes[0]);
+        }
+
+        if TUNE_KING_ATTACKER_PROXIMITY {
+            linear.push(t.king_attacker_proximity[1] - t.king_attacker_proximity[0]);
+        }
+
+        if TUNE_MINORS_VS_ROOK {
+            linear.push(t.minors_vs_rook[1] - t.minors_vs_rook[0]);
+        }
+
         if TUNE_PST_PAWN {
             for i in ALL_SQUARES.squares() {
                 linear.push(t.pst_pawn[i][1] - t.pst_pawn[i][0]);
@@ -471,6 +595,27 @@ impl Default for Trace {
             rooks_halfopen_file: [0; 2],
             rooks_pair: [0; 2],
 
+            tropism: [[0; 2]; 6],
+            opposition: [0; 2],
+            pawns_connected_passed: [[0; 2]; 8],
+            king_stuck: [0; 2],
+            knights_pair: [0; 2],
+            knights_pawns: [0; 2],
+            early_queen: [0; 2],
+            rooks_king_file: [0; 2],
+            bishop_pair_pawns: [0; 2],
+            passed_contested_path: [0; 2],
+            passed_defended_path: [0; 2],
+            connected_rooks: [0; 2],
+            trapped_rook: [0; 2],
+            pawn_majority: [0; 2],
+            pawns_protected_passed: [[0; 2]; 8],
+            passed_blocked: [0; 2],
+            passed_king_closer: [0; 2],
+            fianchetto_holes: [0; 2],
+            king_attacker_proximity: [0; 2],
+            minors_vs_rook: [0; 2],
+
             king_safety: [[0; 2]; 30],
             king_check_knight: [0; 2],
             king_check_bishop: [0; 2],
@@ -493,11 +638,9 @@ impl Trace {
     pub fn from_position(result: f32, mut position: Position) -> Trace {
         let (_, pos) = qsearch(&mut position, -MATE_SCORE, MATE_SCORE);
 
-        let mut e = Eval::from(&pos);
-        let _ = e.score(&pos, 0);
-        e.trace.result = result;
-
-        e.trace
+        let mut trace = Eval::from(&pos).trace(&pos);
+        trace.result = result;
+        trace
     }
 }
 
@@ -654,6 +797,103 @@ impl Parameters {
             i += 1;
         }
 
+        if TUNE_TROPISM {
+            print_array(&self.linear[i..i + 6], "TROPISM_WEIGHT");
+            i += 6;
+        }
+
+        if TUNE_KING_OPPOSITION {
+            print_single(self.linear[i], "KING_OPPOSITION");
+            i += 1;
+        }
+
+        if TUNE_PAWNS_CONNECTED_PASSED {
+            print_array(&self.linear[i..i + 8], "CONNECTED_PASSED_PAWN");
+            i += 8;
+        }
+
+        if TUNE_KING_STUCK {
+            print_single(self.linear[i], "KING_STUCK_PENALTY");
+            i += 1;
+        }
+
+        if TUNE_KNIGHTS_PAIR {
+            print_single(self.linear[i], "KNIGHT_PAIR");
+            i += 1;
+        }
+
+        if TUNE_KNIGHTS_PAWNS {
+            print_single(self.linear[i], "KNIGHT_PAWNS");
+            i += 1;
+        }
+
+        if TUNE_EARLY_QUEEN {
+            print_single(self.linear[i], "EARLY_QUEEN_PENALTY");
+            i += 1;
+        }
+
+        if TUNE_ROOKS_KING_FILE {
+            print_single(self.linear[i], "ROOK_KING_FILE");
+            i += 1;
+        }
+
+        if TUNE_BISHOP_PAIR_PAWNS {
+            print_single(self.linear[i], "BISHOP_PAIR_PAWNS");
+            i += 1;
+        }
+
+        if TUNE_PASSED_PAWN_PATH {
+            print_single(self.linear[i], "PASSED_PAWN_CONTESTED_PATH");
+            i += 1;
+            print_single(self.linear[i], "PASSED_PAWN_DEFENDED_PATH");
+            i += 1;
+        }
+
+        if TUNE_CONNECTED_ROOKS {
+            print_single(self.linear[i], "CONNECTED_ROOKS_BONUS");
+            i += 1;
+        }
+
+        if TUNE_TRAPPED_ROOK {
+            print_single(self.linear[i], "TRAPPED_ROOK_PENALTY");
+            i += 1;
+        }
+
+        if TUNE_PAWN_MAJORITY {
+            print_single(self.linear[i], "PAWN_MAJORITY_BONUS");
+            i += 1;
+        }
+
+        if TUNE_PAWNS_PROTECTED_PASSED {
+            print_array(&self.linear[i..i + 8], "PROTECTED_PASSED_PAWN");
+            i += 8;
+        }
+
+        if TUNE_PASSED_PAWN_BLOCKED {
+            print_single(self.linear[i], "PASSED_PAWN_BLOCKED");
+            i += 1;
+        }
+
+        if TUNE_PASSED_PAWN_KING_CLOSER {
+            print_single(self.linear[i], "PASSED_PAWN_KING_CLOSER");
+            i += 1;
+        }
+
+        if TUNE_FIANCHETTO_HOLES {
+            print_single(self.linear[i], "FIANCHETTO_HOLES_PENALTY");
+            i += 1;
+        }
+
+        if TUNE_KING_ATTACKER_PROXIMITY {
+            print_single(self.linear[i], "KING_ATTACKER_PROXIMITY");
+            i += 1;
+        }
+
+        if TUNE_MINORS_VS_ROOK {
+            print_single(self.linear[i], "MINORS_VS_ROOK");
+            i += 1;
+        }
+
         if TUNE_PST_PAWN {
             print_pst(&self.linear[i..i + 64], "PAWN_PST");
             i += 64;
@@ -710,6 +950,81 @@ impl Parameters {
         }
     }
 
+    /// Number of weights in the flat representation returned by `to_vec`:
+    /// an mg/eg pair for every linear weight followed by the mg-only king
+    /// safety weights.
+    pub fn len(&self) -> usize {
+        2 * self.linear.len()
+            + self.king_safety.len()
+            + 4
+            + self.king_danger.len()
+            + self.king_danger_attacks.len()
+    }
+
+    /// Every evaluation weight, regardless of the `TUNE_*` flags. These are
+    /// the parameters `to_vec` and `from_vec` work on.
+    pub fn all() -> Parameters {
+        Parameters::new(true)
+    }
+
+    /// Flattens every evaluation weight into a single vector, so that an
+    /// external optimizer can work on them. The linear weights come first as
+    /// mg/eg pairs, in the order of the `TUNE_*` flags from material down to
+    /// the piece square tables, followed by the mg-only king safety, check and
+    /// king danger weights. `self` has to hold all weights, as returned by
+    /// `all` or `from_vec`.
+    ///
+    /// The weights are rounded to integers like in the evaluation itself, so
+    /// only whole valued parameters survive a round trip through `from_vec`
+    /// unchanged.
+    pub fn to_vec(&self) -> Vec<i32> {
+        assert_eq!(self.linear.len(), Parameters::all().linear.len());
+        let mut values = Vec::with_capacity(self.len());
+
+        for &(mg, eg) in &self.linear {
+            values.push(mg.round() as i32);
+            values.push(eg.round() as i32);
+        }
+
+        values.extend(self.king_safety.iter().map(|x| x.round() as i32));
+        values.push(self.king_check_knight.round() as i32);
+        values.push(self.king_check_bishop.round() as i32);
+        values.push(self.king_check_rook.round() as i32);
+        values.push(self.king_check_queen.round() as i32);
+        values.extend(self.king_danger.iter().map(|x| x.round() as i32));
+        values.extend(self.king_danger_attacks.iter().map(|x| x.round() as i32));
+
+        values
+    }
+
+    /// Inverse of `to_vec`.
+    pub fn from_vec(values: &[i32]) -> Parameters {
+        let mut params = Parameters::all();
+        assert_eq!(values.len(), params.len());
+
+        let mut values = values.iter().map(|&x| x as f32);
+        for weight in params.linear.iter_mut() {
+            weight.0 = values.next().unwrap();
+            weight.1 = values.next().unwrap();
+        }
+
+        for weight in params.king_safety.iter_mut() {
+            *weight = values.next().unwrap();
+        }
+        params.king_check_knight = values.next().unwrap();
+        params.king_check_bishop = values.next().unwrap();
+        params.king_check_rook = values.next().unwrap();
+        params.king_check_queen = values.next().unwrap();
+        for weight in params.king_danger.iter_mut() {
+            *weight = values.next().unwrap();
+        }
+        for weight in params.king_danger_attacks.iter_mut() {
+            *weight = values.next().unwrap();
+        }
+
+        params
+    }
+
     pub fn total_error(&self, traces: &[CompactTrace]) -> f32 {
         let mut total = 0.;
         let n = traces.len() as f32;
@@ -890,77 +1205,85 @@ impl Parameters {
 
 impl Default for Parameters {
     fn default() -> Parameters {
+        Parameters::new(false)
+    }
+}
+
+impl Parameters {
+    /// Collects the weights enabled by the `TUNE_*` flags, or every weight if
+    /// `all` is set.
+    fn new(all: bool) -> Parameters {
         let mut linear = Vec::new();
 
-        if TUNE_MATERIAL_PAWN {
+        if all || TUNE_MATERIAL_PAWN {
             linear.push((mg(PAWN_SCORE) as f32, eg(PAWN_SCORE) as f32));
         }
 
-        if TUNE_MATERIAL_KNIGHT {
+        if all || TUNE_MATERIAL_KNIGHT {
             linear.push((mg(KNIGHT_SCORE) as f32, eg(KNIGHT_SCORE) as f32));
         }
 
-        if TUNE_MATERIAL_BISHOP {
+        if all || TUNE_MATERIAL_BISHOP {
             linear.push((mg(BISHOP_SCORE) as f32, eg(BISHOP_SCORE) as f32));
         }
 
-        if TUNE_MATERIAL_ROOK {
+        if all || TUNE_MATERIAL_ROOK {
             linear.push((mg(ROOK_SCORE) as f32, eg(ROOK_SCORE) as f32));
         }
 
-        if TUNE_MATERIAL_QUEEN {
+        if all || TUNE_MATERIAL_QUEEN {
             linear.push((mg(QUEEN_SCORE) as f32, eg(QUEEN_SCORE) as f32));
         }
 
-        if TUNE_TEMPO {
+        if all || TUNE_TEMPO {
             linear.push((mg(TEMPO_SCORE) as f32, eg(TEMPO_SCORE) as f32));
         }
 
-        if TUNE_CENTER_CONTROL {
+        if all || TUNE_CENTER_CONTROL {
             linear.push((mg(CENTER_CONTROL) as f32, eg(CENTER_CONTROL) as f32));
         }
 
-        if TUNE_MOBILITY_PAWN {
+        if all || TUNE_MOBILITY_PAWN {
             linear.push((mg(PAWN_MOBILITY) as f32, eg(PAWN_MOBILITY) as f32));
         }
 
-        if TUNE_MOBILITY_KNIGHT {
+        if all || TUNE_MOBILITY_KNIGHT {
             for i in 0..9 {
                 linear.push((mg(KNIGHT_MOBILITY[i]) as f32, eg(KNIGHT_MOBILITY[i]) as f32));
             }
         }
 
-        if TUNE_MOBILITY_BISHOP {
+        if all || TUNE_MOBILITY_BISHOP {
             for i in 0..14 {
                 linear.push((mg(BISHOP_MOBILITY[i]) as f32, eg(BISHOP_MOBILITY[i]) as f32));
             }
         }
 
-        if TUNE_MOBILITY_ROOK {
+        if all || TUNE_MOBILITY_ROOK {
             for i in 0..15 {
                 linear.push((mg(ROOK_MOBILITY[i]) as f32, eg(ROOK_MOBILITY[i]) as f32));
             }
         }
 
-        if TUNE_MOBILITY_QUEEN {
+        if all || TUNE_MOBILITY_QUEEN {
             for i in 0..29 {
                 linear.push((mg(QUEEN_MOBILITY[i]) as f32, eg(QUEEN_MOBILITY[i]) as f32));
             }
         }
 
-        if TUNE_PAWNS_DOUBLED {
+        if all || TUNE_PAWNS_DOUBLED {
             linear.push((mg(DOUBLED_PAWN) as f32, eg(DOUBLED_PAWN) as f32));
         }
 
-        if TUNE_PAWNS_ISOLATED {
+        if all || TUNE_PAWNS_ISOLATED {
             linear.push((mg(ISOLATED_PAWN) as f32, eg(ISOLATED_PAWN) as f32));
         }
 
-        if TUNE_PAWNS_OPEN_ISOLATED {
+        if all || TUNE_PAWNS_OPEN_ISOLATED {
             linear.push((mg(OPEN_ISOLATED_PAWN) as f32, eg(OPEN_ISOLATED_PAWN) as f32));
         }
 
-        if TUNE_PAWNS_PASSED {
+        if all || TUNE_PAWNS_PASSED {
             for &weight in PASSED_PAWN_ON_RANK.iter() {
                 linear.push((mg(weight) as f32, eg(weight) as f32));
             }
@@ -970,61 +1293,174 @@ impl Default for Parameters {
             }
         }
 
-        if TUNE_KNIGHT_OUTPOST {
+        if all || TUNE_KNIGHT_OUTPOST {
             linear.push((mg(KNIGHT_OUTPOST) as f32, eg(KNIGHT_OUTPOST) as f32));
         }
 
-        if TUNE_BISHOPS_PAIR {
+        if all || TUNE_BISHOPS_PAIR {
             linear.push((mg(BISHOP_PAIR) as f32, eg(BISHOP_PAIR) as f32));
         }
 
-        if TUNE_BISHOPS_XRAY {
+        if all || TUNE_BISHOPS_XRAY {
             linear.push((mg(XRAYED_SQUARE) as f32, eg(XRAYED_SQUARE) as f32));
         }
 
-        if TUNE_ROOKS_HALFOPEN_FILE {
+        if all || TUNE_ROOKS_HALFOPEN_FILE {
             linear.push((mg(ROOK_HALFOPEN_FILE) as f32, eg(ROOK_HALFOPEN_FILE) as f32));
         }
 
-        if TUNE_ROOKS_OPEN_FILE {
+        if all || TUNE_ROOKS_OPEN_FILE {
             linear.push((mg(ROOK_OPEN_FILE) as f32, eg(ROOK_OPEN_FILE) as f32));
         }
 
-        if TUNE_ROOKS_PAIR {
+        if all || TUNE_ROOKS_PAIR {
             linear.push((mg(ROOK_PAIR) as f32, eg(ROOK_PAIR) as f32));
         }
 
-        if TUNE_PST_PAWN {
+        if all || TUNE_TROPISM {
+            for &weight in TROPISM_WEIGHT.iter() {
+                linear.push((mg(weight) as f32, eg(weight) as f32));
+            }
+        }
+
+        if all || TUNE_KING_OPPOSITION {
+            linear.push((mg(KING_OPPOSITION) as f32, eg(KING_OPPOSITION) as f32));
+        }
+
+        if all || TUNE_PAWNS_CONNECTED_PASSED {
+            for &weight in CONNECTED_PASSED_PAWN.iter() {
+                linear.push((mg(weight) as f32, eg(weight) as f32));
+            }
+        }
+
+        if all || TUNE_KING_STUCK {
+            linear.push((mg(KING_STUCK_PENALTY) as f32, eg(KING_STUCK_PENALTY) as f32));
+        }
+
+        if all || TUNE_KNIGHTS_PAIR {
+            linear.push((mg(KNIGHT_PAIR) as f32, eg(KNIGHT_PAIR) as f32));
+        }
+
+        if all || TUNE_KNIGHTS_PAWNS {
+            linear.push((mg(KNIGHT_PAWNS) as f32, eg(KNIGHT_PAWNS) as f32));
+        }
+
+        if all || TUNE_EARLY_QUEEN {
+            linear.push((
+                mg(EARLY_QUEEN_PENALTY) as f32,
+                eg(EARLY_QUEEN_PENALTY) as f32,
+            ));
+        }
+
+        if all || TUNE_ROOKS_KING_FILE {
+            linear.push((mg(ROOK_KING_FILE) as f32, eg(ROOK_KING_FILE) as f32));
+        }
+
+        if all || TUNE_BISHOP_PAIR_PAWNS {
+            linear.push((mg(BISHOP_PAIR_PAWNS) as f32, eg(BISHOP_PAIR_PAWNS) as f32));
+        }
+
+        if all || TUNE_PASSED_PAWN_PATH {
+            linear.push((
+                mg(PASSED_PAWN_CONTESTED_PATH) as f32,
+                eg(PASSED_PAWN_CONTESTED_PATH) as f32,
+            ));
+            linear.push((
+                mg(PASSED_PAWN_DEFENDED_PATH) as f32,
+                eg(PASSED_PAWN_DEFENDED_PATH) as f32,
+            ));
+        }
+
+        if all || TUNE_CONNECTED_ROOKS {
+            linear.push((
+                mg(CONNECTED_ROOKS_BONUS) as f32,
+                eg(CONNECTED_ROOKS_BONUS) as f32,
+            ));
+        }
+
+        if all || TUNE_TRAPPED_ROOK {
+            linear.push((
+                mg(TRAPPED_ROOK_PENALTY) as f32,
+                eg(TRAPPED_ROOK_PENALTY) as f32,
+            ));
+        }
+
+        if all || TUNE_PAWN_MAJORITY {
+            linear.push((
+                mg(PAWN_MAJORITY_BONUS) as f32,
+                eg(PAWN_MAJORITY_BONUS) as f32,
+            ));
+        }
+
+        if all || TUNE_PAWNS_PROTECTED_PASSED {
+            for &weight in PROTECTED_PASSED_PAWN.iter() {
+                linear.push((mg(weight) as f32, eg(weight) as f32));
+            }
+        }
+
+        if all || TUNE_PASSED_PAWN_BLOCKED {
+            linear.push((
+                mg(PASSED_PAWN_BLOCKED) as f32,
+                eg(PASSED_PAWN_BLOCKED) as f32,
+            ));
+        }
+
+        if all || TUNE_PASSED_PAWN_KING_CLOSER {
+            linear.push((
+                mg(PASSED_PAWN_KING_CLOSER) as f32,
+                eg(PASSED_PAWN_KING_CLOSER) as f32,
+            ));
+        }
+
+        if all || TUNE_FIANCHETTO_HOLES {
+            linear.push((
+                mg(FIANCHETTO_HOLES_PENALTY) as f32,
+                eg(FIANCHETTO_HOLES_PENALTY) as f32,
+            ));
+        }
+
+        if all || TUNE_KING_ATTACKER_PROXIMITY {
+            linear.push((
+                mg(KING_ATTACKER_PROXIMITY) as f32,
+                eg(KING_ATTACKER_PROXIMITY) as f32,
+            ));
+        }
+
+        if all || TUNE_MINORS_VS_ROOK {
+            linear.push((mg(MINORS_VS_ROOK) as f32, eg(MINORS_VS_ROOK) as f32));
+        }
+
+        if all || TUNE_PST_PAWN {
             for i in ALL_SQUARES.squares() {
                 linear.push((mg(PAWN_PST[i]) as f32, eg(PAWN_PST[i]) as f32));
             }
         }
 
-        if TUNE_PST_KNIGHT {
+        if all || TUNE_PST_KNIGHT {
             for i in ALL_SQUARES.squares() {
                 linear.push((mg(KNIGHT_PST[i]) as f32, eg(KNIGHT_PST[i]) as f32));
             }
         }
 
-        if TUNE_PST_BISHOP {
+        if all || TUNE_PST_BISHOP {
             for i in ALL_SQUARES.squares() {
                 linear.push((mg(BISHOP_PST[i]) as f32, eg(BISHOP_PST[i]) as f32));
             }
         }
 
-        if TUNE_PST_ROOK {
+        if all || TUNE_PST_ROOK {
             for i in ALL_SQUARES.squares() {
                 linear.push((mg(ROOK_PST[i]) as f32, eg(ROOK_PST[i]) as f32));
             }
         }
 
-        if TUNE_PST_QUEEN {
+        if all || TUNE_PST_QUEEN {
             for i in ALL_SQUARES.squares() {
                 linear.push((mg(QUEEN_PST[i]) as f32, eg(QUEEN_PST[i]) as f32));
             }
         }
 
-        if TUNE_PST_KING {
+        if all || TUNE_PST_KING {
             for i in ALL_SQUARES.squares() {
                 linear.push((mg(KING_PST[i]) as f32, eg(KING_PST[i]) as f32));
             }
@@ -1064,8 +1500,7 @@ fn qsearch(position: &mut Position, alpha: Score, beta: Score) -> (Score, Positi
     let mut alpha = alpha;
 
     if !in_check {
-        let mut e = Eval::from(position as &_);
-        let eval = e.score(position, 0);
+        let eval = Eval::from(position as &_).score_uncached(position, 0, 0);
         if eval >= beta {
             return (eval, position.clone());
         }
@@ -1075,7 +1510,7 @@ fn qsearch(position: &mut Position, alpha: Score, beta: Score) -> (Score, Positi
         }
     }
 
-    let mut moves = MovePicker::qsearch(&position);
+    let mut moves = MovePicker::qsearch(&position, false);
 
     let mut best_score = -MATE_SCORE;
     let mut best_pos = position.clone();
@@ -1235,3 +1670,43 @@ fn update_parameter_array(params: &mut [(f32, f32)], gradients: &[(f32, f32)], s
         update_parameter(param, gradient, step);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameters_round_trip_through_vec() {
+        let params = Parameters::all();
+        let values = params.to_vec();
+        assert_eq!(values.len(), params.len());
+        assert_eq!(Parameters::from_vec(&values).to_vec(), values);
+
+        let mut changed = values.clone();
+        changed[0] += 1;
+        assert_eq!(Parameters::from_vec(&changed).to_vec(), changed);
+
+        // Fractional weights are rounded
+        let mut fractional = params.clone();
+        fractional.king_check_rook = 2.6;
+        assert_eq!(fractional.to_vec()[2 * params.linear.len() + 32], 3);
+    }
+
+    #[test]
+    fn parameters_vec_layout() {
+        let len = Parameters::all().len();
+        let values: Vec<i32> = (0..len as i32).collect();
+        let params = Parameters::from_vec(&values);
+
+        let linear = 2 * params.linear.len();
+        assert_eq!(params.king_safety[0], linear as f32);
+        assert_eq!(params.king_safety[29], (linear + 29) as f32);
+        assert_eq!(params.king_check_knight, (linear + 30) as f32);
+        assert_eq!(params.king_check_queen, (linear + 33) as f32);
+        assert_eq!(params.king_danger[0], (linear + 34) as f32);
+        assert_eq!(params.king_danger_attacks[0], (linear + 40) as f32);
+        assert_eq!(params.king_danger_attacks[6], (len - 1) as f32);
+        assert_eq!(params.linear.len(), 523);
+        assert_eq!(len, 1093);
+    }
+}
@@ -14,7 +14,7 @@
    You should have received a copy of the GNU General Public License
    along with this program.  If not, see <https://www.gnu.org/licenses/>.
 */
-use std::mem;
+use core::mem;
 
 use crate::bitboard::Square;
 
@@ -65,7 +65,7 @@ impl<T> From<[T; 64]> for SquareMap<T> {
     }
 }
 
-impl<T> std::ops::Index<Square> for SquareMap<T> {
+impl<T> core::ops::Index<Square> for SquareMap<T> {
     type Output = T;
 
     fn index(&self, sq: Square) -> &Self::Output {
@@ -76,7 +76,7 @@ impl<T> std::ops::Index<Square> for SquareMap<T> {
     }
 }
 
-impl<T> std::ops::IndexMut<Square> for SquareMap<T> {
+impl<T> core::ops::IndexMut<Square> for SquareMap<T> {
     fn index_mut(&mut self, sq: Square) -> &mut Self::Output {
         unsafe {
             let i = mem::transmute::<Square, u8>(sq);
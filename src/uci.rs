@@ -38,7 +38,8 @@ pub enum UciCommand {
     Uci,
     IsReady,
     SetOption(String, String),
-    Position(Position, Vec<String>),
+    /// The position, `None` for `startpos`, and the moves played from it.
+    Position(Option<Position>, Vec<String>),
     Go(GoParams),
     Quit,
     Stop,
@@ -54,6 +55,12 @@ pub struct GoParams {
     pub time_control: TimeControl,
 }
 
+impl Default for UCI {
+    fn default() -> Self {
+        UCI::new()
+    }
+}
+
 impl UCI {
     pub fn new() -> UCI {
         let (main_tx, main_rx) = sync::mpsc::channel();
@@ -148,9 +155,12 @@ impl<'a> From<&'a str> for UciCommand {
             let pos;
             let fen = line.trim_start_matches("position ");
             if fen.starts_with("startpos") {
-                pos = STARTING_POSITION;
+                pos = None;
             } else {
-                pos = Position::from(fen.trim_start_matches("fen"));
+                match Position::try_from_fen(fen.trim_start_matches("fen")) {
+                    Ok(p) => pos = Some(p),
+                    Err(err) => return UciCommand::Unknown(format!("{} ({:?})", line, err)),
+                }
             }
 
             let mut moves = Vec::new();